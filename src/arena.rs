@@ -0,0 +1,1270 @@
+//! The syntax tree allocated in a [`Bump`] arena instead of individual `Box`es and `Vec`s.
+//!
+//! Every operator, call and list in [`ast`] is its own small heap allocation. The
+//! parsers here accept the same syntax and build the same shapes, with children as
+//! references and slices into an arena, so nodes are allocated by bumping a pointer
+//! and freed all at once when the arena is dropped.
+//!
+//! The arena never runs `Drop`, so nodes own no heap memory of their own: text that
+//! isn't borrowed from the source, like a string with escapes, is copied into the
+//! arena, and tokens don't keep their trivia. Tools that need trivia parse with [`ast`].
+
+use crate::ast::{self, peek_binary_op, BinaryKind, BinaryOp, UnaryOp};
+use crate::{Error, ParseError, ParseResult, ParseResultExt, Span, StringPart, Token, TokenKind, Tokenizer};
+use bumpalo::Bump;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// An [`ast::Decl`] in an arena.
+pub enum Decl<'a> {
+    Func(Token<'a>, &'a Func<'a>),
+    Global { name: Token<'a>, ty: Option<Type<'a>>, init: Option<Expr<'a>> },
+    TypeAlias { name: Token<'a>, ty: Type<'a>, span: Span },
+    Import(Token<'a>, Span),
+    Mod(Token<'a>, Span),
+}
+
+pub struct Func<'a> {
+    pub generics: &'a [Token<'a>],
+    pub ty: FnType<'a>,
+    pub body: Block<'a>,
+}
+
+pub enum Type<'a> {
+    Name(Token<'a>),
+    Ptr(&'a Type<'a>, Span),
+    Array(&'a Type<'a>, Option<Expr<'a>>, Span),
+    Func(&'a FnType<'a>),
+    Generic(Token<'a>, &'a [Type<'a>], Span),
+}
+
+pub struct FnType<'a> {
+    pub args: &'a [Type<'a>],
+    pub ret: Option<Type<'a>>,
+    pub span: Span,
+}
+
+pub enum Expr<'a> {
+    Num(ast::NumLit<'a>),
+    Str(Token<'a>),
+    Bool(Token<'a>),
+    Interp(&'a [StrPart<'a>], Span),
+    Name(Token<'a>),
+    SelfRef(Token<'a>),
+    Call(&'a Expr<'a>, &'a [Expr<'a>], Span),
+    Index(&'a Expr<'a>, &'a Expr<'a>, Span),
+    ArrayLit(&'a [Expr<'a>], Span),
+    If(&'a If<'a>),
+    Lambda(&'a FnType<'a>, &'a Block<'a>),
+    Add(&'a Expr<'a>, &'a Expr<'a>),
+    Sub(&'a Expr<'a>, &'a Expr<'a>),
+    Mul(&'a Expr<'a>, &'a Expr<'a>),
+    Div(&'a Expr<'a>, &'a Expr<'a>),
+    Rem(&'a Expr<'a>, &'a Expr<'a>),
+    Unary(UnaryOp, &'a Expr<'a>, Span),
+    Lt(&'a Expr<'a>, &'a Expr<'a>),
+    Eq(&'a Expr<'a>, &'a Expr<'a>),
+    Ne(&'a Expr<'a>, &'a Expr<'a>),
+    Le(&'a Expr<'a>, &'a Expr<'a>),
+    Gt(&'a Expr<'a>, &'a Expr<'a>),
+    Ge(&'a Expr<'a>, &'a Expr<'a>),
+    BitAnd(&'a Expr<'a>, &'a Expr<'a>),
+    BitOr(&'a Expr<'a>, &'a Expr<'a>),
+    BitXor(&'a Expr<'a>, &'a Expr<'a>),
+    Shl(&'a Expr<'a>, &'a Expr<'a>),
+    Shr(&'a Expr<'a>, &'a Expr<'a>),
+    And(&'a Expr<'a>, &'a Expr<'a>),
+    Or(&'a Expr<'a>, &'a Expr<'a>),
+    Binary(Token<'a>, &'a Expr<'a>, &'a Expr<'a>),
+}
+
+pub enum StrPart<'a> {
+    Lit(Token<'a>),
+    Expr(Expr<'a>),
+}
+
+pub enum Stmt<'a> {
+    If(If<'a>),
+    Return(Expr<'a>, Span),
+    Block(Block<'a>),
+    Assign { target: Expr<'a>, value: Expr<'a> },
+    Expr(Expr<'a>),
+    Macro(Macro<'a>),
+    Let { name: Token<'a>, ty: Option<Type<'a>>, init: Option<Expr<'a>>, span: Span },
+    While(While<'a>),
+    Break(Option<Token<'a>>, Span),
+    Continue(Option<Token<'a>>, Span),
+    Func(Token<'a>, Func<'a>),
+}
+
+pub struct While<'a> {
+    pub label: Option<Token<'a>>,
+    pub condition: Expr<'a>,
+    pub body: Block<'a>,
+    pub span: Span,
+}
+
+pub struct Macro<'a> {
+    pub name: Token<'a>,
+    pub args: &'a [Token<'a>],
+    pub span: Span,
+}
+
+pub struct If<'a> {
+    pub condition: Expr<'a>,
+    pub then: Block<'a>,
+    pub otherwise: Option<Else<'a>>,
+    pub span: Span,
+}
+
+pub enum Else<'a> {
+    Block(&'a Block<'a>),
+    If(&'a If<'a>),
+}
+
+pub struct Block<'a> {
+    pub left: Token<'a>,
+    pub items: &'a [Stmt<'a>],
+    pub right: Token<'a>,
+}
+
+impl Decl<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Func(name, func) => name.span.merge(func.span()),
+            Self::Global { name, ty, init } => {
+                let end = init.as_ref().map(Expr::span).or_else(|| ty.as_ref().map(Type::span));
+                end.map_or(name.span, |end| name.span.merge(end))
+            }
+            Self::TypeAlias { span, .. } | Self::Import(_, span) | Self::Mod(_, span) => *span,
+        }
+    }
+}
+
+impl Func<'_> {
+    #[inline]
+    pub fn span(&self) -> Span {
+        self.ty.span.merge(self.body.span())
+    }
+}
+
+impl Type<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Name(name) => name.span,
+            Self::Func(func) => func.span,
+            Self::Ptr(_, span) | Self::Array(_, _, span) | Self::Generic(_, _, span) => *span,
+        }
+    }
+}
+
+impl Expr<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Num(lit) => lit.token.span,
+            Self::Str(token) | Self::Bool(token) | Self::Name(token) | Self::SelfRef(token) => token.span,
+            Self::Interp(_, span) | Self::Call(_, _, span) | Self::ArrayLit(_, span) | Self::Unary(_, _, span) => *span,
+            Self::Index(_, _, span) => *span,
+            Self::If(stmt) => stmt.span,
+            Self::Lambda(ty, body) => ty.span.merge(body.span()),
+            Self::Add(lhs, rhs)
+            | Self::Sub(lhs, rhs)
+            | Self::Mul(lhs, rhs)
+            | Self::Div(lhs, rhs)
+            | Self::Rem(lhs, rhs)
+            | Self::Lt(lhs, rhs)
+            | Self::Eq(lhs, rhs)
+            | Self::Ne(lhs, rhs)
+            | Self::Le(lhs, rhs)
+            | Self::Gt(lhs, rhs)
+            | Self::Ge(lhs, rhs)
+            | Self::BitAnd(lhs, rhs)
+            | Self::BitOr(lhs, rhs)
+            | Self::BitXor(lhs, rhs)
+            | Self::Shl(lhs, rhs)
+            | Self::Shr(lhs, rhs)
+            | Self::And(lhs, rhs)
+            | Self::Or(lhs, rhs)
+            | Self::Binary(_, lhs, rhs) => lhs.span().merge(rhs.span()),
+        }
+    }
+}
+
+impl StrPart<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Lit(token) => token.span,
+            Self::Expr(expr) => expr.span(),
+        }
+    }
+}
+
+impl Stmt<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::If(stmt) => stmt.span,
+            Self::Return(_, span) | Self::Let { span, .. } | Self::Break(_, span) | Self::Continue(_, span) => *span,
+            Self::Block(block) => block.span(),
+            Self::Assign { target, value } => target.span().merge(value.span()),
+            Self::Expr(expr) => expr.span(),
+            Self::Macro(mac) => mac.span,
+            Self::While(stmt) => stmt.span,
+            Self::Func(name, func) => name.span.merge(func.span()),
+        }
+    }
+}
+
+impl Else<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Block(block) => block.span(),
+            Self::If(stmt) => stmt.span,
+        }
+    }
+}
+
+impl Block<'_> {
+    #[inline]
+    pub fn span(&self) -> Span {
+        self.left.span.merge(self.right.span)
+    }
+}
+
+/// Prepares `token` to live in the tree: owned text is copied into `arena` and the
+/// trivia is dropped, so the token has nothing left to free.
+fn token<'a>(arena: &'a Bump, token: Token<'a>) -> Token<'a> {
+    Token {
+        span: token.span,
+        kind: token.kind,
+        content: Cow::Borrowed(text(arena, token.content)),
+        leading: Vec::new(),
+        trailing: Vec::new(),
+    }
+}
+
+fn tokens<'a>(arena: &'a Bump, tokens: Vec<Token<'a>>) -> &'a [Token<'a>] {
+    arena.alloc_slice_fill_iter(tokens.into_iter().map(|t| token(arena, t)))
+}
+
+fn text<'a>(arena: &'a Bump, text: Cow<'a, str>) -> &'a str {
+    match text {
+        Cow::Borrowed(text) => text,
+        Cow::Owned(text) => arena.alloc_str(&text),
+    }
+}
+
+/// The arena counterpart of [`ast::parse_program`]; the declarations are allocated in
+/// `arena` too.
+pub fn parse_program<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> Result<&'a [Decl<'a>], Vec<Error>> {
+    let (decls, errors) = parse_program_recovering(tok, arena);
+    if errors.is_empty() {
+        Ok(decls)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The arena counterpart of [`ast::parse_program_recovering`].
+pub fn parse_program_recovering<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> (&'a [Decl<'a>], Vec<Error>) {
+    let mut decls = Vec::new();
+    while tok.has_more_tokens() {
+        let start = tok.location();
+        match tok.try_with(|tok| tok.labelled("declaration", |tok| parse_decl(tok, arena))) {
+            Ok(decl) => decls.push(decl),
+            Err(error) => {
+                let error = error.into_error().unwrap_or_else(|| tok.expectation_error());
+                ast::synchronize(tok, start, false);
+                tok.report(error);
+            }
+        }
+    }
+    if let Some(error) = tok.error() {
+        tok.report(error);
+    }
+    (arena.alloc_slice_fill_iter(decls), tok.take_diagnostics())
+}
+
+pub fn parse_decl<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Decl<'a>> {
+    if let Some(keyword) = tok.consume_keyword("import") {
+        let path = tok.labelled("path string", ast::parse_expr_str)
+            .required(tok)?;
+        let span = keyword.span.merge(path.span);
+        return Ok(Decl::Import(token(arena, path), span));
+    }
+    if let Some(keyword) = tok.consume_keyword("mod") {
+        let name = tok.consume_ident()
+            .ok_or_else(|| tok.expected("module name"))
+            .required(tok)?;
+        let span = keyword.span.merge(name.span);
+        return Ok(Decl::Mod(token(arena, name), span));
+    }
+    if let Some(alias) = tok.try_with(|tok| parse_type_alias(tok, arena)).optional()? {
+        return Ok(alias);
+    }
+    let name = tok.consume_ident().ok_or(ParseError::NoMatch)?;
+    let generics = ast::parse_generic_params(tok)?;
+    if !generics.is_empty() || tok.peek_str("(").is_some() {
+        let ty = parse_fn_type(tok, arena)
+            .required(tok)?;
+        let body = parse_block(tok, arena)
+            .required(tok)?;
+        Ok(Decl::Func(token(arena, name), arena.alloc(Func { generics: tokens(arena, generics), ty, body })))
+    } else {
+        let ty = tok.try_with(|tok| parse_type(tok, arena)).optional()?;
+        let init = tok.consume("=")
+            .map(|_| parse_expr(tok, arena).required(tok))
+            .transpose()?;
+        if ty.is_none() && init.is_none() {
+            return Err(tok.expected("`=`"));
+        }
+        Ok(Decl::Global { name: token(arena, name), ty, init })
+    }
+}
+
+fn parse_type_alias<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Decl<'a>> {
+    let Some(keyword) = tok.consume_keyword("type") else {
+        return Err(ParseError::NoMatch);
+    };
+    let name = tok.consume_ident()
+        .ok_or_else(|| tok.expected("alias name"))
+        .required(tok)?;
+    tok.expect_with("=", "after type alias name")?;
+    let ty = parse_type(tok, arena)
+        .required(tok)?;
+    let span = keyword.span.merge(ty.span());
+    Ok(Decl::TypeAlias { name: token(arena, name), ty, span })
+}
+
+pub fn parse_type<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Type<'a>> {
+    if let Some(func) = tok.labelled("type", |tok| tok.try_with(|tok| parse_fn_type(tok, arena))).optional()? {
+        Ok(Type::Func(arena.alloc(func)))
+    } else if let Some(word) = tok.consume_ident() {
+        let word = token(arena, word);
+        let mut out = match tok.peek_str("<") {
+            Some(_) => {
+                let (_, args, right) = tok.separated_list("<", ",", ">", "to close type arguments", |tok| tok.nested(|tok| parse_type(tok, arena)))?;
+                let span = word.span.merge(right.span);
+                Type::Generic(word, arena.alloc_slice_fill_iter(args), span)
+            }
+            None => Type::Name(word),
+        };
+        loop {
+            if let Some(star) = tok.consume("*") {
+                let span = out.span().merge(star.span);
+                out = Type::Ptr(arena.alloc(out), span);
+            } else if tok.consume("[").is_some() {
+                let len = tok.try_with(|tok| parse_expr(tok, arena)).optional()?;
+                let right = tok.expect_with("]", "to close array type")?;
+                let span = out.span().merge(right.span);
+                out = Type::Array(arena.alloc(out), len, span);
+            } else {
+                break;
+            }
+        }
+        Ok(out)
+    } else {
+        Err(tok.expected("type"))
+    }
+}
+
+fn parse_fn_type<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<FnType<'a>> {
+    let (left, args, right) = tok.separated_list("(", ",", ")", "to close parameter list", |tok| tok.nested(|tok| parse_type(tok, arena)))?;
+
+    let ret = tok.try_with(|tok| tok.nested(|tok| parse_type(tok, arena))).optional()?;
+    let span = left.span.merge(ret.as_ref().map_or(right.span, Type::span));
+
+    Ok(FnType { args: arena.alloc_slice_fill_iter(args), ret, span })
+}
+
+pub fn parse_stmt<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Stmt<'a>> {
+    if let Some(block) = tok.try_with(|tok| parse_block(tok, arena)).optional()? {
+        Ok(Stmt::Block(block))
+    } else if let Some(stmt) = tok.try_with(|tok| parse_if(tok, arena)).optional()? {
+        Ok(Stmt::If(stmt))
+    } else if let Some(stmt) = tok.try_with(|tok| parse_while(tok, arena)).optional()? {
+        Ok(Stmt::While(stmt))
+    } else if let Some(stmt) = tok.try_with(|tok| parse_jump(tok, arena)).optional()? {
+        Ok(stmt)
+    } else if let Some(stmt) = tok.try_with(|tok| parse_return(tok, arena)).optional()? {
+        Ok(stmt)
+    } else if let Some(stmt) = tok.try_with(|tok| parse_let(tok, arena)).optional()? {
+        Ok(stmt)
+    } else if let Some(mac) = tok.try_with(|tok| parse_macro(tok, arena)).optional()? {
+        Ok(Stmt::Macro(mac))
+    } else if let Some(stmt) = tok.try_with(|tok| parse_func_stmt(tok, arena)).optional()? {
+        Ok(stmt)
+    } else if let Some(stmt) = tok.try_with(|tok| parse_expr_stmt(tok, arena)).optional()? {
+        Ok(stmt)
+    } else {
+        Err(ParseError::NoMatch)
+    }
+}
+
+fn parse_func_stmt<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Stmt<'a>> {
+    let name = tok.consume_ident().ok_or(ParseError::NoMatch)?;
+    if tok.peek_str("<").is_none() && tok.peek_str("(").is_none() {
+        return Err(ParseError::NoMatch);
+    }
+    let (generics, ty) = tok.try_with(|tok| Ok((ast::parse_generic_params(tok)?, parse_fn_type(tok, arena)?)))
+        .map_err(|_| ParseError::NoMatch)?;
+    if tok.peek_str("{").is_none() {
+        return Err(ParseError::NoMatch);
+    }
+    let body = parse_block(tok, arena)
+        .required(tok)?;
+    Ok(Stmt::Func(token(arena, name), Func { generics: tokens(arena, generics), ty, body }))
+}
+
+fn parse_let<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Stmt<'a>> {
+    let Some(keyword) = tok.consume_keyword("let").or_else(|| tok.consume_keyword("var")) else {
+        return Err(tok.expected("`let`"));
+    };
+    let name = tok.consume_ident()
+        .ok_or_else(|| tok.expected("variable name"))
+        .required(tok)?;
+    let ty = tok.try_with(|tok| parse_type(tok, arena)).optional()?;
+    let init = tok.consume("=")
+        .map(|_| parse_expr(tok, arena).required(tok))
+        .transpose()?;
+    if ty.is_none() && init.is_none() {
+        tok.expected("`=`");
+        return Err(tok.expectation_error().into());
+    }
+    let end = init.as_ref().map(Expr::span).or_else(|| ty.as_ref().map(Type::span));
+    let span = keyword.span.merge(end.unwrap_or(name.span));
+    Ok(Stmt::Let { name: token(arena, name), ty, init, span })
+}
+
+fn parse_macro<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Macro<'a>> {
+    let ast::Macro { name, args, span } = ast::parse_macro(tok)?;
+    Ok(Macro { name: token(arena, name), args: tokens(arena, args), span })
+}
+
+fn parse_expr_stmt<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Stmt<'a>> {
+    let start = tok.location();
+    let target = parse_place(tok, arena)?;
+    if tok.peek_str("==").is_some() || tok.consume("=").is_none() {
+        if let Expr::Call(..) = target {
+            return Ok(Stmt::Expr(target));
+        }
+        return Err(tok.expected("`=`"));
+    }
+    if !matches!(target, Expr::Name(_) | Expr::Unary(UnaryOp::Deref, ..) | Expr::Index(..)) {
+        return Err(Error::at(start, "cannot assign to this expression").into());
+    }
+
+    let value = parse_expr(tok, arena)
+        .required(tok)?;
+    Ok(Stmt::Assign { target, value })
+}
+
+fn parse_place<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Expr<'a>> {
+    if let Some(star) = tok.consume("*") {
+        let operand = tok.nested(|tok| parse_place(tok, arena))?;
+        let span = star.span.merge(operand.span());
+        Ok(Expr::Unary(UnaryOp::Deref, arena.alloc(operand), span))
+    } else {
+        parse_expr_unary(tok, arena)
+    }
+}
+
+fn parse_return<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Stmt<'a>> {
+    let Some(colon) = tok.consume(":") else {
+        return Err(tok.expected("`:`"));
+    };
+    let expr = parse_expr(tok, arena)
+        .required(tok)?;
+    let span = colon.span.merge(expr.span());
+    Ok(Stmt::Return(expr, span))
+}
+
+fn parse_if<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<If<'a>> {
+    let Some(keyword) = tok.consume_keyword("if") else {
+        return Err(tok.expected("`if`"));
+    };
+
+    // Arm by arm like `ast::parse_if`, so long `else if` chains don't recurse.
+    let mut arms = vec![parse_if_arm(tok, arena, keyword.span)?];
+    let mut otherwise = None;
+    while tok.consume_keyword("else").is_some() {
+        if let Some(keyword) = tok.consume_keyword("if") {
+            arms.push(parse_if_arm(tok, arena, keyword.span)?);
+            continue;
+        }
+        let block = parse_block(tok, arena)
+            .or_else_parse(|| Err(tok.expected("`if`")))
+            .required(tok)?;
+        otherwise = Some(Else::Block(arena.alloc(block)));
+        break;
+    }
+
+    loop {
+        let (keyword, condition, then) = arms.pop().expect("there is at least one arm");
+        let span = keyword.merge(otherwise.as_ref().map_or(then.span(), Else::span));
+        let stmt = If { condition, then, otherwise, span };
+        if arms.is_empty() {
+            return Ok(stmt);
+        }
+        otherwise = Some(Else::If(arena.alloc(stmt)));
+    }
+}
+
+/// See [`ast::parse_condition`].
+fn parse_condition<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Expr<'a>> {
+    if tok.peek_str("(").is_none() {
+        return parse_expr(tok, arena);
+    }
+    let condition = tok.try_with(|tok| match parse_expr(tok, arena) {
+        Ok(condition) if tok.peek_str("{").is_some() => Ok(condition),
+        _ => Err(ParseError::NoMatch),
+    }).optional()?;
+    if let Some(condition) = condition {
+        return Ok(condition);
+    }
+    tok.nested(|tok| {
+        let (_, inner, _) = tok.delimited("(", |tok| parse_expr(tok, arena), ")", "to close parenthesized expression")?;
+        let operand = parse_postfix_ops(tok, arena, inner)?;
+        parse_binary_ops(tok, arena, operand, 0)
+    })
+}
+
+/// The condition and block after the `if` keyword at `keyword`.
+fn parse_if_arm<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump, keyword: Span) -> ParseResult<(Span, Expr<'a>, Block<'a>)> {
+    let condition = parse_condition(tok, arena)
+        .required(tok)?;
+    let then = parse_block(tok, arena)
+        .required(tok)?;
+    Ok((keyword, condition, then))
+}
+
+fn parse_while<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<While<'a>> {
+    let label = ast::parse_label(tok).optional()?;
+    let keyword = match &label {
+        Some(_) => {
+            tok.expect_with(":", "after loop label")?;
+            tok.consume_keyword("while")
+                .ok_or_else(|| tok.expected("`while`"))
+                .required(tok)?
+        }
+        None => tok.consume_keyword("while").ok_or_else(|| tok.expected("`while`"))?,
+    };
+    let condition = parse_condition(tok, arena)
+        .required(tok)?;
+    let body = parse_block(tok, arena)
+        .required(tok)?;
+    let span = label.as_ref().unwrap_or(&keyword).span.merge(body.span());
+    Ok(While { label: label.map(|label| token(arena, label)), condition, body, span })
+}
+
+fn parse_jump<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Stmt<'a>> {
+    let Some(keyword) = tok.consume_keyword("break").or_else(|| tok.consume_keyword("continue")) else {
+        tok.expected("`break`");
+        return Err(tok.expected("`continue`"));
+    };
+    let label = match tok.peek_str("'") {
+        Some(quote) if quote.start.line == keyword.span.end.line => Some(ast::parse_label(tok).required(tok)?),
+        _ => None,
+    };
+    let span = keyword.span.merge(label.as_ref().map_or(keyword.span, |label| label.span));
+    let label = label.map(|label| token(arena, label));
+    match keyword.content() {
+        "break" => Ok(Stmt::Break(label, span)),
+        _ => Ok(Stmt::Continue(label, span)),
+    }
+}
+
+pub fn parse_block<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Block<'a>> {
+    let (left, items, right) = tok.delimited("{", |tok| tok.nested(|tok| {
+        let mut items = Vec::new();
+        while tok.has_more_tokens() && tok.peek_str("}").is_none() {
+            let start = tok.location();
+            match parse_stmt(tok, arena) {
+                Ok(item) => items.push(item),
+                Err(error) => {
+                    let error = error.into_error().unwrap_or_else(|| tok.expectation_error());
+                    ast::synchronize(tok, start, true);
+                    tok.report(error);
+                }
+            }
+        }
+        Ok(items)
+    }), "}", "to close block")?;
+    Ok(Block { left: token(arena, left), items: arena.alloc_slice_fill_iter(items), right: token(arena, right) })
+}
+
+/// The arena counterpart of [`ast::parse_expr`], accepting the same syntax.
+pub fn parse_expr<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Expr<'a>> {
+    tok.nested(|tok| parse_expr_bp(tok, arena, 0))
+}
+
+fn parse_expr_bp<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump, min_bp: u8) -> ParseResult<Expr<'a>> {
+    let lhs = parse_expr_unary(tok, arena)?;
+    parse_binary_ops(tok, arena, lhs, min_bp)
+}
+
+fn parse_binary_ops<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump, mut lhs: Expr<'a>, min_bp: u8) -> ParseResult<Expr<'a>> {
+    while let Some(op) = peek_binary_op(tok, min_bp) {
+        let op_token = tok.consume(op.symbol).expect("the operator was just peeked");
+
+        let rhs = tok.nested(|tok| parse_expr_bp(tok, arena, op.rhs_bp()))
+            .required(tok)?;
+        lhs = build_binary(arena, &op, op_token, arena.alloc(lhs), arena.alloc(rhs));
+    }
+    Ok(lhs)
+}
+
+/// The arena counterpart of the node `op` makes for `lhs op rhs`.
+fn build_binary<'a>(arena: &'a Bump, op: &BinaryOp, op_token: Token<'a>, lhs: &'a Expr<'a>, rhs: &'a Expr<'a>) -> Expr<'a> {
+    match op.kind {
+        BinaryKind::Or => Expr::Or(lhs, rhs),
+        BinaryKind::And => Expr::And(lhs, rhs),
+        BinaryKind::BitOr => Expr::BitOr(lhs, rhs),
+        BinaryKind::BitXor => Expr::BitXor(lhs, rhs),
+        BinaryKind::BitAnd => Expr::BitAnd(lhs, rhs),
+        BinaryKind::Lt => Expr::Lt(lhs, rhs),
+        BinaryKind::Eq => Expr::Eq(lhs, rhs),
+        BinaryKind::Ne => Expr::Ne(lhs, rhs),
+        BinaryKind::Le => Expr::Le(lhs, rhs),
+        BinaryKind::Gt => Expr::Gt(lhs, rhs),
+        BinaryKind::Ge => Expr::Ge(lhs, rhs),
+        BinaryKind::Shl => Expr::Shl(lhs, rhs),
+        BinaryKind::Shr => Expr::Shr(lhs, rhs),
+        BinaryKind::Add => Expr::Add(lhs, rhs),
+        BinaryKind::Sub => Expr::Sub(lhs, rhs),
+        BinaryKind::Mul => Expr::Mul(lhs, rhs),
+        BinaryKind::Div => Expr::Div(lhs, rhs),
+        BinaryKind::Rem => Expr::Rem(lhs, rhs),
+        BinaryKind::Custom => Expr::Binary(token(arena, op_token), lhs, rhs),
+    }
+}
+
+fn parse_expr_unary<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Expr<'a>> {
+    let Some(op) = tok.consume_any(&["-", "!", "*", "&"]) else {
+        return parse_expr_postfix(tok, arena);
+    };
+    let kind = match op.content() {
+        "-" => UnaryOp::Neg,
+        "!" => UnaryOp::Not,
+        "*" => UnaryOp::Deref,
+        _ => UnaryOp::Addr,
+    };
+    let operand = tok.nested(|tok| parse_expr_unary(tok, arena))
+        .required(tok)?;
+    let span = op.span.merge(operand.span());
+    Ok(Expr::Unary(kind, arena.alloc(operand), span))
+}
+
+fn parse_expr_postfix<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Expr<'a>> {
+    let out = tok.labelled("expression", |tok| parse_expr_primary(tok, arena))?;
+    parse_postfix_ops(tok, arena, out)
+}
+
+fn parse_postfix_ops<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump, mut out: Expr<'a>) -> ParseResult<Expr<'a>> {
+    loop {
+        if tok.peek_str("(").is_some() {
+            let (_, args, right) = tok.separated_list("(", ",", ")", "to close argument list", |tok| parse_expr(tok, arena))?;
+            let span = out.span().merge(right.span);
+            out = Expr::Call(arena.alloc(out), arena.alloc_slice_fill_iter(args), span);
+        } else if tok.peek_str("[").is_some() {
+            let (_, index, right) = tok.delimited("[", |tok| parse_expr(tok, arena), "]", "to close index")?;
+            let span = out.span().merge(right.span);
+            out = Expr::Index(arena.alloc(out), arena.alloc(index), span);
+        } else {
+            return Ok(out);
+        }
+    }
+}
+
+fn parse_expr_primary<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Expr<'a>> {
+    if let Some(num) = tok.consume_number()? {
+        let lit = ast::NumLit::new(num)?;
+        Ok(Expr::Num(ast::NumLit {
+            token: token(arena, lit.token),
+            value: lit.value,
+            radix: lit.radix,
+            suffix: lit.suffix.map(|suffix| Cow::Borrowed(text(arena, suffix))),
+        }))
+    } else if let Some(keyword) = tok.consume_keyword("self") {
+        Ok(Expr::SelfRef(token(arena, keyword)))
+    } else if let Some(stmt) = tok.try_with(|tok| parse_if(tok, arena)).optional()? {
+        Ok(Expr::If(arena.alloc(stmt)))
+    } else if let Some(lambda) = tok.try_with(|tok| parse_lambda(tok, arena)).optional()? {
+        Ok(lambda)
+    } else if tok.peek_str("(").is_some() {
+        let (_, inner, _) = tok.delimited("(", |tok| parse_expr(tok, arena), ")", "to close parenthesized expression")?;
+        Ok(inner)
+    } else if tok.peek_str("[").is_some() {
+        let (left, items, right) = tok.separated_list("[", ",", "]", "to close array literal", |tok| parse_expr(tok, arena))?;
+        Ok(Expr::ArrayLit(arena.alloc_slice_fill_iter(items), left.span.merge(right.span)))
+    } else if let Some(word) = tok.consume_keyword("true").or_else(|| tok.consume_keyword("false")) {
+        Ok(Expr::Bool(token(arena, word)))
+    } else if let Some(name) = tok.consume_ident() {
+        Ok(Expr::Name(token(arena, name)))
+    } else {
+        parse_expr_strings(tok, arena)
+    }
+}
+
+/// See [`ast::parse_lambda`].
+fn parse_lambda<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Expr<'a>> {
+    if tok.peek_str("(").is_none() {
+        return Err(ParseError::NoMatch);
+    }
+    let ty = tok.try_with(|tok| match parse_fn_type(tok, arena) {
+        Ok(ty) if tok.peek_str("{").is_some() => Ok(ty),
+        _ => Err(ParseError::NoMatch),
+    })?;
+    let body = parse_block(tok, arena)
+        .required(tok)?;
+    Ok(Expr::Lambda(arena.alloc(ty), arena.alloc(body)))
+}
+
+/// See [`ast::parse_expr_strings`]. Adjacent literals are joined before they go into
+/// the arena, so only the joined text is copied there.
+fn parse_expr_strings<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<Expr<'a>> {
+    let mut parts = Vec::new();
+    let mut span: Option<Span> = None;
+    let mut interpolated = false;
+    loop {
+        let (more, more_span) = if let Some((more, more_span)) = tok.try_with(|tok| parse_expr_interp(tok, arena)).optional()? {
+            interpolated = true;
+            (more, more_span)
+        } else if let Some(string) = tok.try_with(ast::parse_expr_str).optional()? {
+            let string_span = string.span;
+            (vec![StrPart::Lit(string)], string_span)
+        } else {
+            break;
+        };
+        span = Some(span.map_or(more_span, |span| span.merge(more_span)));
+        for part in more {
+            match (parts.last_mut(), part) {
+                (Some(StrPart::Lit(lit)), StrPart::Lit(next)) => ast::join_literals(lit, next),
+                (_, part) => parts.push(part),
+            }
+        }
+    }
+    let Some(span) = span else {
+        return Err(ParseError::NoMatch);
+    };
+    if interpolated {
+        let parts = parts.into_iter().map(|part| match part {
+            StrPart::Lit(lit) => StrPart::Lit(token(arena, lit)),
+            part => part,
+        });
+        return Ok(Expr::Interp(arena.alloc_slice_fill_iter(parts), span));
+    }
+    let Some(StrPart::Lit(string)) = parts.pop() else {
+        unreachable!("plain strings join into a single literal");
+    };
+    Ok(Expr::Str(token(arena, string)))
+}
+
+/// See [`ast::parse_expr_interp`]. The literal parts aren't in the arena yet.
+fn parse_expr_interp<'a, 'src: 'a>(tok: &Tokenizer<'src>, arena: &'a Bump) -> ParseResult<(Vec<StrPart<'a>>, Span)> {
+    let Some(string) = tok.consume_interpolated_string()? else {
+        return Err(ParseError::NoMatch);
+    };
+
+    let parts = string.parts.into_iter()
+        .map(|part| match part {
+            StringPart::Literal(lit) => {
+                let content = ast::unescape(tok, lit.span)?;
+                Ok(StrPart::Lit(Token { content, ..lit }))
+            }
+            StringPart::Expr(span) => {
+                let inner = tok.sub_tokenizer(span);
+                let expr = parse_expr(&inner, arena)
+                    .required(&inner)?;
+                if inner.has_more_tokens() {
+                    return Err(Error::at(inner.location(), "expected `}` to close `${`").into());
+                }
+                Ok(StrPart::Expr(expr))
+            }
+        })
+        .collect::<ParseResult<_>>()?;
+    Ok((parts, string.span))
+}
+
+impl Decl<'_> {
+    /// Renders the declaration the way [`ast::Decl::to_sexpr`] does.
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        decl(&mut out, self);
+        out
+    }
+}
+
+impl Stmt<'_> {
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        stmt(&mut out, self);
+        out
+    }
+}
+
+impl Expr<'_> {
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        expr(&mut out, self);
+        out
+    }
+}
+
+fn decl(out: &mut String, decl: &Decl) {
+    match decl {
+        Decl::Func(name, f) => func(out, name, f),
+        Decl::Global { name, ty: t, init } => {
+            write!(out, "(global {name} ").unwrap();
+            optional(out, t.as_ref(), ty);
+            out.push(' ');
+            optional(out, init.as_ref(), expr);
+            out.push(')');
+        }
+        Decl::TypeAlias { name, ty: t, .. } => {
+            write!(out, "(type {name} ").unwrap();
+            ty(out, t);
+            out.push(')');
+        }
+        Decl::Import(path, _) => write!(out, "(import {:?})", path.content()).unwrap(),
+        Decl::Mod(name, _) => write!(out, "(mod {name})").unwrap(),
+    }
+}
+
+fn func(out: &mut String, name: &Token, f: &Func) {
+    write!(out, "(fn {name} ").unwrap();
+    if !f.generics.is_empty() {
+        out.push_str("(generics");
+        for param in f.generics {
+            write!(out, " {param}").unwrap();
+        }
+        out.push_str(") ");
+    }
+    fn_type(out, &f.ty);
+    out.push(' ');
+    block(out, &f.body);
+    out.push(')');
+}
+
+fn ty(out: &mut String, t: &Type) {
+    match t {
+        Type::Name(name) => out.push_str(name.content()),
+        Type::Ptr(inner, _) => {
+            out.push_str("(ptr ");
+            ty(out, inner);
+            out.push(')');
+        }
+        Type::Array(elem, len, _) => {
+            out.push_str("(array ");
+            ty(out, elem);
+            if let Some(len) = len {
+                out.push(' ');
+                expr(out, len);
+            }
+            out.push(')');
+        }
+        Type::Func(func) => fn_type(out, func),
+        Type::Generic(name, args, _) => {
+            write!(out, "(generic {name}").unwrap();
+            for arg in *args {
+                out.push(' ');
+                ty(out, arg);
+            }
+            out.push(')');
+        }
+    }
+}
+
+fn fn_type(out: &mut String, func: &FnType) {
+    out.push_str("(fn-type (");
+    for (i, arg) in func.args.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        ty(out, arg);
+    }
+    out.push(')');
+    if let Some(ret) = &func.ret {
+        out.push(' ');
+        ty(out, ret);
+    }
+    out.push(')');
+}
+
+fn stmt(out: &mut String, s: &Stmt) {
+    match s {
+        Stmt::If(s) => if_(out, s),
+        Stmt::Return(e, _) => {
+            out.push_str("(ret ");
+            expr(out, e);
+            out.push(')');
+        }
+        Stmt::Block(b) => block(out, b),
+        Stmt::Assign { target, value } => {
+            out.push_str("(assign ");
+            expr(out, target);
+            out.push(' ');
+            expr(out, value);
+            out.push(')');
+        }
+        Stmt::Expr(e) => expr(out, e),
+        Stmt::Macro(mac) => {
+            write!(out, "(macro {}", mac.name).unwrap();
+            for arg in mac.args {
+                write!(out, " {arg}").unwrap();
+            }
+            out.push(')');
+        }
+        Stmt::Let { name, ty: t, init, .. } => {
+            write!(out, "(let {name} ").unwrap();
+            optional(out, t.as_ref(), ty);
+            out.push(' ');
+            optional(out, init.as_ref(), expr);
+            out.push(')');
+        }
+        Stmt::While(s) => {
+            out.push_str("(while ");
+            if let Some(label) = &s.label {
+                write!(out, "{label} ").unwrap();
+            }
+            expr(out, &s.condition);
+            out.push(' ');
+            block(out, &s.body);
+            out.push(')');
+        }
+        Stmt::Break(label, _) => jump(out, "break", label.as_ref()),
+        Stmt::Continue(label, _) => jump(out, "continue", label.as_ref()),
+        Stmt::Func(name, f) => func(out, name, f),
+    }
+}
+
+fn jump(out: &mut String, keyword: &str, label: Option<&Token>) {
+    match label {
+        Some(label) => write!(out, "({keyword} {label})").unwrap(),
+        None => write!(out, "({keyword})").unwrap(),
+    }
+}
+
+fn block(out: &mut String, b: &Block) {
+    out.push_str("(block");
+    for item in b.items {
+        out.push(' ');
+        stmt(out, item);
+    }
+    out.push(')');
+}
+
+fn if_(out: &mut String, s: &If) {
+    out.push_str("(if ");
+    expr(out, &s.condition);
+    out.push(' ');
+    block(out, &s.then);
+    match &s.otherwise {
+        Some(Else::Block(otherwise)) => {
+            out.push(' ');
+            block(out, otherwise);
+        }
+        Some(Else::If(otherwise)) => {
+            out.push(' ');
+            if_(out, otherwise);
+        }
+        None => {}
+    }
+    out.push(')');
+}
+
+fn expr(out: &mut String, e: &Expr) {
+    let (op, lhs, rhs) = match e {
+        Expr::Num(lit) => {
+            out.push_str(lit.token.content());
+            return;
+        }
+        Expr::Bool(token) | Expr::Name(token) | Expr::SelfRef(token) => {
+            out.push_str(token.content());
+            return;
+        }
+        Expr::Str(token) => {
+            write!(out, "{:?}", token.content()).unwrap();
+            return;
+        }
+        Expr::Interp(parts, _) => {
+            out.push_str("(interp");
+            for part in *parts {
+                match part {
+                    StrPart::Lit(lit) => write!(out, " {:?}", lit.content()).unwrap(),
+                    StrPart::Expr(e) => {
+                        out.push(' ');
+                        expr(out, e);
+                    }
+                }
+            }
+            out.push(')');
+            return;
+        }
+        Expr::Call(callee, args, _) => {
+            out.push_str("(call ");
+            expr(out, callee);
+            for arg in *args {
+                out.push(' ');
+                expr(out, arg);
+            }
+            out.push(')');
+            return;
+        }
+        Expr::ArrayLit(items, _) => {
+            out.push_str("(array");
+            for item in *items {
+                out.push(' ');
+                expr(out, item);
+            }
+            out.push(')');
+            return;
+        }
+        Expr::If(s) => return if_(out, s),
+        Expr::Lambda(ty, body) => {
+            out.push_str("(lambda ");
+            fn_type(out, ty);
+            out.push(' ');
+            block(out, body);
+            out.push(')');
+            return;
+        }
+        Expr::Unary(op, operand, _) => {
+            let op = match op {
+                UnaryOp::Neg => "neg",
+                UnaryOp::Not => "not",
+                UnaryOp::Deref => "deref",
+                UnaryOp::Addr => "addr",
+            };
+            write!(out, "({op} ").unwrap();
+            expr(out, operand);
+            out.push(')');
+            return;
+        }
+        Expr::Index(base, index, _) => ("index", base, index),
+        Expr::Add(lhs, rhs) => ("add", lhs, rhs),
+        Expr::Sub(lhs, rhs) => ("sub", lhs, rhs),
+        Expr::Mul(lhs, rhs) => ("mul", lhs, rhs),
+        Expr::Div(lhs, rhs) => ("div", lhs, rhs),
+        Expr::Rem(lhs, rhs) => ("rem", lhs, rhs),
+        Expr::Lt(lhs, rhs) => ("lt", lhs, rhs),
+        Expr::Eq(lhs, rhs) => ("eq", lhs, rhs),
+        Expr::Ne(lhs, rhs) => ("ne", lhs, rhs),
+        Expr::Le(lhs, rhs) => ("le", lhs, rhs),
+        Expr::Gt(lhs, rhs) => ("gt", lhs, rhs),
+        Expr::Ge(lhs, rhs) => ("ge", lhs, rhs),
+        Expr::BitAnd(lhs, rhs) => ("bitand", lhs, rhs),
+        Expr::BitOr(lhs, rhs) => ("bitor", lhs, rhs),
+        Expr::BitXor(lhs, rhs) => ("bitxor", lhs, rhs),
+        Expr::Shl(lhs, rhs) => ("shl", lhs, rhs),
+        Expr::Shr(lhs, rhs) => ("shr", lhs, rhs),
+        Expr::And(lhs, rhs) => ("and", lhs, rhs),
+        Expr::Or(lhs, rhs) => ("or", lhs, rhs),
+        Expr::Binary(op, lhs, rhs) => (op.content(), lhs, rhs),
+    };
+    write!(out, "({op} ").unwrap();
+    expr(out, lhs);
+    out.push(' ');
+    expr(out, rhs);
+    out.push(')');
+}
+
+fn optional<T>(out: &mut String, node: Option<&T>, f: fn(&mut String, &T)) {
+    match node {
+        Some(node) => f(out, node),
+        None => out.push('_'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{tokenizer, Assoc, ParserConfig, BINARY_OPS};
+    use crate::TokenizerConfig;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Counts the bytes each thread has allocated and not freed yet, so a test can
+    /// check that dropping the arena gives back everything parsing took.
+    struct Counting;
+
+    thread_local! {
+        static LIVE: Cell<isize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            LIVE.with(|live| live.set(live.get() + layout.size() as isize));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            LIVE.with(|live| live.set(live.get() - layout.size() as isize));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    #[test]
+    fn test_arena_expr() {
+        let arena = Bump::new();
+        let src = "1 + self(2, -3) * 4 < \"s\"";
+        let tok = tokenizer(src);
+        let expr = parse_expr(&tok, &arena).unwrap_or_else(|_| panic!("expected an expression"));
+        assert_eq!(&src[expr.span()], src);
+
+        let Expr::Lt(sum, Expr::Str(s)) = expr else {
+            panic!("expected `<` at the top");
+        };
+        assert_eq!(s.content(), "s");
+        let Expr::Add(Expr::Num(one), Expr::Mul(call, Expr::Num(_))) = sum else {
+            panic!("expected `1 + call * 4`");
+        };
+        assert_eq!(one.value, ast::NumValue::Int(1));
+        let Expr::Call(Expr::SelfRef(_), [Expr::Num(_), Expr::Unary(UnaryOp::Neg, ..)], span) = call else {
+            panic!("expected a call with two arguments");
+        };
+        assert_eq!(&src[*span], "self(2, -3)");
+
+        let tok = tokenizer("if 1 < 2 { :0 } + \"a\\tb${5}\" \"c\"");
+        let Ok(Expr::Add(Expr::If(_), Expr::Interp([StrPart::Lit(a), StrPart::Expr(_), StrPart::Lit(c)], _))) = parse_expr(&tok, &arena) else {
+            panic!("expected an if-expression added to an interpolated string");
+        };
+        assert_eq!((a.content(), c.content()), ("a\tb", "c"));
+
+        let tok = tokenizer("(1 + 2) * 3");
+        assert!(matches!(parse_expr(&tok, &arena), Ok(Expr::Mul(Expr::Add(..), Expr::Num(_)))));
+
+        let tok = tokenizer("1 +");
+        assert_eq!(parse_expr(&tok, &arena).err().and_then(crate::ParseError::into_error).unwrap().message, "expected expression, found end of input");
+    }
+
+    #[test]
+    fn test_arena_operators() {
+        let arena = Bump::new();
+        let mut config = ParserConfig {
+            binary_ops: [BINARY_OPS, &[BinaryOp {
+                symbol: "**",
+                precedence: 10,
+                assoc: Assoc::Right,
+                kind: BinaryKind::Custom,
+            }]].concat(),
+        };
+        // `+` rebound to build a bitwise or.
+        config.binary_ops.iter_mut().find(|op| op.symbol == "+").unwrap().kind = BinaryKind::BitOr;
+        let tok = tokenizer("1 + 2 ** 3 ** 4").with_extension(config);
+        let Ok(Expr::BitOr(Expr::Num(_), Expr::Binary(op, Expr::Num(_), Expr::Binary(..)))) = parse_expr(&tok, &arena) else {
+            panic!("expected `1 | (2 ** (3 ** 4))`");
+        };
+        assert_eq!(op.content(), "**");
+    }
+
+    #[test]
+    fn test_arena_program() {
+        let src = "\
+type Ints = int[4]*
+import \"lib\"
+mod util
+count int = 0
+max<T> (T, T) T {
+    'outer: while (x) {
+        if (a) { break 'outer } else if a == b { continue } else { :b }
+    }
+    *p = f(2)
+    f(xs[0])
+    let f = (int) int { :self(1) }
+    print! a, b
+    inner () { :0 }
+    :[a, b]
+}
+";
+        let arena = Bump::new();
+        let decls = parse_program(&tokenizer(src), &arena).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let ast_decls = ast::parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let spans = |spans: &mut dyn Iterator<Item = Span>| spans.map(|span| &src[span]).collect::<Vec<_>>();
+        assert_eq!(spans(&mut decls.iter().map(Decl::span)), spans(&mut ast_decls.iter().map(ast::Decl::span)));
+
+        let [Decl::TypeAlias { ty: Type::Ptr(Type::Array(..), _), .. }, Decl::Import(path, _), Decl::Mod(..), Decl::Global { .. }, Decl::Func(name, func)] = decls else {
+            panic!("expected an alias, an import, a module, a global and a function");
+        };
+        assert_eq!((path.content(), name.content()), ("lib", "max"));
+        assert_eq!(func.generics.len(), 1);
+        let [Stmt::While(stmt), Stmt::Assign { .. }, Stmt::Expr(Expr::Call(_, [Expr::Index(..)], _)), Stmt::Let { init: Some(Expr::Lambda(..)), .. }, Stmt::Macro(mac), Stmt::Func(..), Stmt::Return(Expr::ArrayLit(..), _)] = func.body.items else {
+            panic!("expected the statements of `max`");
+        };
+        assert_eq!(mac.args.len(), 2);
+        assert_eq!(stmt.label.as_ref().map(Token::content), Some("'outer"));
+        assert!(matches!(stmt.condition, Expr::Name(_)));
+        let [Stmt::If(If { condition: Expr::Name(_), then, otherwise: Some(Else::If(If { otherwise: Some(Else::Block(_)), .. })), .. })] = stmt.body.items else {
+            panic!("expected an `else if` chain");
+        };
+        assert!(matches!(then.items, [Stmt::Break(Some(_), _)]));
+
+        // Errors are recovered from as in `ast`.
+        let src = "f () { let = 1\n :2 }\n= 3\ng () {}";
+        let (decls, errors) = parse_program_recovering(&tokenizer(src), &arena);
+        let (ast_decls, ast_errors) = ast::parse_program_recovering(&tokenizer(src));
+        assert_eq!(decls.len(), ast_decls.len());
+        assert_eq!(errors.iter().map(|e| &e.message).collect::<Vec<_>>(), ast_errors.iter().map(|e| &e.message).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_arena_matches_ast() {
+        let corpus = [
+            "type Ints = int[4]*\ntype Table = map<str, list<int*>>[2]\nimport \"lib\"\nmod util\ncount int = 0\nlimit = 1u8 << 3",
+            "main (int, char**) int {\n    args! argc, argv\n    if argc != 1 {\n        printf(\"usage: %s <path>\", argv[0])\n        :1\n    } else {\n        :0\n    }\n}",
+            "fib (int)int {\n    args! n\n    :if n < 2 { :0 } else { :self(n - 1) + self(n - 2) }\n}",
+            "max<T> (T, T) T {\n    'outer: while (x) {\n        if (a) { break 'outer } else if a == b { continue } else { :b }\n    }\n    *p[0] = -f(2)[1]\n    xs[i] = !a && b || c\n    var y int\n    let f = (int) int { :self(1) }\n    inner () { :0 }\n    trace! { }\n    :[a, [b], f(c)(d)]\n}",
+            "ops () {\n    :a | b ^ c & d == e << 1 + 2 * 3 % 4 - 5 / 6 >= 7 <= 8 > 9 < 10 >> &x\n}",
+            "strings () {\n    :\"tab\\there \\u{00e9}\" \"${x + 1} and ${\"inner\"}\" r\"\\d+\"\n}",
+            "nums () {\n    let a = 0x1fu16\n    let b = 1.5e3\n    let c = 0b101i8\n    :a\n}",
+            // Recovered from the same way.
+            "f () { let = 1\n :2 }\n= 3\ng () { :(1 + }\nh () { if (c) { :1 } while x { x = 0 } }",
+        ];
+        let config = ParserConfig {
+            binary_ops: [BINARY_OPS, &[BinaryOp { symbol: "**", precedence: 10, assoc: Assoc::Right, kind: BinaryKind::Custom }]].concat(),
+        };
+        let arena = Bump::new();
+        for src in corpus {
+            for config in [None, Some(&config)] {
+                let with_config = |tok: Tokenizer<'static>| match config {
+                    Some(config) => tok.with_extension(config.clone()),
+                    None => tok,
+                };
+                let (decls, errors) = parse_program_recovering(&with_config(tokenizer(src)), &arena);
+                let (ast_decls, ast_errors) = ast::parse_program_recovering(&with_config(tokenizer(src)));
+                assert_eq!(decls.iter().map(Decl::to_sexpr).collect::<Vec<_>>(), ast_decls.iter().map(ast::Decl::to_sexpr).collect::<Vec<_>>(), "{src}");
+                let messages = |errors: &[Error]| errors.iter().map(|error| (error.span, error.message.clone())).collect::<Vec<_>>();
+                assert_eq!(messages(&errors), messages(&ast_errors), "{src}");
+            }
+        }
+        let src = "pow () { :2 ** 3 ** 4 }";
+        let tok = tokenizer(src).with_extension(config);
+        assert_eq!(parse_program(&tok, &arena).unwrap_or_else(|_| panic!("expected the program to parse"))[0].to_sexpr(), "(fn pow (fn-type ()) (block (ret (** 2 (** 3 4)))))");
+    }
+
+    #[test]
+    fn test_arena_frees_everything() {
+        // Escapes and joined strings make owned text, and trivia fills the tokens' `Vec`s.
+        let src = "\
+// a comment
+f (int) int {
+    let s = \"a\\tb\" \"c\" /* more */
+    let t = \"${s}\\n${(int) int { :0 }(1)}\"
+    if s { :1 } else { :2 }
+}
+";
+        let parse = || {
+            let tok = crate::Tokenizer::new_with_config(src, TokenizerConfig { preserve_trivia: true, ..ast::config() });
+            let arena = Bump::new();
+            let decls = parse_program(&tok, &arena).unwrap_or_else(|_| panic!("expected the program to parse"));
+            assert_eq!(decls.len(), 1);
+        };
+        // The first parse may set up state that lives on; only the second is measured.
+        parse();
+        let before = LIVE.with(Cell::get);
+        parse();
+        assert_eq!(LIVE.with(Cell::get) - before, 0, "bytes still allocated after dropping the arena");
+    }
+}
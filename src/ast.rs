@@ -0,0 +1,2322 @@
+//! The sample language's syntax tree and its recursive-descent parser.
+
+use crate::{Token, TokenKind, Tokenizer, TokenizerConfig, Error, Span, Location, StringPart, FileId, SourceManager, ParseError, ParseResult, ParseResultExt, Number, NumberKind};
+use std::borrow::Cow;
+use std::iter::Peekable;
+use std::str::Chars;
+
+pub use crate::dot::to_dot;
+pub use crate::comments::Comments;
+pub use crate::metrics::{metrics, Metrics};
+
+/// Words that can never be used as names.
+pub const KEYWORDS: &[&str] = &["if", "else", "let", "var", "self", "true", "false", "type", "import", "mod", "while", "break", "continue"];
+
+/// A tokenizer configured with the language's reserved words.
+pub fn tokenizer(source: &str) -> Tokenizer<'_> {
+    Tokenizer::new_with_config(source, config())
+}
+
+/// The default [`TokenizerConfig::max_nesting`] for the language's parsers.
+pub const MAX_NESTING: usize = 128;
+
+/// The tokenizer settings behind [`tokenizer`]; start from these to change the
+/// language's lexical rules. The rest of the grammar is set with a [`ParserConfig`].
+pub fn config() -> TokenizerConfig {
+    TokenizerConfig {
+        keywords: KEYWORDS.iter().map(|s| s.to_string()).collect(),
+        // Every level is a handful of recursive calls; this keeps hostile input like
+        // `((((...))))` within the main thread's stack, even in debug builds.
+        max_nesting: Some(MAX_NESTING),
+        ..TokenizerConfig::default()
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Decl<'a> {
+    Func(Token<'a>, Func<'a>),
+    /// `name type = init` at file scope; like [`Stmt::Let`], the type or the
+    /// initializer may be left out.
+    Global { name: Token<'a>, ty: Option<Type<'a>>, init: Option<Expr<'a>> },
+    /// `type name = type`
+    TypeAlias { name: Token<'a>, ty: Type<'a>, span: Span },
+    /// `import "path"`; the token's content is the path with escapes processed.
+    Import(Token<'a>, Span),
+    /// `mod name`
+    Mod(Token<'a>, Span),
+}
+
+/// One file of a multi-file program.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Module<'a> {
+    pub file: FileId,
+    pub decls: Vec<Decl<'a>>,
+    /// The file each [`Decl::Import`] and [`Decl::Mod`] in `decls` resolved to, in order.
+    pub imports: Vec<FileId>,
+}
+
+/// What an `import` or `mod` declaration asks a [`Resolver`] for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ModuleRef<'a> {
+    /// `import "path"`
+    Import(&'a str),
+    /// `mod name`
+    Mod(&'a str),
+}
+
+/// Loads the sources that `import` and `mod` declarations refer to.
+pub trait Resolver {
+    /// Returns the name and contents of the file `target` refers to from inside the
+    /// file named `from`. Returning a name that is already loaded reuses that file.
+    fn resolve(&mut self, from: &str, target: ModuleRef<'_>) -> Result<(String, String), String>;
+}
+
+impl<F> Resolver for F
+where
+    F: FnMut(&str, ModuleRef<'_>) -> Result<(String, String), String>,
+{
+    #[inline]
+    fn resolve(&mut self, from: &str, target: ModuleRef<'_>) -> Result<(String, String), String> {
+        self(from, target)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Func<'a> {
+    /// The names in a `name<T, U> (...)` parameter list, empty for ordinary functions.
+    pub generics: Vec<Token<'a>>,
+    pub ty: FnType<'a>,
+    pub body: Block<'a>
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Type<'a> {
+    /// A named type. Builtins like `int`, `char`, and `bool` (the type of
+    /// [`Expr::Bool`] and of comparisons) are ordinary names here.
+    Name(Token<'a>),
+    Ptr(Box<Self>, Span),
+    /// `elem[len]`, or `elem[]` when the length is left out.
+    Array(Box<Self>, Option<Expr<'a>>, Span),
+    Func(Box<FnType<'a>>),
+    /// `name<arg, ...>`, a generic type applied to arguments.
+    Generic(Token<'a>, Vec<Self>, Span),
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FnType<'a> {
+    pub args: Vec<Type<'a>>,
+    pub ret: Option<Type<'a>>,
+    pub span: Span,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Expr<'a> {
+    Num(NumLit<'a>),
+    Str(Token<'a>),
+    /// `true` or `false`, of type `bool`.
+    Bool(Token<'a>),
+    Interp(Vec<StrPart<'a>>, Span),
+    Name(Token<'a>),
+    /// The `self` keyword, referring to the enclosing function.
+    SelfRef(Token<'a>),
+    /// `callee(arg, ...)`
+    Call(Box<Self>, Vec<Self>, Span),
+    /// `base[index]`
+    Index(Box<(Self, Self)>, Span),
+    /// `[a, b, ...]`
+    ArrayLit(Vec<Self>, Span),
+    /// `if` in expression position, as in `:if c { :a } else { :b }`; its value is
+    /// whatever the taken branch returns.
+    If(Box<If<'a>>),
+    /// `(args) ret { ... }`, a function written in place, as in `sort(xs, (int, int) bool { :a < b })`.
+    /// Like [`Stmt::Func`] it captures nothing, and `self` inside refers to the lambda.
+    Lambda { ty: Box<FnType<'a>>, body: Block<'a> },
+    Add(Box<(Self, Self)>),
+    Sub(Box<(Self, Self)>),
+    Mul(Box<(Self, Self)>),
+    Div(Box<(Self, Self)>),
+    Rem(Box<(Self, Self)>),
+    Unary(UnaryOp, Box<Self>, Span),
+    Lt(Box<(Self, Self)>),
+    Eq(Box<(Self, Self)>),
+    Ne(Box<(Self, Self)>),
+    Le(Box<(Self, Self)>),
+    Gt(Box<(Self, Self)>),
+    Ge(Box<(Self, Self)>),
+    BitAnd(Box<(Self, Self)>),
+    BitOr(Box<(Self, Self)>),
+    BitXor(Box<(Self, Self)>),
+    Shl(Box<(Self, Self)>),
+    Shr(Box<(Self, Self)>),
+    /// `lhs && rhs`; `rhs` is only evaluated when `lhs` is true.
+    And(Box<(Self, Self)>),
+    /// `lhs || rhs`; `rhs` is only evaluated when `lhs` is false.
+    Or(Box<(Self, Self)>),
+    /// `lhs op rhs` for an operator added to [`ParserConfig::binary_ops`] that has no
+    /// variant of its own.
+    Binary(Token<'a>, Box<(Self, Self)>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum UnaryOp {
+    /// `-x`
+    Neg,
+    /// `!x`
+    Not,
+    /// `*p`
+    Deref,
+    /// `&x`
+    Addr,
+}
+
+/// A numeric literal with its value worked out at parse time.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NumLit<'a> {
+    pub token: Token<'a>,
+    pub value: NumValue,
+    pub radix: u32,
+    /// The type suffix, like `u8` in `0xFFu8`.
+    pub suffix: Option<Cow<'a, str>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum NumValue {
+    Int(u64),
+    Float(f64),
+}
+
+/// A piece of an interpolated string: literal text or a `${expr}` hole.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum StrPart<'a> {
+    Lit(Token<'a>),
+    Expr(Expr<'a>),
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Stmt<'a> {
+    If(If<'a>),
+    Return(Expr<'a>, Span),
+    Block(Block<'a>),
+    Assign { target: Expr<'a>, value: Expr<'a> },
+    /// A call made for its effects, as in `printf("hi")`. Its result is discarded.
+    Expr(Expr<'a>),
+    Macro(Macro<'a>),
+    /// `let name type = init`, where either the type or the initializer may be left
+    /// out. `var` is accepted in place of `let`.
+    Let { name: Token<'a>, ty: Option<Type<'a>>, init: Option<Expr<'a>>, span: Span },
+    While(While<'a>),
+    /// `break`, or `break 'label` to leave an outer loop.
+    Break(Option<Token<'a>>, Span),
+    /// `continue`, or `continue 'label` to go on with an outer loop.
+    Continue(Option<Token<'a>>, Span),
+    /// A function declared inside a block, like a [`Decl::Func`]. It doesn't capture
+    /// anything, so its body only sees its arguments and the declarations at file scope.
+    Func(Token<'a>, Func<'a>),
+}
+
+/// `while condition { ... }`, optionally labelled as in `'outer: while ...`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct While<'a> {
+    /// The label including its quote, like `'outer`.
+    pub label: Option<Token<'a>>,
+    pub condition: Expr<'a>,
+    pub body: Block<'a>,
+    pub span: Span,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Macro<'a> {
+    pub name: Token<'a>,
+    pub args: Vec<Token<'a>>,
+    pub span: Span,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct If<'a> {
+    pub condition: Expr<'a>,
+    pub then: Block<'a>,
+    pub otherwise: Option<Else<'a>>,
+    /// From `if` to the end of the last `else`, so each arm of an `else if` chain
+    /// spans the rest of the chain.
+    pub span: Span,
+}
+
+// An `else` block is far more common than `else if`, so it's the one kept inline.
+#[allow(clippy::large_enum_variant)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Else<'a> {
+    /// `else { ... }`
+    Block(Block<'a>),
+    /// `else if ...`
+    If(Box<If<'a>>),
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Block<'a> {
+    pub left: Token<'a>,
+    pub items: Vec<Stmt<'a>>,
+    pub right: Token<'a>
+}
+
+// Nodes whose extent isn't covered by their children, like `*` on a pointer type or
+// the keyword starting a statement, keep their own span; the rest merge children.
+
+impl Decl<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Func(name, func) => name.span.merge(func.span()),
+            Self::Global { name, ty, init } => {
+                let end = init.as_ref().map(Expr::span).or_else(|| ty.as_ref().map(Type::span));
+                end.map_or(name.span, |end| name.span.merge(end))
+            }
+            Self::TypeAlias { span, .. } | Self::Import(_, span) | Self::Mod(_, span) => *span,
+        }
+    }
+}
+
+impl Func<'_> {
+    /// From the argument list to the end of the body; see [`Decl::span`] for the name.
+    #[inline]
+    pub fn span(&self) -> Span {
+        self.ty.span.merge(self.body.span())
+    }
+}
+
+impl Type<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Name(name) => name.span,
+            Self::Func(func) => func.span,
+            Self::Ptr(_, span) | Self::Array(_, _, span) | Self::Generic(_, _, span) => *span,
+        }
+    }
+}
+
+impl Expr<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Num(lit) => lit.token.span,
+            Self::Str(token) | Self::Bool(token) | Self::Name(token) | Self::SelfRef(token) => token.span,
+            Self::Interp(_, span) | Self::Call(_, _, span) | Self::ArrayLit(_, span) | Self::Unary(_, _, span) => *span,
+            Self::Index(_, span) => *span,
+            Self::If(stmt) => stmt.span,
+            Self::Lambda { ty, body } => ty.span.merge(body.span()),
+            Self::Add(operands)
+            | Self::Sub(operands)
+            | Self::Mul(operands)
+            | Self::Div(operands)
+            | Self::Rem(operands)
+            | Self::Lt(operands)
+            | Self::Eq(operands)
+            | Self::Ne(operands)
+            | Self::Le(operands)
+            | Self::Gt(operands)
+            | Self::Ge(operands)
+            | Self::BitAnd(operands)
+            | Self::BitOr(operands)
+            | Self::BitXor(operands)
+            | Self::Shl(operands)
+            | Self::Shr(operands)
+            | Self::And(operands)
+            | Self::Or(operands)
+            | Self::Binary(_, operands) => operands.0.span().merge(operands.1.span()),
+        }
+    }
+}
+
+impl StrPart<'_> {
+    /// The literal text, or the expression inside `${}`.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Lit(token) => token.span,
+            Self::Expr(expr) => expr.span(),
+        }
+    }
+}
+
+impl Stmt<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::If(stmt) => stmt.span,
+            Self::Return(_, span) | Self::Let { span, .. } | Self::Break(_, span) | Self::Continue(_, span) => *span,
+            Self::Block(block) => block.span(),
+            Self::Assign { target, value } => target.span().merge(value.span()),
+            Self::Expr(expr) => expr.span(),
+            Self::Macro(mac) => mac.span,
+            Self::While(stmt) => stmt.span,
+            Self::Func(name, func) => name.span.merge(func.span()),
+        }
+    }
+}
+
+impl Else<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Block(block) => block.span(),
+            Self::If(stmt) => stmt.span,
+        }
+    }
+}
+
+impl Block<'_> {
+    #[inline]
+    pub fn span(&self) -> Span {
+        self.left.span.merge(self.right.span)
+    }
+}
+
+/// Whether `a` and `b` are the same tree, whatever their spans and the layout of the
+/// source they came from. Both are compared through [`Decl::to_sexpr`], which also
+/// serves as a hashable normal form, e.g. as a cache key.
+pub fn eq_ignoring_spans(a: &Decl, b: &Decl) -> bool {
+    a.to_sexpr() == b.to_sexpr()
+}
+
+// `into_owned` copies every borrowed token so the tree no longer refers to the source.
+
+impl Module<'_> {
+    pub fn into_owned(self) -> Module<'static> {
+        Module {
+            file: self.file,
+            decls: self.decls.into_iter().map(Decl::into_owned).collect(),
+            imports: self.imports,
+        }
+    }
+}
+
+impl Decl<'_> {
+    pub fn into_owned(self) -> Decl<'static> {
+        match self {
+            Self::Func(name, func) => Decl::Func(name.into_owned(), func.into_owned()),
+            Self::Global { name, ty, init } => Decl::Global {
+                name: name.into_owned(),
+                ty: ty.map(Type::into_owned),
+                init: init.map(Expr::into_owned),
+            },
+            Self::TypeAlias { name, ty, span } => Decl::TypeAlias { name: name.into_owned(), ty: ty.into_owned(), span },
+            Self::Import(path, span) => Decl::Import(path.into_owned(), span),
+            Self::Mod(name, span) => Decl::Mod(name.into_owned(), span),
+        }
+    }
+}
+
+impl Func<'_> {
+    pub fn into_owned(self) -> Func<'static> {
+        Func {
+            generics: self.generics.into_iter().map(Token::into_owned).collect(),
+            ty: self.ty.into_owned(),
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+impl Type<'_> {
+    pub fn into_owned(self) -> Type<'static> {
+        match self {
+            Self::Name(name) => Type::Name(name.into_owned()),
+            Self::Ptr(inner, span) => Type::Ptr(Box::new(inner.into_owned()), span),
+            Self::Array(elem, len, span) => Type::Array(Box::new(elem.into_owned()), len.map(Expr::into_owned), span),
+            Self::Func(func) => Type::Func(Box::new(func.into_owned())),
+            Self::Generic(name, args, span) => {
+                Type::Generic(name.into_owned(), args.into_iter().map(Type::into_owned).collect(), span)
+            }
+        }
+    }
+}
+
+impl FnType<'_> {
+    pub fn into_owned(self) -> FnType<'static> {
+        FnType {
+            args: self.args.into_iter().map(Type::into_owned).collect(),
+            ret: self.ret.map(Type::into_owned),
+            span: self.span,
+        }
+    }
+}
+
+impl Expr<'_> {
+    pub fn into_owned(self) -> Expr<'static> {
+        let pair = |operands: Box<(Self, Self)>| {
+            let (lhs, rhs) = *operands;
+            Box::new((lhs.into_owned(), rhs.into_owned()))
+        };
+        match self {
+            Self::Num(lit) => Expr::Num(lit.into_owned()),
+            Self::Str(token) => Expr::Str(token.into_owned()),
+            Self::Bool(token) => Expr::Bool(token.into_owned()),
+            Self::Interp(parts, span) => Expr::Interp(parts.into_iter().map(StrPart::into_owned).collect(), span),
+            Self::Name(token) => Expr::Name(token.into_owned()),
+            Self::SelfRef(token) => Expr::SelfRef(token.into_owned()),
+            Self::Call(callee, args, span) => {
+                Expr::Call(Box::new(callee.into_owned()), args.into_iter().map(Expr::into_owned).collect(), span)
+            }
+            Self::Index(operands, span) => Expr::Index(pair(operands), span),
+            Self::ArrayLit(items, span) => Expr::ArrayLit(items.into_iter().map(Expr::into_owned).collect(), span),
+            Self::If(stmt) => Expr::If(Box::new(stmt.into_owned())),
+            Self::Lambda { ty, body } => Expr::Lambda { ty: Box::new(ty.into_owned()), body: body.into_owned() },
+            Self::Add(operands) => Expr::Add(pair(operands)),
+            Self::Sub(operands) => Expr::Sub(pair(operands)),
+            Self::Mul(operands) => Expr::Mul(pair(operands)),
+            Self::Div(operands) => Expr::Div(pair(operands)),
+            Self::Rem(operands) => Expr::Rem(pair(operands)),
+            Self::Unary(op, operand, span) => Expr::Unary(op, Box::new(operand.into_owned()), span),
+            Self::Lt(operands) => Expr::Lt(pair(operands)),
+            Self::Eq(operands) => Expr::Eq(pair(operands)),
+            Self::Ne(operands) => Expr::Ne(pair(operands)),
+            Self::Le(operands) => Expr::Le(pair(operands)),
+            Self::Gt(operands) => Expr::Gt(pair(operands)),
+            Self::Ge(operands) => Expr::Ge(pair(operands)),
+            Self::BitAnd(operands) => Expr::BitAnd(pair(operands)),
+            Self::BitOr(operands) => Expr::BitOr(pair(operands)),
+            Self::BitXor(operands) => Expr::BitXor(pair(operands)),
+            Self::Shl(operands) => Expr::Shl(pair(operands)),
+            Self::Shr(operands) => Expr::Shr(pair(operands)),
+            Self::And(operands) => Expr::And(pair(operands)),
+            Self::Or(operands) => Expr::Or(pair(operands)),
+            Self::Binary(op, operands) => Expr::Binary(op.into_owned(), pair(operands)),
+        }
+    }
+}
+
+impl NumLit<'_> {
+    pub fn into_owned(self) -> NumLit<'static> {
+        NumLit {
+            token: self.token.into_owned(),
+            suffix: self.suffix.map(|suffix| Cow::Owned(suffix.into_owned())),
+            ..self
+        }
+    }
+}
+
+impl StrPart<'_> {
+    pub fn into_owned(self) -> StrPart<'static> {
+        match self {
+            Self::Lit(token) => StrPart::Lit(token.into_owned()),
+            Self::Expr(expr) => StrPart::Expr(expr.into_owned()),
+        }
+    }
+}
+
+impl Stmt<'_> {
+    pub fn into_owned(self) -> Stmt<'static> {
+        match self {
+            Self::If(stmt) => Stmt::If(stmt.into_owned()),
+            Self::Return(expr, span) => Stmt::Return(expr.into_owned(), span),
+            Self::Block(block) => Stmt::Block(block.into_owned()),
+            Self::Assign { target, value } => Stmt::Assign { target: target.into_owned(), value: value.into_owned() },
+            Self::Expr(expr) => Stmt::Expr(expr.into_owned()),
+            Self::Macro(mac) => Stmt::Macro(mac.into_owned()),
+            Self::Let { name, ty, init, span } => Stmt::Let {
+                name: name.into_owned(),
+                ty: ty.map(Type::into_owned),
+                init: init.map(Expr::into_owned),
+                span,
+            },
+            Self::While(stmt) => Stmt::While(stmt.into_owned()),
+            Self::Break(label, span) => Stmt::Break(label.map(Token::into_owned), span),
+            Self::Continue(label, span) => Stmt::Continue(label.map(Token::into_owned), span),
+            Self::Func(name, func) => Stmt::Func(name.into_owned(), func.into_owned()),
+        }
+    }
+}
+
+impl While<'_> {
+    pub fn into_owned(self) -> While<'static> {
+        While {
+            label: self.label.map(Token::into_owned),
+            condition: self.condition.into_owned(),
+            body: self.body.into_owned(),
+            span: self.span,
+        }
+    }
+}
+
+impl Macro<'_> {
+    pub fn into_owned(self) -> Macro<'static> {
+        Macro {
+            name: self.name.into_owned(),
+            args: self.args.into_iter().map(Token::into_owned).collect(),
+            span: self.span,
+        }
+    }
+}
+
+impl If<'_> {
+    pub fn into_owned(self) -> If<'static> {
+        If {
+            condition: self.condition.into_owned(),
+            then: self.then.into_owned(),
+            otherwise: self.otherwise.map(Else::into_owned),
+            span: self.span,
+        }
+    }
+}
+
+impl Else<'_> {
+    pub fn into_owned(self) -> Else<'static> {
+        match self {
+            Self::Block(block) => Else::Block(block.into_owned()),
+            Self::If(stmt) => Else::If(Box::new(stmt.into_owned())),
+        }
+    }
+}
+
+impl Block<'_> {
+    pub fn into_owned(self) -> Block<'static> {
+        Block {
+            left: self.left.into_owned(),
+            items: self.items.into_iter().map(Stmt::into_owned).collect(),
+            right: self.right.into_owned(),
+        }
+    }
+}
+
+/// Parses declarations until the end of input. Input that isn't a declaration is
+/// reported rather than silently ignored.
+pub fn parse_program<'src>(tok: &Tokenizer<'src>) -> Result<Vec<Decl<'src>>, Vec<Error>> {
+    let (decls, errors) = parse_program_recovering(tok);
+    if errors.is_empty() {
+        Ok(decls)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Like [`parse_program`], but keeps going after errors: a declaration or statement
+/// that fails to parse is reported and skipped up to the next statement boundary.
+/// Returns the declarations that did parse along with every error.
+pub fn parse_program_recovering<'src>(tok: &Tokenizer<'src>) -> (Vec<Decl<'src>>, Vec<Error>) {
+    let mut decls = Vec::new();
+    while tok.has_more_tokens() {
+        let start = tok.location();
+        match tok.try_with(|tok| tok.labelled("declaration", parse_decl)) {
+            Ok(decl) => decls.push(decl),
+            Err(error) => {
+                let error = error.into_error().unwrap_or_else(|| tok.expectation_error());
+                synchronize(tok, start, false);
+                tok.report(error);
+            }
+        }
+    }
+    if let Some(error) = tok.error() {
+        tok.report(error);
+    }
+    (decls, tok.take_diagnostics())
+}
+
+/// Skips the rest of a statement that began at `start` and failed to parse: up to the
+/// first token on a later line, just past a `;`, or, inside a block, up to its `}`.
+/// Something is always consumed unless a `}` ends the block, so the caller can't stall.
+pub(crate) fn synchronize(tok: &Tokenizer, start: Location, in_block: bool) {
+    loop {
+        let token = match tok.peek_token() {
+            Ok(token) => token,
+            Err(error) => {
+                tok.skip_invalid(error.location());
+                continue;
+            }
+        };
+        if token.kind == TokenKind::Eof || (in_block && token == "}") {
+            break;
+        }
+        if token == ";" {
+            let _ = tok.next_token();
+            break;
+        }
+        if token.span.start.line > start.line && tok.location() != start {
+            break;
+        }
+        let _ = tok.next_token();
+    }
+}
+
+/// Parses `root` and every file it reaches through `import` and `mod`, adding the
+/// files `resolver` loads to `sources`. Modules come back in load order, root first.
+pub fn parse_modules<'sm>(
+    sources: &'sm mut SourceManager,
+    root: FileId,
+    resolver: &mut impl Resolver,
+) -> Result<Vec<Module<'sm>>, Vec<Error>> {
+    // Loading a file needs `sources` mutably, so each file is first parsed from a copy
+    // to find what it refers to, and the trees are built once everything is loaded.
+    let mut order = vec![root];
+    let mut imports = Vec::new();
+    let mut errors = Vec::new();
+    while let Some(&file) = order.get(imports.len()) {
+        let name = sources.name(file).unwrap_or_default().to_string();
+        let source = sources.source(file).unwrap_or_default().to_string();
+        let decls = match parse_program(&Tokenizer::for_file((file, &source), config())) {
+            Ok(decls) => decls,
+            Err(e) => {
+                errors.extend(e);
+                Vec::new()
+            }
+        };
+
+        let mut resolved = Vec::new();
+        for decl in &decls {
+            let (token, target) = match decl {
+                Decl::Import(path, _) => (path, ModuleRef::Import(path.content())),
+                Decl::Mod(name, _) => (name, ModuleRef::Mod(name.content())),
+                _ => continue,
+            };
+            match resolver.resolve(&name, target) {
+                Ok((name, source)) => {
+                    let id = sources.find(&name).unwrap_or_else(|| sources.add(name, source));
+                    if !order.contains(&id) {
+                        order.push(id);
+                    }
+                    resolved.push(id);
+                }
+                Err(message) => {
+                    errors.push(Error::new(token.span, format!("cannot load `{}`: {message}", token.content())));
+                }
+            }
+        }
+        imports.push(resolved);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let sources = &*sources;
+    order.into_iter()
+        .zip(imports)
+        .map(|(file, imports)| {
+            let source = sources.source(file).unwrap_or_default();
+            let decls = parse_program(&Tokenizer::for_file((file, source), config()))?;
+            Ok(Module { file, decls, imports })
+        })
+        .collect()
+}
+
+pub fn parse_decl<'src>(tok: &Tokenizer<'src>) -> ParseResult<Decl<'src>> {
+    if let Some(keyword) = tok.consume_keyword("import") {
+        let path = tok.labelled("path string", parse_expr_str)
+            .required(tok)?;
+        let span = keyword.span.merge(path.span);
+        return Ok(Decl::Import(path, span));
+    }
+    if let Some(keyword) = tok.consume_keyword("mod") {
+        let name = tok.consume_ident()
+            .ok_or_else(|| tok.expected("module name"))
+            .required(tok)?;
+        let span = keyword.span.merge(name.span);
+        return Ok(Decl::Mod(name, span));
+    }
+    if let Some(alias) = tok.try_with(parse_type_alias).optional()? {
+        return Ok(alias);
+    }
+    let name = tok.consume_ident().ok_or(ParseError::NoMatch)?;
+    let generics = parse_generic_params(tok)?;
+    if !generics.is_empty() || tok.peek_str("(").is_some() {
+        let ty = parse_fn_type(tok)
+            .required(tok)?;
+        let body = parse_block(tok)
+            .required(tok)?;
+        Ok(Decl::Func(name, Func { generics, ty, body }))
+    } else {
+        let ty = tok.try_with(parse_type).optional()?;
+        let init = tok.consume("=")
+            .map(|_| parse_expr(tok).required(tok))
+            .transpose()?;
+        if ty.is_none() && init.is_none() {
+            return Err(tok.expected("`=`"));
+        }
+        Ok(Decl::Global { name, ty, init })
+    }
+}
+
+/// Parses an optional `<T, U>` list of type parameter names.
+pub fn parse_generic_params<'src>(tok: &Tokenizer<'src>) -> Result<Vec<Token<'src>>, Error> {
+    if tok.peek_str("<").is_none() {
+        return Ok(Vec::new());
+    }
+    tok.separated_list("<", ",", ">", "to close type parameters", |tok| tok.consume_ident().ok_or_else(|| tok.expected("type parameter name")))
+        .map(|(_, params, _)| params)
+        .map_err(|error| error.into_error().expect("`<` was just peeked"))
+}
+
+pub fn parse_type_alias<'src>(tok: &Tokenizer<'src>) -> ParseResult<Decl<'src>> {
+    let Some(keyword) = tok.consume_keyword("type") else {
+        return Err(ParseError::NoMatch);
+    };
+    let name = tok.consume_ident()
+        .ok_or_else(|| tok.expected("alias name"))
+        .required(tok)?;
+    tok.expect_with("=", "after type alias name")?;
+    let ty = parse_type(tok)
+        .required(tok)?;
+    let span = keyword.span.merge(ty.span());
+    Ok(Decl::TypeAlias { name, ty, span })
+}
+
+/// Parses a type without resolving names: aliases, builtins, and unknown names all
+/// come out as [`Type::Name`] for the semantic layer to sort out.
+pub fn parse_type<'src>(tok: &Tokenizer<'src>) -> ParseResult<Type<'src>> {
+    if let Some(func) = tok.labelled("type", |tok| tok.try_with(parse_fn_type)).optional()? {
+        Ok(Type::Func(Box::new(func)))
+    } else if let Some(word) = tok.consume_ident() {
+        // Suffixes apply left to right, so `int[4]*` points to an array of four ints
+        // while `int*[4]` is an array of four pointers.
+        let mut out = match tok.peek_str("<") {
+            Some(_) => {
+                let (_, args, right) = tok.separated_list("<", ",", ">", "to close type arguments", |tok| tok.nested(parse_type))?;
+                let span = word.span.merge(right.span);
+                Type::Generic(word, args, span)
+            }
+            None => Type::Name(word),
+        };
+        loop {
+            if let Some(star) = tok.consume("*") {
+                let span = out.span().merge(star.span);
+                out = Type::Ptr(Box::new(out), span);
+            } else if tok.consume("[").is_some() {
+                let len = tok.try_with(parse_expr).optional()?;
+                let right = tok.expect_with("]", "to close array type")?;
+                let span = out.span().merge(right.span);
+                out = Type::Array(Box::new(out), len, span);
+            } else {
+                break;
+            }
+        }
+        Ok(out)
+    } else {
+        Err(tok.expected("type"))
+    }
+}
+
+pub fn parse_fn_type<'src>(tok: &Tokenizer<'src>) -> ParseResult<FnType<'src>> {
+    let (left, args, right) = tok.separated_list("(", ",", ")", "to close parameter list", |tok| tok.nested(parse_type))?;
+
+    let ret = tok.try_with(|tok| tok.nested(parse_type)).optional()?;
+    let span = left.span.merge(ret.as_ref().map_or(right.span, Type::span));
+
+    Ok(FnType { args, ret, span })
+}
+
+pub fn parse_stmt<'src>(tok: &Tokenizer<'src>) -> ParseResult<Stmt<'src>> {
+    if let Some(block) = tok.try_with(parse_block).optional()? {
+        Ok(Stmt::Block(block))
+    } else if let Some(stmt) = tok.try_with(parse_if).optional()? {
+        Ok(Stmt::If(stmt))
+    } else if let Some(stmt) = tok.try_with(parse_while).optional()? {
+        Ok(Stmt::While(stmt))
+    } else if let Some(stmt) = tok.try_with(parse_jump).optional()? {
+        Ok(stmt)
+    } else if let Some(stmt) = tok.try_with(parse_return).optional()? {
+        Ok(stmt)
+    } else if let Some(stmt) = tok.try_with(parse_let).optional()? {
+        Ok(stmt)
+    } else if let Some(mac) = tok.try_with(parse_macro).optional()? {
+        Ok(Stmt::Macro(mac))
+    } else if let Some(stmt) = tok.try_with(parse_func_stmt).optional()? {
+        Ok(stmt)
+    } else if let Some(stmt) = tok.try_with(parse_expr_stmt).optional()? {
+        Ok(stmt)
+    } else {
+        Err(ParseError::NoMatch)
+    }
+}
+
+/// `name (args) ret { ... }` inside a block. Up to the `{` of the body it reads like a
+/// call, so anything else is left to the other statements.
+pub fn parse_func_stmt<'src>(tok: &Tokenizer<'src>) -> ParseResult<Stmt<'src>> {
+    let name = tok.consume_ident().ok_or(ParseError::NoMatch)?;
+    if tok.peek_str("<").is_none() && tok.peek_str("(").is_none() {
+        return Err(ParseError::NoMatch);
+    }
+    let (generics, ty) = tok.try_with(|tok| Ok((parse_generic_params(tok)?, parse_fn_type(tok)?)))
+        .map_err(|_| ParseError::NoMatch)?;
+    if tok.peek_str("{").is_none() {
+        return Err(ParseError::NoMatch);
+    }
+    let body = parse_block(tok)
+        .required(tok)?;
+    Ok(Stmt::Func(name, Func { generics, ty, body }))
+}
+
+pub fn parse_let<'src>(tok: &Tokenizer<'src>) -> ParseResult<Stmt<'src>> {
+    let Some(keyword) = tok.consume_keyword("let").or_else(|| tok.consume_keyword("var")) else {
+        return Err(tok.expected("`let`"));
+    };
+    let name = tok.consume_ident()
+        .ok_or_else(|| tok.expected("variable name"))
+        .required(tok)?;
+    let ty = tok.try_with(parse_type).optional()?;
+    let init = tok.consume("=")
+        .map(|_| parse_expr(tok).required(tok))
+        .transpose()?;
+    if ty.is_none() && init.is_none() {
+        tok.expected("`=`");
+        return Err(tok.expectation_error().into());
+    }
+    let end = init.as_ref().map(Expr::span).or_else(|| ty.as_ref().map(Type::span));
+    let span = keyword.span.merge(end.unwrap_or(name.span));
+    Ok(Stmt::Let { name, ty, init, span })
+}
+
+/// `name! arg, arg, ...` where the arguments run to the end of the line or a `{`.
+pub fn parse_macro<'src>(tok: &Tokenizer<'src>) -> ParseResult<Macro<'src>> {
+    let name = tok.consume_ident().ok_or(ParseError::NoMatch)?;
+    let bang = tok.consume("!").ok_or_else(|| tok.expected("`!`"))?;
+    let line = bang.span.end.line;
+
+    let mut args = Vec::new();
+    loop {
+        let next = tok.peek_token()?;
+        if next.kind == TokenKind::Eof || next.span.start.line != line || next == "{" {
+            break;
+        }
+        args.push(tok.next_token()?);
+        if tok.peek_token()?.span.start.line != line || tok.consume(",").is_none() {
+            break;
+        }
+    }
+    let span = name.span.merge(args.last().map_or(bang.span, |arg| arg.span));
+    Ok(Macro { name, args, span })
+}
+
+/// `place = expr`, or a call made for its effects. Both start with an expression,
+/// which is a place only if a `=` follows it.
+pub fn parse_expr_stmt<'src>(tok: &Tokenizer<'src>) -> ParseResult<Stmt<'src>> {
+    let start = tok.location();
+    let target = parse_place(tok)?;
+    if tok.peek_str("==").is_some() || tok.consume("=").is_none() {
+        if let Expr::Call(..) = target {
+            return Ok(Stmt::Expr(target));
+        }
+        return Err(tok.expected("`=`"));
+    }
+    if !is_assignable(&target) {
+        return Err(Error::at(start, "cannot assign to this expression".to_string()).into());
+    }
+
+    let value = parse_expr(tok)
+        .required(tok)?;
+    Ok(Stmt::Assign { target, value })
+}
+
+/// The left-hand side of an assignment: `*place`, or any other unary expression so
+/// a misplaced `=` is reported rather than skipped.
+fn parse_place<'src>(tok: &Tokenizer<'src>) -> ParseResult<Expr<'src>> {
+    if let Some(star) = tok.consume("*") {
+        let operand = tok.nested(parse_place)?;
+        let span = star.span.merge(operand.span());
+        Ok(Expr::Unary(UnaryOp::Deref, Box::new(operand), span))
+    } else {
+        parse_expr_unary(tok)
+    }
+}
+
+/// Whether `expr` names a place that can be assigned to: a variable, a dereference or
+/// an element.
+fn is_assignable(expr: &Expr) -> bool {
+    matches!(expr, Expr::Name(_) | Expr::Unary(UnaryOp::Deref, ..) | Expr::Index(..))
+}
+
+/// `:expr`
+pub fn parse_return<'src>(tok: &Tokenizer<'src>) -> ParseResult<Stmt<'src>> {
+    let Some(colon) = tok.consume(":") else {
+        return Err(tok.expected("`:`"));
+    };
+    let expr = parse_expr(tok)
+        .required(tok)?;
+    let span = colon.span.merge(expr.span());
+    Ok(Stmt::Return(expr, span))
+}
+
+pub fn parse_if<'src>(tok: &Tokenizer<'src>) -> ParseResult<If<'src>> {
+    let Some(keyword) = tok.consume_keyword("if") else {
+        return Err(tok.expected("`if`"));
+    };
+
+    // An `else if` chain is parsed arm by arm and nested from the last arm outwards,
+    // so long chains don't recurse.
+    let mut arms = vec![parse_if_arm(tok, keyword)?];
+    let mut otherwise = None;
+    while tok.consume_keyword("else").is_some() {
+        if let Some(keyword) = tok.consume_keyword("if") {
+            arms.push(parse_if_arm(tok, keyword)?);
+            continue;
+        }
+        let block = parse_block(tok)
+            .or_else_parse(|| Err(tok.expected("`if`")))
+            .required(tok)?;
+        otherwise = Some(Else::Block(block));
+        break;
+    }
+
+    loop {
+        let (keyword, condition, then) = arms.pop().expect("there is at least one arm");
+        let span = keyword.span.merge(otherwise.as_ref().map_or(then.span(), Else::span));
+        let stmt = If { condition, then, otherwise, span };
+        if arms.is_empty() {
+            return Ok(stmt);
+        }
+        otherwise = Some(Else::If(Box::new(stmt)));
+    }
+}
+
+/// `'label: while condition { ... }`, the label being optional.
+pub fn parse_while<'src>(tok: &Tokenizer<'src>) -> ParseResult<While<'src>> {
+    let label = parse_label(tok).optional()?;
+    let keyword = match &label {
+        Some(_) => {
+            tok.expect_with(":", "after loop label")?;
+            tok.consume_keyword("while")
+                .ok_or_else(|| tok.expected("`while`"))
+                .required(tok)?
+        }
+        None => tok.consume_keyword("while").ok_or_else(|| tok.expected("`while`"))?,
+    };
+    let condition = parse_condition(tok)
+        .required(tok)?;
+    let body = parse_block(tok)
+        .required(tok)?;
+    let span = label.as_ref().unwrap_or(&keyword).span.merge(body.span());
+    Ok(While { label, condition, body, span })
+}
+
+/// `break` or `continue`, with the label of the loop they apply to if it's on the
+/// same line.
+pub fn parse_jump<'src>(tok: &Tokenizer<'src>) -> ParseResult<Stmt<'src>> {
+    let Some(keyword) = tok.consume_keyword("break").or_else(|| tok.consume_keyword("continue")) else {
+        tok.expected("`break`");
+        return Err(tok.expected("`continue`"));
+    };
+    let label = match tok.peek_str("'") {
+        Some(quote) if quote.start.line == keyword.span.end.line => Some(parse_label(tok).required(tok)?),
+        _ => None,
+    };
+    let span = keyword.span.merge(label.as_ref().map_or(keyword.span, |label| label.span));
+    match keyword.content() {
+        "break" => Ok(Stmt::Break(label, span)),
+        _ => Ok(Stmt::Continue(label, span)),
+    }
+}
+
+/// `'name`, as a single token that includes the quote.
+pub(crate) fn parse_label<'src>(tok: &Tokenizer<'src>) -> ParseResult<Token<'src>> {
+    let Some(quote) = tok.consume("'") else {
+        return Err(tok.expected("loop label"));
+    };
+    let name = tok.consume_ident()
+        .filter(|name| name.span.start == quote.span.end)
+        .ok_or_else(|| Error::at(quote.span.end, "expected a label name after `'`".to_string()))?;
+    let span = quote.span.merge(name.span);
+    let content = tok.try_lex_for(span)?;
+    Ok(Token { span, content: Cow::Borrowed(content), ..name })
+}
+
+/// The condition of an `if` or `while`. A `(` starting it may begin a lambda, but if
+/// reading one leaves no block for the statement, as in `if (c) { :1 }`, the
+/// parentheses group the condition instead.
+fn parse_condition<'src>(tok: &Tokenizer<'src>) -> ParseResult<Expr<'src>> {
+    if tok.peek_str("(").is_none() {
+        return parse_expr(tok);
+    }
+    let condition = tok.try_with(|tok| match parse_expr(tok) {
+        Ok(condition) if tok.peek_str("{").is_some() => Ok(condition),
+        _ => Err(ParseError::NoMatch),
+    }).optional()?;
+    if let Some(condition) = condition {
+        return Ok(condition);
+    }
+    tok.nested(|tok| {
+        let (_, inner, _) = tok.delimited("(", parse_expr, ")", "to close parenthesized expression")?;
+        let operand = parse_postfix_ops(tok, inner)?;
+        parse_binary_ops(tok, operand, 0)
+    })
+}
+
+/// The condition and block after an `if` keyword.
+fn parse_if_arm<'src>(tok: &Tokenizer<'src>, keyword: Token<'src>) -> ParseResult<(Token<'src>, Expr<'src>, Block<'src>)> {
+    let condition = parse_condition(tok)
+        .required(tok)?;
+    let then = parse_block(tok)
+        .required(tok)?;
+    Ok((keyword, condition, then))
+}
+
+pub fn parse_block<'src>(tok: &Tokenizer<'src>) -> ParseResult<Block<'src>> {
+    let (left, items, right) = tok.delimited("{", |tok| tok.nested(|tok| {
+        let mut items = Vec::new();
+        while tok.has_more_tokens() && tok.peek_str("}").is_none() {
+            let start = tok.location();
+            match parse_stmt(tok) {
+                Ok(item) => items.push(item),
+                Err(error) => {
+                    let error = error.into_error().unwrap_or_else(|| tok.expectation_error());
+                    synchronize(tok, start, true);
+                    tok.report(error);
+                }
+            }
+        }
+        Ok(items)
+    }), "}", "to close block")?;
+    Ok(Block { left, items, right })
+}
+
+/// A binary operator as [`parse_expr`] sees it, listed in [`ParserConfig::binary_ops`].
+#[derive(Debug, Clone, Copy)]
+pub struct BinaryOp {
+    pub symbol: &'static str,
+    /// Binding power; higher binds tighter. Must be above zero.
+    pub precedence: u8,
+    pub assoc: Assoc,
+    /// The node made from the operator and its operands.
+    pub kind: BinaryKind,
+}
+
+/// The node a [`BinaryOp`] makes, named after its [`Expr`] variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryKind {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Lt,
+    Eq,
+    Ne,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    /// [`Expr::Binary`], which keeps the operator token.
+    Custom,
+}
+
+/// Which way a chain of operators with the same precedence groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a ** b ** c` is `a ** (b ** c)`.
+    Right,
+}
+
+/// The language's own operators, the default [`ParserConfig::binary_ops`].
+pub const BINARY_OPS: &[BinaryOp] = &[
+    BinaryOp { symbol: "||", precedence: 1, assoc: Assoc::Left, kind: BinaryKind::Or },
+    BinaryOp { symbol: "&&", precedence: 2, assoc: Assoc::Left, kind: BinaryKind::And },
+    BinaryOp { symbol: "|", precedence: 3, assoc: Assoc::Left, kind: BinaryKind::BitOr },
+    BinaryOp { symbol: "^", precedence: 4, assoc: Assoc::Left, kind: BinaryKind::BitXor },
+    BinaryOp { symbol: "&", precedence: 5, assoc: Assoc::Left, kind: BinaryKind::BitAnd },
+    BinaryOp { symbol: "<", precedence: 6, assoc: Assoc::Left, kind: BinaryKind::Lt },
+    BinaryOp { symbol: "==", precedence: 6, assoc: Assoc::Left, kind: BinaryKind::Eq },
+    BinaryOp { symbol: "!=", precedence: 6, assoc: Assoc::Left, kind: BinaryKind::Ne },
+    BinaryOp { symbol: "<=", precedence: 6, assoc: Assoc::Left, kind: BinaryKind::Le },
+    BinaryOp { symbol: ">", precedence: 6, assoc: Assoc::Left, kind: BinaryKind::Gt },
+    BinaryOp { symbol: ">=", precedence: 6, assoc: Assoc::Left, kind: BinaryKind::Ge },
+    BinaryOp { symbol: "<<", precedence: 7, assoc: Assoc::Left, kind: BinaryKind::Shl },
+    BinaryOp { symbol: ">>", precedence: 7, assoc: Assoc::Left, kind: BinaryKind::Shr },
+    BinaryOp { symbol: "+", precedence: 8, assoc: Assoc::Left, kind: BinaryKind::Add },
+    BinaryOp { symbol: "-", precedence: 8, assoc: Assoc::Left, kind: BinaryKind::Sub },
+    BinaryOp { symbol: "*", precedence: 9, assoc: Assoc::Left, kind: BinaryKind::Mul },
+    BinaryOp { symbol: "/", precedence: 9, assoc: Assoc::Left, kind: BinaryKind::Div },
+    BinaryOp { symbol: "%", precedence: 9, assoc: Assoc::Left, kind: BinaryKind::Rem },
+];
+
+/// Settings of the language's parsers that aren't lexical, attached to a tokenizer
+/// with [`Tokenizer::with_extension`]. Parsing with a tokenizer that has none uses
+/// the defaults.
+#[derive(Debug, Clone)]
+pub struct ParserConfig {
+    /// The binary operators [`parse_expr`] accepts.
+    pub binary_ops: Vec<BinaryOp>,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self { binary_ops: BINARY_OPS.to_vec() }
+    }
+}
+
+impl BinaryKind {
+    /// The node for `lhs op rhs`.
+    fn build<'src>(self, op: Token<'src>, lhs: Expr<'src>, rhs: Expr<'src>) -> Expr<'src> {
+        let operands = Box::new((lhs, rhs));
+        match self {
+            Self::Or => Expr::Or(operands),
+            Self::And => Expr::And(operands),
+            Self::BitOr => Expr::BitOr(operands),
+            Self::BitXor => Expr::BitXor(operands),
+            Self::BitAnd => Expr::BitAnd(operands),
+            Self::Lt => Expr::Lt(operands),
+            Self::Eq => Expr::Eq(operands),
+            Self::Ne => Expr::Ne(operands),
+            Self::Le => Expr::Le(operands),
+            Self::Gt => Expr::Gt(operands),
+            Self::Ge => Expr::Ge(operands),
+            Self::Shl => Expr::Shl(operands),
+            Self::Shr => Expr::Shr(operands),
+            Self::Add => Expr::Add(operands),
+            Self::Sub => Expr::Sub(operands),
+            Self::Mul => Expr::Mul(operands),
+            Self::Div => Expr::Div(operands),
+            Self::Rem => Expr::Rem(operands),
+            Self::Custom => Expr::Binary(op, operands),
+        }
+    }
+}
+
+impl BinaryOp {
+    /// The binding power the right operand is parsed with: a right-associative
+    /// operator lets its own precedence level continue on the right.
+    pub(crate) fn rhs_bp(&self) -> u8 {
+        match self.assoc {
+            Assoc::Left => self.precedence,
+            Assoc::Right => self.precedence - 1,
+        }
+    }
+}
+
+/// The operator in [`ParserConfig::binary_ops`] starting at the cursor, if it binds
+/// tighter than `min_bp`. The longest symbol wins, so `**` is never read as `*`, nor
+/// `||` as `|` when only `|` binds tightly enough.
+pub(crate) fn peek_binary_op(tok: &Tokenizer, min_bp: u8) -> Option<BinaryOp> {
+    let ops = tok.extension::<ParserConfig>().map_or(BINARY_OPS, |config| &config.binary_ops);
+    ops.iter()
+        .filter(|op| tok.peek_str(op.symbol).is_some())
+        .max_by_key(|op| op.symbol.len())
+        .filter(|op| op.precedence > min_bp)
+        .copied()
+}
+
+pub fn parse_expr<'src>(tok: &Tokenizer<'src>) -> ParseResult<Expr<'src>> {
+    tok.nested(|tok| parse_expr_bp(tok, 0))
+}
+
+/// Precedence climbing: parses operands joined by operators binding tighter than
+/// `min_bp`.
+fn parse_expr_bp<'src>(tok: &Tokenizer<'src>, min_bp: u8) -> ParseResult<Expr<'src>> {
+    let lhs = parse_expr_unary(tok)?;
+    parse_binary_ops(tok, lhs, min_bp)
+}
+
+/// The operators binding tighter than `min_bp` that follow an operand already parsed.
+fn parse_binary_ops<'src>(tok: &Tokenizer<'src>, mut lhs: Expr<'src>, min_bp: u8) -> ParseResult<Expr<'src>> {
+    while let Some(op) = peek_binary_op(tok, min_bp) {
+        let token = tok.consume(op.symbol).expect("the operator was just peeked");
+
+        let rhs = tok.nested(|tok| parse_expr_bp(tok, op.rhs_bp()))
+            .required(tok)?;
+        lhs = op.kind.build(token, lhs, rhs);
+    }
+    Ok(lhs)
+}
+
+/// Prefix operators, which bind tighter than any binary operator.
+pub fn parse_expr_unary<'src>(tok: &Tokenizer<'src>) -> ParseResult<Expr<'src>> {
+    let Some(op) = tok.consume_any(&["-", "!", "*", "&"]) else {
+        return parse_expr_postfix(tok);
+    };
+    let kind = match op.content() {
+        "-" => UnaryOp::Neg,
+        "!" => UnaryOp::Not,
+        "*" => UnaryOp::Deref,
+        _ => UnaryOp::Addr,
+    };
+    let operand = tok.nested(parse_expr_unary)
+        .required(tok)?;
+    let span = op.span.merge(operand.span());
+    Ok(Expr::Unary(kind, Box::new(operand), span))
+}
+
+/// A primary followed by any number of call argument lists and indices.
+pub fn parse_expr_postfix<'src>(tok: &Tokenizer<'src>) -> ParseResult<Expr<'src>> {
+    let out = tok.labelled("expression", parse_expr_primary)?;
+    parse_postfix_ops(tok, out)
+}
+
+/// The call argument lists and indices that follow an operand already parsed.
+fn parse_postfix_ops<'src>(tok: &Tokenizer<'src>, mut out: Expr<'src>) -> ParseResult<Expr<'src>> {
+    loop {
+        if tok.peek_str("(").is_some() {
+            let (_, args, right) = tok.separated_list("(", ",", ")", "to close argument list", parse_expr)?;
+            let span = out.span().merge(right.span);
+            out = Expr::Call(Box::new(out), args, span);
+        } else if tok.peek_str("[").is_some() {
+            let (_, index, right) = tok.delimited("[", parse_expr, "]", "to close index")?;
+            let span = out.span().merge(right.span);
+            out = Expr::Index(Box::new((out, index)), span);
+        } else {
+            return Ok(out);
+        }
+    }
+}
+
+pub fn parse_expr_primary<'src>(tok: &Tokenizer<'src>) -> ParseResult<Expr<'src>> {
+    if let Some(num) = tok.consume_number()? {
+        Ok(Expr::Num(NumLit::new(num)?))
+    } else if let Some(token) = tok.consume_keyword("self") {
+        Ok(Expr::SelfRef(token))
+    } else if let Some(stmt) = tok.try_with(parse_if).optional()? {
+        Ok(Expr::If(Box::new(stmt)))
+    } else if let Some(lambda) = tok.try_with(parse_lambda).optional()? {
+        Ok(lambda)
+    } else if tok.peek_str("(").is_some() {
+        let (_, inner, _) = tok.delimited("(", parse_expr, ")", "to close parenthesized expression")?;
+        Ok(inner)
+    } else if tok.peek_str("[").is_some() {
+        let (left, items, right) = tok.separated_list("[", ",", "]", "to close array literal", parse_expr)?;
+        Ok(Expr::ArrayLit(items, left.span.merge(right.span)))
+    } else if let Some(token) = tok.consume_keyword("true").or_else(|| tok.consume_keyword("false")) {
+        Ok(Expr::Bool(token))
+    } else if let Some(name) = tok.consume_ident() {
+        Ok(Expr::Name(name))
+    } else {
+        parse_expr_strings(tok)
+    }
+}
+
+/// `(args) ret { ... }`. Doesn't match unless a function type and a `{` follow, so a
+/// `(` starting an operand is otherwise left to group an expression: `(a + b) * c`.
+/// Conditions of `if` and `while` get another reading, see [`parse_condition`].
+pub fn parse_lambda<'src>(tok: &Tokenizer<'src>) -> ParseResult<Expr<'src>> {
+    if tok.peek_str("(").is_none() {
+        return Err(ParseError::NoMatch);
+    }
+    let ty = tok.try_with(|tok| match parse_fn_type(tok) {
+        Ok(ty) if tok.peek_str("{").is_some() => Ok(ty),
+        _ => Err(ParseError::NoMatch),
+    })?;
+    let body = parse_block(tok)
+        .required(tok)?;
+    Ok(Expr::Lambda { ty: Box::new(ty), body })
+}
+
+/// Parses a run of adjacent string literals, joined into one as in C so long strings
+/// can be split across lines: `"foo" "bar"` is `"foobar"`. If any of them has `${}`
+/// holes, the result is an interpolated string spanning them all.
+pub fn parse_expr_strings<'src>(tok: &Tokenizer<'src>) -> ParseResult<Expr<'src>> {
+    let mut parts = Vec::new();
+    let mut span: Option<Span> = None;
+    let mut interpolated = false;
+    loop {
+        let (more, more_span) = if let Some((more, more_span)) = tok.try_with(parse_expr_interp).optional()? {
+            interpolated = true;
+            (more, more_span)
+        } else if let Some(string) = tok.try_with(parse_expr_str).optional()? {
+            let string_span = string.span;
+            (vec![StrPart::Lit(string)], string_span)
+        } else {
+            break;
+        };
+        span = Some(span.map_or(more_span, |span| span.merge(more_span)));
+        for part in more {
+            match (parts.last_mut(), part) {
+                (Some(StrPart::Lit(lit)), StrPart::Lit(next)) => join_literals(lit, next),
+                (_, part) => parts.push(part),
+            }
+        }
+    }
+    let Some(span) = span else {
+        return Err(ParseError::NoMatch);
+    };
+    if interpolated {
+        return Ok(Expr::Interp(parts, span));
+    }
+    let Some(StrPart::Lit(string)) = parts.pop() else {
+        unreachable!("plain strings join into a single literal");
+    };
+    Ok(Expr::Str(string))
+}
+
+/// Appends `next` to the literal `lit`, which then spans both.
+pub(crate) fn join_literals<'src>(lit: &mut Token<'src>, next: Token<'src>) {
+    lit.content.to_mut().push_str(&next.content);
+    lit.span = lit.span.merge(next.span);
+    lit.trailing = next.trailing;
+}
+
+impl<'src> NumLit<'src> {
+    /// Works out the value of a lexed number, checking that it fits the type its
+    /// suffix names. Unsuffixed integers are `u64`s and unsuffixed floats `f64`s.
+    pub fn new(number: Number<'src>) -> Result<Self, Error> {
+        let Number { token, kind, radix, value, suffix } = number;
+        let span = token.span;
+        let out_of_range = |ty: &str| Error::new(span, format!("literal out of range for `{ty}`"));
+        let float = kind == NumberKind::Float || matches!(suffix, Some("f32" | "f64"));
+        let value = if float {
+            if radix != 10 {
+                return Err(Error::new(span, format!("a base {radix} literal can't be a float")));
+            }
+            let ty = match suffix {
+                None | Some("f64") => "f64",
+                Some("f32") => "f32",
+                Some(other) => return Err(Error::new(span, format!("invalid suffix `{other}` for a float literal"))),
+            };
+            let value = value.parse::<f64>().map_err(|_| out_of_range(ty))?;
+            if !value.is_finite() || (ty == "f32" && !(value as f32).is_finite()) {
+                return Err(out_of_range(ty));
+            }
+            NumValue::Float(value)
+        } else {
+            let ty = suffix.unwrap_or("u64");
+            let max = match ty {
+                "u8" => u8::MAX as u64,
+                "u16" => u16::MAX as u64,
+                "u32" => u32::MAX as u64,
+                "u64" | "usize" => u64::MAX,
+                "i8" => i8::MAX as u64,
+                "i16" => i16::MAX as u64,
+                "i32" => i32::MAX as u64,
+                "i64" | "isize" => i64::MAX as u64,
+                other => return Err(Error::new(span, format!("invalid suffix `{other}` for an integer literal"))),
+            };
+            match u64::from_str_radix(&value, radix) {
+                Ok(value) if value <= max => NumValue::Int(value),
+                _ => return Err(out_of_range(ty)),
+            }
+        };
+        Ok(NumLit { token, value, radix, suffix: suffix.map(Cow::Borrowed) })
+    }
+}
+
+pub fn parse_expr_str<'src>(tok: &Tokenizer<'src>) -> ParseResult<Token<'src>> {
+    if let Some(raw) = tok.consume_raw_string()? {
+        return Ok(raw);
+    }
+    if let Some(text) = tok.consume_multiline_string()? {
+        return Ok(text);
+    }
+    if tok.peek_str("\"").is_none() {
+        return Err(ParseError::NoMatch);
+    };
+    let mut cursor = tok.cursor().chars();
+    let start = tok.location();
+    let mut end = start;
+    
+    cursor.next(); // skip the quote
+    tok.adv(&mut end, '"');
+    let content_start = end;
+    loop {
+        match cursor.next() {
+            None => {
+                return Err(Error::at(end, format!("Expected {:?}", '"')).into());
+            }
+            Some('\r' | '\n') => {
+                return Err(Error::at(end, "unterminated string".to_string()).into());
+            }
+            Some('"') => break,
+            Some('\\') => {
+                tok.adv(&mut end, '\\');
+                if let Some(c) = cursor.next() {
+                    tok.adv(&mut end, c);
+                }
+            }
+            Some(c) => tok.adv(&mut end, c),
+        }
+    }
+
+    let content = unescape(tok, Span { start: content_start, end })?;
+    tok.adv(&mut end, '"');
+
+    let span = Span { start, end };
+    let raw = tok.try_lex_for(span)?;
+    let lexeme = tok.consume(raw)
+        .ok_or_else(|| Error::at(start, "string lexeme does not match the source".to_string()))?;
+
+    Ok(Token {
+        kind: TokenKind::String,
+        content,
+        ..lexeme
+    })
+}
+
+/// Parses a string containing `${expr}` holes, leaving plain strings to `parse_expr_str`.
+/// Returns the parts along with the span of the whole literal.
+pub fn parse_expr_interp<'src>(tok: &Tokenizer<'src>) -> ParseResult<(Vec<StrPart<'src>>, Span)> {
+    let Some(string) = tok.consume_interpolated_string()? else {
+        return Err(ParseError::NoMatch);
+    };
+
+    let parts = string.parts.into_iter()
+        .map(|part| match part {
+            StringPart::Literal(lit) => {
+                let content = unescape(tok, lit.span)?;
+                Ok(StrPart::Lit(Token { content, ..lit }))
+            }
+            StringPart::Expr(span) => {
+                let inner = tok.sub_tokenizer(span);
+                let expr = parse_expr(&inner)
+                    .required(&inner)?;
+                if inner.has_more_tokens() {
+                    return Err(Error::at(inner.location(), "expected `}` to close `${`".to_string()).into());
+                }
+                Ok(StrPart::Expr(expr))
+            }
+        })
+        .collect::<ParseResult<_>>()?;
+    Ok((parts, string.span))
+}
+
+/// Processes the escape sequences in the string contents covered by `span`,
+/// borrowing from the source when there are none.
+pub(crate) fn unescape<'src>(tok: &Tokenizer<'src>, span: Span) -> Result<Cow<'src, str>, Error> {
+    let raw = tok.try_lex_for(span)?;
+    if !raw.contains('\\') {
+        return Ok(Cow::Borrowed(raw));
+    }
+
+    /// Reads `count` digits of `radix`, optionally wrapped in braces (`\x41` or `\x{41}`).
+    fn radix_escape(
+        count: usize, radix: u32, 
+        cursor: &mut Peekable<Chars>, location: &mut Location,
+        tok: &Tokenizer, escape: Location,
+    ) -> Result<char, Error> {
+        let braced = cursor.next_if_eq(&'{').is_some();
+        if braced {
+            tok.adv(location, '{');
+        }
+
+        let start = *location;
+        for _ in 0..count {
+            if let Some(c) = cursor.next_if(|c| c.is_digit(radix)) {
+                tok.adv(location, c);
+            } else {
+                return Err(Error::at(*location, format!("Expected {}-radix digit", radix)));
+            }
+        }
+        let end = *location;
+        if braced {
+            if cursor.next() != Some('}') {
+                return Err(Error::at(*location, "Expected '}'".to_string()));
+            }
+            tok.adv(location, '}');
+        }
+
+        let content = tok.try_lex_for(Span { start, end })?;
+        let value = u32::from_str_radix(content, radix).unwrap();
+        let span = Span { start: escape, end: *location };
+        char::from_u32(value).ok_or_else(|| {
+            Error::new(span, format!("invalid character escape `{}`", tok.lex_for(span).unwrap_or("")))
+        })
+    }
+
+    let mut out = String::with_capacity(raw.len());
+    let mut location = span.start;
+    let mut cursor = raw.chars().peekable();
+    while let Some(c) = cursor.next() {
+        let escape = location;
+        tok.adv(&mut location, c);
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        let Some(c) = cursor.next() else {
+            break;
+        };
+        tok.adv(&mut location, c);
+        out.push(match c {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '\\' => '\\',
+            '"' => '"',
+            '\'' => '\'',
+            '$' => '$',
+            'u' => radix_escape(4, 16, &mut cursor, &mut location, tok, escape)?,
+            'x' => radix_escape(2, 16, &mut cursor, &mut location, tok, escape)?,
+            '0'..='7' => {
+                let mut value = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    let Some(c) = cursor.next_if(|c| c.is_digit(8)) else {
+                        break;
+                    };
+                    tok.adv(&mut location, c);
+                    value = value * 8 + c.to_digit(8).unwrap();
+                }
+                char::from_u32(value).unwrap()
+            }
+            _ => {
+                let span = Span { start: escape, end: location };
+                return Err(Error::new(span, format!("unknown escape sequence `{}`", &tok.source()[span])));
+            }
+        });
+    }
+    Ok(Cow::Owned(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Tokenizer, Error};
+    use super::*;
+
+    fn parse<'a, T>(src: &'a str, f: impl Fn(&Tokenizer<'a>) -> ParseResult<T>) -> Option<T> {
+        match f(&tokenizer(src)) {
+            Err(ParseError::Invalid(e)) => {
+                eprintln!("error at {}", e.location());
+                eprintln!(" :: {}", e.message);
+                panic!("error occurred!")
+            }
+            Err(ParseError::NoMatch) => None,
+            Ok(item) => Some(item),
+        }
+    }
+
+    impl<'a> Type<'a> {
+        fn assert_named(&self) -> &str {
+            match self {
+                Self::Name(n) => n.content(),
+                _ => panic!("type is not named!"),
+            }
+        }
+
+        fn assert_pointer(&self) -> &Type<'a> {
+            match self {
+                Self::Ptr(inner, _) => inner.as_ref(),
+                _ => panic!("type is not a pointer!"),
+            }
+        }
+
+        fn assert_array(&self) -> (&Type<'a>, Option<&Expr<'a>>) {
+            match self {
+                Self::Array(elem, len, _) => (elem.as_ref(), len.as_ref()),
+                _ => panic!("type is not an array!"),
+            }
+        }
+
+        fn assert_generic(&self) -> (&str, &[Type<'a>]) {
+            match self {
+                Self::Generic(name, args, _) => (name.content(), args),
+                _ => panic!("type is not generic!"),
+            }
+        }
+
+        fn assert_func(&self) -> &FnType<'a> {
+            match self {
+                Self::Func(func) => func.as_ref(),
+                _ => panic!("type is not a function!"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_types() {
+        let ty = parse("int", parse_type).unwrap();
+        assert_eq!(ty.assert_named(), "int");
+
+        let ty = parse("int**", parse_type).unwrap();
+        let ty = ty.assert_pointer();
+        let ty = ty.assert_pointer();
+        assert_eq!(ty.assert_named(), "int");
+
+        let ty = parse("(int,char**)int", parse_type).unwrap();
+        let func = ty.assert_func();
+        assert_eq!(func.args.len(), 2);
+        assert_eq!(func.args[0].assert_named(), "int");
+        assert_eq!(func.args[1].assert_pointer().assert_pointer().assert_named(), "char");
+        assert_eq!(func.ret.as_ref().unwrap().assert_named(), "int");
+
+
+        let ty = parse("((int,void*)bool,void*)", parse_type).unwrap();
+        let func = ty.assert_func();
+        assert_eq!(func.args.len(), 2);
+        let inner = func.args[0].assert_func();
+        assert_eq!(inner.args.len(), 2);
+        assert_eq!(inner.args[0].assert_named(), "int");
+        assert_eq!(inner.args[1].assert_pointer().assert_named(), "void");
+        assert_eq!(inner.ret.as_ref().unwrap().assert_named(), "bool");
+        assert_eq!(func.args[1].assert_pointer().assert_named(), "void");
+        assert!(func.ret.is_none())
+    }
+
+    #[test]
+    fn test_optional_rewinds() {
+        let tok = Tokenizer::new("(int, 0");
+        let ty = tok.try_with(parse_fn_type);
+        assert!(matches!(ty, Err(ParseError::Invalid(_))));
+
+        let tok = Tokenizer::new("int");
+        let res: ParseResult<()> = tok.try_with(|tok| {
+            tok.consume("int");
+            Err(ParseError::NoMatch)
+        });
+        assert!(matches!(res, Err(ParseError::NoMatch)));
+        assert_eq!(tok.location().index, 0);
+    }
+
+    #[test]
+    fn test_keyword_names() {
+        assert!(parse("if", parse_type).is_none());
+        assert_eq!(parse("iffy", parse_type).unwrap().assert_named(), "iffy");
+    }
+
+    #[test]
+    fn test_numbers() {
+        let num = |src| match parse(src, parse_expr_primary) {
+            Some(Expr::Num(lit)) => lit,
+            _ => panic!("expected a number"),
+        };
+        let hex = num("0xFF_u8");
+        assert_eq!((hex.value, hex.radix, hex.suffix.as_deref()), (NumValue::Int(255), 16, Some("u8")));
+        assert_eq!(num("2.5e1").value, NumValue::Float(25.0));
+        assert_eq!(num("1f32").value, NumValue::Float(1.0));
+        assert_eq!(num("18446744073709551615").value, NumValue::Int(u64::MAX));
+
+        let error = |src| parse_expr_primary(&tokenizer(src)).err().and_then(ParseError::into_error).unwrap();
+        let overflow = error("256u8");
+        assert_eq!(overflow.message, "literal out of range for `u8`");
+        assert_eq!((overflow.span.start.index, overflow.span.end.index), (0, 5));
+        assert_eq!(error("18446744073709551616").message, "literal out of range for `u64`");
+        assert_eq!(error("1e999").message, "literal out of range for `f64`");
+        assert_eq!(error("1.5u8").message, "invalid suffix `u8` for a float literal");
+        assert_eq!(error("7px").message, "invalid suffix `px` for an integer literal");
+    }
+
+    #[test]
+    fn test_strings() {
+        let src = r#""""#;
+        let content = parse(src, parse_expr_str).unwrap();
+        assert_eq!(content.content(), "");
+
+        let src = r#""Hello World!""#;
+        let content = parse(src, parse_expr_str).unwrap();
+        assert_eq!(content.content(), "Hello World!");
+
+        let src = r#""tab\there \u{00e9}""#;
+        let content = parse(src, parse_expr_str).unwrap();
+        assert_eq!(content.content(), "tab\there \u{00e9}");
+
+        let src = r#"r"\d+\.\d*""#;
+        let content = parse(src, parse_expr_str).unwrap();
+        assert_eq!(content.content(), r"\d+\.\d*");
+
+        let src = "\"foo\" \"bar\"\n    r\"\\n\"";
+        let Some(Expr::Str(joined)) = parse(src, parse_expr_primary) else {
+            panic!("expected adjacent strings to join");
+        };
+        assert_eq!(joined.content(), "foobar\\n");
+        assert_eq!(&src[joined.span], src);
+
+        let src = r#""a" "${1}" "b""#;
+        let Some(Expr::Interp(parts, span)) = parse(src, parse_expr_primary) else {
+            panic!("expected an interpolated string");
+        };
+        assert_eq!(&src[span], src);
+        assert!(matches!(parts.as_slice(), [StrPart::Lit(a), StrPart::Expr(_), StrPart::Lit(b)] if a.content() == "a" && b.content() == "b"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        let src = r#""\0\'\101\x41\x{42}\u{00e9}\u00e8\7""#;
+        let content = parse(src, parse_expr_str).unwrap();
+        assert_eq!(content.content(), "\0'AAB\u{e9}\u{e8}\u{7}");
+
+        let src = r#""ok \q""#;
+        let Err(ParseError::Invalid(e)) = parse_expr_str(&tokenizer(src)) else {
+            panic!("expected an error");
+        };
+        assert_eq!(e.location().column, 4);
+        assert_eq!(e.message, "unknown escape sequence `\\q`");
+
+        assert!(parse_expr_str(&tokenizer(r#""\u{d800}""#)).is_err());
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let src = r#""a=${1 + 2}, \${b} ${"}"}""#;
+        let Expr::Interp(parts, _) = parse(src, parse_expr_primary).unwrap() else {
+            panic!("expected an interpolated string");
+        };
+        assert_eq!(parts.len(), 4);
+        assert!(matches!(&parts[0], StrPart::Lit(t) if t.content() == "a="));
+        assert!(matches!(&parts[1], StrPart::Expr(Expr::Add(_))));
+        assert!(matches!(&parts[2], StrPart::Lit(t) if t.content() == ", ${b} "));
+        assert!(matches!(&parts[3], StrPart::Expr(Expr::Str(t)) if t.content() == "}"));
+
+        assert!(matches!(parse(r#""plain""#, parse_expr_primary), Some(Expr::Str(_))));
+    }
+
+    #[test]
+    fn test_block_nesting() {
+        let block = parse("{ { } {} }", parse_block).unwrap();
+        assert_eq!(block.items.len(), 2);
+
+        let tok = Tokenizer::new_with_config("{ { { } } }", crate::TokenizerConfig {
+            max_nesting: Some(2),
+            ..crate::TokenizerConfig::default()
+        });
+        // The too-deep block is reported and skipped like any other bad statement.
+        assert!(parse_block(&tok).is_ok());
+        let errors = tok.take_diagnostics();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "nesting is deeper than the limit of 2");
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        // Debug builds spend far more stack per level than release ones; this is what
+        // the limit is sized for.
+        let main_thread_stack = 8 << 20;
+        std::thread::Builder::new().stack_size(main_thread_stack).spawn(|| {
+            let limit = format!("nesting is deeper than the limit of {MAX_NESTING}");
+            let deep = [
+                format!("{}1", "-".repeat(10_000)),
+                format!("{}1", "self(".repeat(10_000)),
+            ];
+            for src in &deep {
+                let error = parse_expr(&tokenizer(src)).err().and_then(ParseError::into_error).unwrap();
+                assert_eq!(error.message, limit);
+            }
+            let error = parse_type(&tokenizer(&"(".repeat(10_000))).err().and_then(ParseError::into_error).unwrap();
+            assert_eq!(error.message, limit);
+            let error = parse_expr(&tokenizer(&format!("{}1", "(".repeat(10_000)))).err().and_then(ParseError::into_error).unwrap();
+            assert_eq!(error.message, limit);
+            let error = parse_stmt(&tokenizer(&format!("{}x = 1", "*".repeat(10_000)))).err().and_then(ParseError::into_error).unwrap();
+            assert_eq!(error.message, limit);
+
+            // Blocks recover from the error, so it shows up as a diagnostic.
+            let src = format!("{}1{}", "if 1 { :".repeat(10_000), " }".repeat(10_000));
+            let tok = tokenizer(&src);
+            assert!(parse_expr(&tok).is_ok());
+            assert_eq!(tok.take_diagnostics()[0].message, limit);
+
+            assert!(parse(&format!("{}1", "-".repeat(100)), parse_expr).is_some());
+        }).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_expr() {
+        let Expr::Lt(cmp) = parse("1 + 2 - 3 < 4", parse_expr).unwrap() else {
+            panic!("`<` should bind loosest");
+        };
+        let Expr::Sub(sub) = &cmp.0 else {
+            panic!("`-` should be left-associative");
+        };
+        assert!(matches!(&sub.0, Expr::Add(_)));
+        assert!(matches!(&cmp.1, Expr::Num(n) if n.token.content() == "4"));
+
+        let Some(Expr::Lt(cmp)) = parse("argc < limit(1)(argv)", parse_expr) else {
+            panic!("expected a comparison of names");
+        };
+        assert!(matches!(&cmp.0, Expr::Name(name) if name.content() == "argc"));
+        let Expr::Call(callee, args, _) = &cmp.1 else {
+            panic!("expected a call");
+        };
+        assert!(matches!(callee.as_ref(), Expr::Call(inner, _, _) if matches!(inner.as_ref(), Expr::Name(_))));
+        assert!(matches!(args.as_slice(), [Expr::Name(name)] if name.content() == "argv"));
+        assert!(parse("else", parse_expr).is_none());
+
+        let mut config = ParserConfig {
+            binary_ops: [BINARY_OPS, &[BinaryOp {
+                symbol: "**",
+                precedence: 10,
+                assoc: Assoc::Right,
+                kind: BinaryKind::Custom,
+            }]].concat(),
+        };
+        let expr = parse_expr(&tokenizer("2 ** 3 ** 4 * 5 - 6 - 7").with_extension(config.clone()))
+            .unwrap_or_else(|_| panic!("expected an expression"));
+        assert_eq!(expr.to_sexpr(), "(sub (sub (mul (** 2 (** 3 4)) 5) 6) 7)");
+        // Interpolation holes are parsed with the same operators.
+        let expr = parse_expr(&tokenizer("\"${2 ** 3}\"").with_extension(config.clone()));
+        assert!(matches!(expr, Ok(Expr::Interp(parts, _)) if matches!(parts.as_slice(), [StrPart::Expr(Expr::Binary(..))])));
+        // An operator of the language's own can be rebound to another node.
+        config.binary_ops.iter_mut().find(|op| op.symbol == "-").unwrap().kind = BinaryKind::Custom;
+        let expr = parse_expr(&tokenizer("a - b").with_extension(config));
+        assert!(matches!(expr, Ok(Expr::Binary(op, _)) if op.content() == "-"));
+
+        let expr = parse("argc != 1 == a <= b < c >= d > e", parse_expr).unwrap();
+        assert_eq!(expr.to_sexpr(), "(gt (ge (lt (le (eq (ne argc 1) a) b) c) d) e)");
+        assert!(matches!(parse("1 + 2 == 3", parse_expr), Some(Expr::Eq(_))));
+        let expr = parse("a || b && c < d || e && f", parse_expr).unwrap();
+        assert_eq!(expr.to_sexpr(), "(or (or a (and b (lt c d))) (and e f))");
+        let expr = parse("a | b ^ c & d == e << 1 + 2", parse_expr).unwrap();
+        assert_eq!(expr.to_sexpr(), "(bitor a (bitxor b (bitand c (eq d (shl e (add 1 2))))))");
+        let expr = parse("a&&b & c || x >> 1 >= 2 << y", parse_expr).unwrap();
+        assert_eq!(expr.to_sexpr(), "(or (and a (bitand b c)) (ge (shr x 1) (shl 2 y)))");
+
+        let tok = tokenizer("1 +");
+        let error = parse_expr(&tok).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected expression, found end of input");
+
+        let block = parse("{ :1 + 2 if 1 < 2 { :0 } }", parse_block).unwrap();
+        assert!(matches!(&block.items[0], Stmt::Return(Expr::Add(_), _)));
+        assert!(matches!(&block.items[1], Stmt::If(If { condition: Expr::Lt(_), .. })));
+    }
+
+    #[test]
+    fn test_multiplicative() {
+        let Expr::Add(add) = parse("1 + 2 * 3 % 4", parse_expr).unwrap() else {
+            panic!("`*` and `%` should bind tighter than `+`");
+        };
+        let Expr::Rem(rem) = &add.1 else {
+            panic!("`*` and `%` should be left-associative");
+        };
+        assert!(matches!(&rem.0, Expr::Mul(_)));
+        assert!(matches!(parse("8 / 2 // halve", parse_expr), Some(Expr::Div(_))));
+    }
+
+    #[test]
+    fn test_unary() {
+        let Expr::Sub(sub) = parse("-1 - -*&2", parse_expr).unwrap() else {
+            panic!("unary operators should bind tighter than `-`");
+        };
+        assert!(matches!(&sub.0, Expr::Unary(UnaryOp::Neg, n, _) if matches!(**n, Expr::Num(_))));
+        let Expr::Unary(UnaryOp::Neg, inner, _) = &sub.1 else {
+            panic!("expected negation");
+        };
+        let Expr::Unary(UnaryOp::Deref, inner, _) = inner.as_ref() else {
+            panic!("expected dereference");
+        };
+        assert!(matches!(inner.as_ref(), Expr::Unary(UnaryOp::Addr, ..)));
+        assert!(matches!(parse("!1 * 2", parse_expr), Some(Expr::Mul(_))));
+
+        let error = parse_expr(&tokenizer("-")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected expression, found end of input");
+    }
+
+    #[test]
+    fn test_assign() {
+        let block = parse("{ *p = 3 x = 1 + 2 }", parse_block).unwrap();
+        assert!(matches!(&block.items[0], Stmt::Assign { target: Expr::Unary(UnaryOp::Deref, ..), .. }));
+        assert!(matches!(&block.items[1], Stmt::Assign { target: Expr::Name(n), value: Expr::Add(_) } if n.content() == "x"));
+
+        let stmt = parse("a[i] = v", parse_expr_stmt).unwrap();
+        assert_eq!(stmt.to_sexpr(), "(assign (index a i) v)");
+        assert!(matches!(parse("*p[0] = v", parse_expr_stmt), Some(Stmt::Assign { target: Expr::Unary(UnaryOp::Deref, ..), .. })));
+
+        let error = parse_expr_stmt(&tokenizer("-1 = 2")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "cannot assign to this expression");
+        assert!(matches!(parse_expr_stmt(&tokenizer("x == 2")), Err(ParseError::NoMatch)));
+
+        // A call on its own is a statement too.
+        let block = parse("{ printf(\"%d\", xs[0])\n*p = 1 }", parse_block).unwrap();
+        assert_eq!(block.items.iter().map(Stmt::to_sexpr).collect::<Vec<_>>(), ["(call printf \"%d\" (index xs 0))", "(assign (deref p) 1)"]);
+        assert!(matches!(parse_expr_stmt(&tokenizer("x + 1")), Err(ParseError::NoMatch)));
+    }
+
+    #[test]
+    fn test_macro() {
+        let block = parse("{\n    args! argc, argv\n    x = 1\n}", parse_block).unwrap();
+        let Stmt::Macro(mac) = &block.items[0] else {
+            panic!("expected a macro statement");
+        };
+        assert_eq!(mac.name.content(), "args");
+        assert_eq!(mac.args.iter().map(|t| t.content()).collect::<Vec<_>>(), ["argc", "argv"]);
+        assert!(matches!(block.items[1], Stmt::Assign { .. }));
+
+        let mac = parse("trace! { }", parse_macro).unwrap();
+        assert!(mac.args.is_empty());
+        assert!(parse("x = 1", parse_macro).is_none());
+    }
+
+    #[test]
+    fn test_let() {
+        let block = parse("{ let x int = 1 var y = 2 let p char* }", parse_block).unwrap();
+        assert!(matches!(&block.items[0], Stmt::Let { name, ty: Some(_), init: Some(_), .. } if name.content() == "x"));
+        assert!(matches!(&block.items[1], Stmt::Let { ty: None, init: Some(Expr::Num(_)), .. }));
+        let Stmt::Let { ty: Some(ty), init: None, .. } = &block.items[2] else {
+            panic!("expected an uninitialized declaration");
+        };
+        assert_eq!(ty.assert_pointer().assert_named(), "char");
+
+        let error = parse_let(&tokenizer("let if = 1")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected variable name, found `if`");
+        let error = parse_let(&tokenizer("let x }")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected type or `=`, found `}`");
+    }
+
+    #[test]
+    fn test_self_ref() {
+        let Expr::Add(add) = parse("self(3 - 1) + self(3 - 2, 0)", parse_expr).unwrap() else {
+            panic!("expected a sum of calls");
+        };
+        let Expr::Call(callee, args, _) = &add.0 else {
+            panic!("expected a call");
+        };
+        assert!(matches!(callee.as_ref(), Expr::SelfRef(t) if t.content() == "self"));
+        assert!(matches!(args.as_slice(), [Expr::Sub(_)]));
+        assert!(matches!(&add.1, Expr::Call(_, args, _) if args.len() == 2));
+
+        let error = parse_expr(&tokenizer("self(1 2")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected `)` to close argument list");
+    }
+
+    #[test]
+    fn test_bool() {
+        assert!(matches!(parse("true", parse_expr), Some(Expr::Bool(t)) if t.content() == "true"));
+        assert!(matches!(parse("!false", parse_expr), Some(Expr::Unary(UnaryOp::Not, b, _)) if matches!(*b, Expr::Bool(_))));
+
+        let Stmt::Let { ty: Some(ty), init: Some(Expr::Bool(_)), .. } = parse("let done bool = false", parse_let).unwrap() else {
+            panic!("expected a bool declaration");
+        };
+        assert_eq!(ty.assert_named(), "bool");
+        assert!(parse("true", parse_type).is_none());
+    }
+
+    #[test]
+    fn test_globals() {
+        let Decl::Global { name, ty: Some(ty), init: Some(Expr::Num(_)) } = parse("limit int = 10", parse_decl).unwrap() else {
+            panic!("expected a global");
+        };
+        assert_eq!(name.content(), "limit");
+        assert_eq!(ty.assert_named(), "int");
+        assert!(matches!(parse("greeting = \"hi\"", parse_decl), Some(Decl::Global { ty: None, init: Some(Expr::Str(_)), .. })));
+        assert!(matches!(parse("buf char*", parse_decl), Some(Decl::Global { init: None, .. })));
+        assert!(matches!(parse("main () int {}", parse_decl), Some(Decl::Func(..))));
+        assert!(parse("lonely", parse_decl).is_none());
+    }
+
+    #[test]
+    fn test_type_alias() {
+        let Decl::TypeAlias { name, ty, .. } = parse("type str = char*", parse_decl).unwrap() else {
+            panic!("expected a type alias");
+        };
+        assert_eq!(name.content(), "str");
+        assert_eq!(ty.assert_pointer().assert_named(), "char");
+
+        let Decl::TypeAlias { ty, .. } = parse("type callback = (int, str) int", parse_decl).unwrap() else {
+            panic!("expected a type alias");
+        };
+        assert_eq!(ty.assert_func().args[1].assert_named(), "str");
+
+        let error = parse_decl(&tokenizer("type x int")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected `=` after type alias name");
+    }
+
+    #[test]
+    fn test_array_types() {
+        let ty = parse("int[10]", parse_type).unwrap();
+        let (elem, len) = ty.assert_array();
+        assert_eq!(elem.assert_named(), "int");
+        assert!(matches!(len, Some(Expr::Num(n)) if n.token.content() == "10"));
+
+        let ty = parse("char[]", parse_type).unwrap();
+        assert!(ty.assert_array().1.is_none());
+
+        let ty = parse("int[4]*", parse_type).unwrap();
+        assert_eq!(ty.assert_pointer().assert_array().0.assert_named(), "int");
+        let ty = parse("int*[4]", parse_type).unwrap();
+        assert_eq!(ty.assert_array().0.assert_pointer().assert_named(), "int");
+
+        let error = parse_type(&tokenizer("int[4")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected `]` to close array type");
+    }
+
+    #[test]
+    fn test_if_expr() {
+        let src = "{ :if 1 < 2 { :0 } else { :self(1) + self(2) } }";
+        let block = parse(src, parse_block).unwrap();
+        let Stmt::Return(Expr::If(stmt), _) = &block.items[0] else {
+            panic!("expected `:if` to parse as a returned if-expression");
+        };
+        assert!(matches!(stmt.condition, Expr::Lt(_)));
+        assert!(matches!(&stmt.then.items[0], Stmt::Return(Expr::Num(_), _)));
+        assert!(matches!(&stmt.otherwise, Some(Else::Block(block)) if matches!(block.items[0], Stmt::Return(Expr::Add(_), _))));
+
+        assert!(matches!(parse("{ if 1 { } }", parse_block).unwrap().items[0], Stmt::If(_)));
+
+        let src = "if a { :1 } else if b { :2 } else if c { :3 } else { :4 }";
+        let stmt = parse(src, parse_if).unwrap();
+        assert_eq!(Stmt::If(stmt).to_sexpr(), "(if a (block (ret 1)) (if b (block (ret 2)) (if c (block (ret 3)) (block (ret 4)))))");
+        let stmt = parse(src, parse_if).unwrap();
+        let Some(Else::If(second)) = &stmt.otherwise else {
+            panic!("expected an `else if`");
+        };
+        assert_eq!(&src[stmt.span], src);
+        assert_eq!(&src[second.span], "if b { :2 } else if c { :3 } else { :4 }");
+        let error = parse_if(&tokenizer("if a { } else :1")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected `{` or `if`, found `:`");
+
+        // A parenthesized condition isn't a lambda taking the block as its body.
+        let stmt = parse("if (c) { :1 }", parse_if).unwrap();
+        assert_eq!(Stmt::If(stmt).to_sexpr(), "(if c (block (ret 1)))");
+        let stmt = parse("if (a || b) && c { }", parse_if).unwrap();
+        assert_eq!(Stmt::If(stmt).to_sexpr(), "(if (and (or a b) c) (block))");
+        let stmt = parse("if (int) bool { :true }(1) { }", parse_if).unwrap();
+        assert!(matches!(stmt.condition, Expr::Call(callee, ..) if matches!(*callee, Expr::Lambda { .. })));
+        let error = parse_if(&tokenizer("if (a + ) { }")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected expression, found `)`");
+    }
+
+    #[test]
+    fn test_loops() {
+        let src = "{\n    'outer: while a {\n        while b { break 'outer }\n        continue\n    }\n}";
+        let block = parse(src, parse_block).unwrap();
+        let Stmt::While(stmt) = &block.items[0] else {
+            panic!("expected a labelled `while`");
+        };
+        assert_eq!(stmt.label.as_ref().unwrap().content(), "'outer");
+        assert_eq!(&src[stmt.span], &src[6..src.len() - 2]);
+        assert_eq!(block.items[0].to_sexpr(), "(while 'outer a (block (while b (block (break 'outer))) (continue)))");
+
+        // A label on the next line doesn't belong to the `break`.
+        let block = parse("{ while a { break\n'l: while b { } } }", parse_block).unwrap();
+        assert_eq!(block.items[0].to_sexpr(), "(while a (block (break) (while 'l b (block))))");
+
+        let stmt = parse("while (x) { x = 0 }", parse_while).unwrap();
+        assert_eq!(Stmt::While(stmt).to_sexpr(), "(while x (block (assign x 0)))");
+
+        let error = parse_while(&tokenizer("'outer while a { }")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected `:` after loop label");
+        let error = parse_jump(&tokenizer("break ' outer")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected a label name after `'`");
+    }
+
+    #[test]
+    fn test_lambda() {
+        let src = "sort(xs, (int, int) bool { :a < b })";
+        let Expr::Call(_, args, _) = parse(src, parse_expr).unwrap() else {
+            panic!("expected a call");
+        };
+        let Expr::Lambda { ty, body } = &args[1] else {
+            panic!("expected a lambda argument");
+        };
+        assert_eq!(ty.args.len(), 2);
+        assert_eq!(&src[args[1].span()], "(int, int) bool { :a < b }");
+        assert_eq!(args[1].to_sexpr(), "(lambda (fn-type (int int) bool) (block (ret (lt a b))))");
+        assert!(matches!(body.items[0], Stmt::Return(Expr::Lt(_), _)));
+
+        // Called in place.
+        assert!(matches!(parse("() { }()", parse_expr), Some(Expr::Call(callee, ..)) if matches!(*callee, Expr::Lambda { .. })));
+
+        // Without a function type and a `{`, parentheses group.
+        assert_eq!(parse("(a + b) * c", parse_expr).unwrap().to_sexpr(), "(mul (add a b) c)");
+        assert_eq!(parse("-(a)(1)", parse_expr).unwrap().to_sexpr(), "(neg (call a 1))");
+        assert_eq!(parse("((1))", parse_expr).unwrap().to_sexpr(), "1");
+        let error = parse_expr(&tokenizer("(a + b")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected `)` to close parenthesized expression");
+    }
+
+    #[test]
+    fn test_array_lit() {
+        let src = "primes int[3] = [2, 3, 5,]";
+        let Some(Decl::Global { init: Some(init), .. }) = parse(src, parse_decl) else {
+            panic!("expected an initialized global");
+        };
+        assert_eq!(&src[init.span()], "[2, 3, 5,]");
+        assert_eq!(init.to_sexpr(), "(array 2 3 5)");
+        assert_eq!(parse("[]", parse_expr).unwrap().to_sexpr(), "(array)");
+        assert_eq!(parse("[[1], f(2)]", parse_expr).unwrap().to_sexpr(), "(array (array 1) (call f 2))");
+
+        let error = parse_expr(&tokenizer("[1, 2")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected `]` to close array literal");
+
+        // Indexing is a postfix operator, like a call.
+        assert_eq!(parse("-xs[i][f(j)](k)", parse_expr).unwrap().to_sexpr(), "(neg (call (index (index xs i) (call f j)) k))");
+        assert_eq!(&"[1, 2][0]"[parse("[1, 2][0]", parse_expr).unwrap().span()], "[1, 2][0]");
+        let error = parse_expr(&tokenizer("xs[i")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected `]` to close index");
+    }
+
+    #[test]
+    fn test_nested_func() {
+        let src = "{\n    twice (int) int { :x * 2 }\n    :twice(1)\n}";
+        let block = parse(src, parse_block).unwrap();
+        let Stmt::Func(name, func) = &block.items[0] else {
+            panic!("expected a nested function");
+        };
+        assert_eq!(name.content(), "twice");
+        assert_eq!(&src[block.items[0].span()], "twice (int) int { :x * 2 }");
+        assert_eq!(block.items[0].to_sexpr(), "(fn twice (fn-type (int) int) (block (ret (mul x 2))))");
+        assert!(func.generics.is_empty());
+
+        let block = parse("{ id<T> (T) T { :x } }", parse_block).unwrap();
+        assert!(matches!(&block.items[0], Stmt::Func(_, func) if func.generics.len() == 1));
+        // Without a body these are still assignments.
+        let block = parse("{ f(x) = 1\n*p = 2 }", parse_block).unwrap();
+        assert!(block.items.iter().all(|item| matches!(item, Stmt::Assign { .. })));
+    }
+
+    #[test]
+    fn test_program() {
+        let src = "limit int = 3\ntype str = char*\nmain () int { :0 }\n";
+        let decls = parse_program(&tokenizer(src)).unwrap();
+        assert_eq!(decls.len(), 3);
+        assert!(matches!(decls[2], Decl::Func(..)));
+        assert!(parse_program(&tokenizer("  // nothing\n")).unwrap().is_empty());
+
+        let Err(errors) = parse_program(&tokenizer("main () int { :0 }\n+ 1")) else {
+            panic!("expected trailing `+ 1` to be rejected");
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "expected declaration, found `+`");
+        assert_eq!(errors[0].location().line, 1);
+
+        let Err(errors) = parse_program(&tokenizer("x int /* open")) else {
+            panic!("expected the unterminated comment to be reported");
+        };
+        assert_eq!(errors[0].message, "unterminated block comment");
+    }
+
+    #[test]
+    fn test_modules() {
+        let decls = parse_program(&tokenizer("import \"std/io\"\nmod util\n")).unwrap();
+        assert!(matches!(&decls[0], Decl::Import(path, _) if path.content() == "std/io"));
+        assert!(matches!(&decls[1], Decl::Mod(name, _) if name.content() == "util"));
+        assert!(parse_program(&tokenizer("import util")).is_err());
+
+        let files = [
+            ("main", "import \"io\"\nmod util\nmain () int { :0 }"),
+            ("util", "import \"main\"\nlimit int = 3"),
+            ("io", "mod util"),
+        ];
+        let mut calls = Vec::new();
+        let mut resolver = |from: &str, target: ModuleRef<'_>| {
+            calls.push(from.to_string());
+            let name = match target {
+                ModuleRef::Import(path) | ModuleRef::Mod(path) => path,
+            };
+            files.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(n, s)| (n.to_string(), s.to_string()))
+                .ok_or_else(|| "no such file".to_string())
+        };
+        let mut sources = SourceManager::new();
+        let root = sources.add(files[0].0, files[0].1);
+        let modules = parse_modules(&mut sources, root, &mut resolver).unwrap();
+        assert_eq!(modules.iter().map(|m| m.file).collect::<Vec<_>>(), [FileId(0), FileId(1), FileId(2)]);
+        assert_eq!(modules[0].imports, [FileId(1), FileId(2)]);
+        assert_eq!(modules[2].imports, [root]);
+
+        let mut sources = SourceManager::new();
+        let root = sources.add("main", "import \"missing\"");
+        let Err(errors) = parse_modules(&mut sources, root, &mut resolver) else {
+            panic!("expected the missing import to be reported");
+        };
+        assert_eq!(errors[0].message, "cannot load `missing`: no such file");
+        assert_eq!(errors[0].span.file(), root);
+        assert_eq!(calls, ["main", "main", "io", "util", "main"]);
+    }
+
+    #[test]
+    fn test_generics() {
+        let ty = parse("map<str, list<int*>>[2]", parse_type).unwrap();
+        let (name, args) = ty.assert_array().0.assert_generic();
+        assert_eq!(name, "map");
+        assert_eq!(args[0].assert_named(), "str");
+        let (name, args) = args[1].assert_generic();
+        assert_eq!(name, "list");
+        assert_eq!(args[0].assert_pointer().assert_named(), "int");
+
+        let Some(Decl::Func(_, func)) = parse("first<T> (list<T>) T { }", parse_decl) else {
+            panic!("expected a generic function");
+        };
+        assert_eq!(func.generics.iter().map(|t| t.content()).collect::<Vec<_>>(), ["T"]);
+        assert_eq!(func.ty.args[0].assert_generic().1[0].assert_named(), "T");
+
+        let error = tokenizer("pair<A, 1> () { }");
+        assert_eq!(parse_decl(&error).err().and_then(ParseError::into_error).unwrap().message, "expected type parameter name, found `1`");
+        let error = parse_type(&tokenizer("list<int")).err().and_then(ParseError::into_error).unwrap();
+        assert_eq!(error.message, "expected `>` to close type arguments");
+    }
+
+    #[test]
+    fn test_spans() {
+        let src = "main (int) int* {\n    let x list<int>[2] = -self(1, 2) + 3\n    :if 2 < 1 { :\"${3}!\" }\n}";
+        let decl = parse(src, parse_decl).unwrap();
+        assert_eq!(&src[decl.span()], src);
+        let Decl::Func(_, func) = &decl else {
+            panic!("expected a function");
+        };
+        assert_eq!(&src[func.ty.span], "(int) int*");
+
+        let Stmt::Let { ty: Some(ty), init: Some(init), span, .. } = &func.body.items[0] else {
+            panic!("expected `let` with a type and an initializer");
+        };
+        assert_eq!(&src[*span], "let x list<int>[2] = -self(1, 2) + 3");
+        assert_eq!(&src[ty.span()], "list<int>[2]");
+        let Expr::Add(add) = init else {
+            panic!("expected an addition");
+        };
+        assert_eq!(&src[add.0.span()], "-self(1, 2)");
+
+        let stmt = &func.body.items[1];
+        assert_eq!(&src[stmt.span()], ":if 2 < 1 { :\"${3}!\" }");
+        let Stmt::Return(Expr::If(stmt), _) = stmt else {
+            panic!("expected a returned if-expression");
+        };
+        assert_eq!(&src[stmt.condition.span()], "2 < 1");
+        assert_eq!(&src[stmt.then.items[0].span()], ":\"${3}!\"");
+
+        let mac = parse("args! argc, argv\n", parse_macro).unwrap();
+        assert_eq!(mac.span.end.column, 16);
+    }
+
+    #[test]
+    fn test_eq_ignoring_spans() {
+        let compact = parse("main () int { let x = 1+2 :x }", parse_decl).unwrap();
+        let spread = parse("main ( ) int {\n    let x = 1 + 2\n\n    :x // done\n}", parse_decl).unwrap();
+        let changed = parse("main () int { let x = 2 + 1 :x }", parse_decl).unwrap();
+        assert!(eq_ignoring_spans(&compact, &spread));
+        assert!(!eq_ignoring_spans(&compact, &changed));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serialize() {
+        let decl = parse("limit int* = -3", parse_decl).unwrap();
+        let json = serde_json::to_value(&decl).unwrap();
+        let global = &json["Global"];
+        assert_eq!(global["name"]["content"], "limit");
+        assert_eq!(global["name"]["kind"], "Ident");
+        assert_eq!(global["name"]["span"]["end"]["index"], 5);
+        assert_eq!(global["ty"]["Ptr"][0]["Name"]["content"], "int");
+        assert_eq!(global["init"]["Unary"][0], "Neg");
+        assert_eq!(global["init"]["Unary"][1]["Num"]["token"]["content"], "3");
+        assert_eq!(global["init"]["Unary"][1]["Num"]["value"]["Int"], 3);
+    }
+
+    #[test]
+    fn test_into_owned() {
+        let decls = {
+            let src = String::from("main () str {\n    let x int = 1 + 2\n    :\"a${2}\\n\"\n}");
+            let tok = tokenizer(&src);
+            parse_program(&tok).unwrap().into_iter().map(Decl::into_owned).collect::<Vec<_>>()
+        };
+        let owned = std::thread::spawn(move || decls.iter().map(Decl::to_sexpr).collect::<Vec<_>>())
+            .join()
+            .unwrap();
+        assert_eq!(owned, [r#"(fn main (fn-type () str) (block (let x int (add 1 2)) (ret (interp "a" 2 "\n"))))"#]);
+    }
+
+    #[test]
+    fn test_recovery() {
+        let src = "main () int {\n    let x =\n    :0\n    let y int = 2 + ; :1\n    { : }\n}\nbad + 1\nlimit int = 3\n";
+        let (decls, errors) = parse_program_recovering(&tokenizer(src));
+        let messages = errors.iter().map(|e| (e.location().line, e.message.as_str())).collect::<Vec<_>>();
+        assert_eq!(messages, [
+            (2, "expected expression, found `:`"),
+            (3, "expected expression, found `;`"),
+            (4, "expected expression, found `}`"),
+            (6, "expected type or `=`, found `+`"),
+        ]);
+
+        let tok = tokenizer("{ ) }");
+        parse_block(&tok).unwrap_or_else(|_| panic!("expected the block to recover"));
+        let error = &tok.take_diagnostics()[0];
+        assert_eq!(error.message, "expected `{`, `if`, loop label, `while`, `break`, `continue`, `:`, `let`, or expression, found `)`");
+        assert_eq!(error.expected, ["`{`", "`if`", "loop label", "`while`", "`break`", "`continue`", "`:`", "`let`", "expression"]);
+
+        assert_eq!(decls.len(), 2);
+        let Decl::Func(_, func) = &decls[0] else {
+            panic!("expected the function to survive its errors");
+        };
+        assert_eq!(func.body.items.len(), 3);
+        assert!(matches!(&func.body.items[0], Stmt::Return(Expr::Num(n), _) if n.token.content() == "0"));
+        assert!(matches!(&func.body.items[1], Stmt::Return(Expr::Num(n), _) if n.token.content() == "1"));
+        assert!(matches!(&func.body.items[2], Stmt::Block(block) if block.items.is_empty()));
+        assert!(matches!(&decls[1], Decl::Global { name, .. } if name.content() == "limit"));
+
+        let Err(errors) = parse_program(&tokenizer(src)) else {
+            panic!("expected parse_program to fail");
+        };
+        assert_eq!(errors.len(), 4);
+    }
+}
@@ -0,0 +1,152 @@
+//! Associates comments with declarations and statements, see [`Comments`].
+
+use crate::ast::{self, Decl, Stmt};
+use crate::{Error, Span, Token, TokenKind, TriviaKind};
+
+/// Every token of a source file, comments included, for looking up the comments
+/// around a node. The parser skips comments, so they're kept here on the side and
+/// matched to nodes by span.
+///
+/// A node's doc comment is the block of comments on the lines right above it, with no
+/// blank line in between; its trailing comment is one on the same line after it.
+pub struct Comments<'src> {
+    tokens: Vec<Token<'src>>,
+}
+
+impl<'src> Comments<'src> {
+    pub fn new(source: &'src str) -> Result<Self, Error> {
+        let tokens = ast::tokenizer(source).raw(|tok| tok.iter().collect::<Result<_, _>>())?;
+        Ok(Self { tokens })
+    }
+
+    /// The comments making up the doc comment of the node at `span`, top to bottom.
+    pub fn leading(&self, span: Span) -> Vec<&Token<'src>> {
+        let end = self.tokens.partition_point(|token| token.span.start < span.start);
+        let mut comments = Vec::new();
+        let mut newlines = 0;
+        for (i, token) in self.tokens[..end].iter().enumerate().rev() {
+            match token.kind {
+                TokenKind::Trivia(TriviaKind::Whitespace) => {}
+                TokenKind::Trivia(TriviaKind::Newline) => {
+                    newlines += 1;
+                    if newlines > 1 {
+                        break;
+                    }
+                }
+                TokenKind::Trivia(TriviaKind::LineComment | TriviaKind::BlockComment) => {
+                    // A comment after code on its line trails that code instead.
+                    if !self.starts_line(i) {
+                        break;
+                    }
+                    comments.push(token);
+                    newlines = 0;
+                }
+                _ => break,
+            }
+        }
+        comments.reverse();
+        comments
+    }
+
+    /// The comment following the node at `span` on its last line.
+    pub fn trailing(&self, span: Span) -> Option<&Token<'src>> {
+        let start = self.tokens.partition_point(|token| token.span.start < span.end);
+        self.tokens[start..].iter()
+            .find(|token| token.kind != TokenKind::Trivia(TriviaKind::Whitespace))
+            .filter(|token| matches!(token.kind, TokenKind::Trivia(TriviaKind::LineComment | TriviaKind::BlockComment)))
+    }
+
+    /// The text of the doc comment of the node at `span` with the comment markers
+    /// removed, one line per line comment.
+    pub fn doc(&self, span: Span) -> Option<String> {
+        let comments = self.leading(span);
+        if comments.is_empty() {
+            return None;
+        }
+        let lines = comments.iter()
+            .map(|comment| {
+                let text = comment.content();
+                let text = match comment.kind {
+                    TokenKind::Trivia(TriviaKind::BlockComment) => text.get(2..text.len() - 2).unwrap_or_default().trim(),
+                    _ => text.get(2..).unwrap_or_default(),
+                };
+                text.strip_prefix(' ').unwrap_or(text)
+            })
+            .collect::<Vec<_>>();
+        Some(lines.join("\n"))
+    }
+
+    /// Whether only whitespace comes between the start of the line and token `i`.
+    fn starts_line(&self, i: usize) -> bool {
+        self.tokens[..i].iter()
+            .rev()
+            .find(|token| token.kind != TokenKind::Trivia(TriviaKind::Whitespace))
+            .is_none_or(|token| token.kind == TokenKind::Trivia(TriviaKind::Newline))
+    }
+}
+
+impl Decl<'_> {
+    /// The comment block right above this declaration, see [`Comments::doc`].
+    pub fn doc(&self, comments: &Comments) -> Option<String> {
+        comments.doc(self.span())
+    }
+
+    /// The comment after this node on its last line.
+    pub fn trailing_comment<'c, 'src>(&self, comments: &'c Comments<'src>) -> Option<&'c Token<'src>> {
+        comments.trailing(self.span())
+    }
+}
+
+impl Stmt<'_> {
+    /// The comment block right above this statement, see [`Comments::doc`].
+    pub fn doc(&self, comments: &Comments) -> Option<String> {
+        comments.doc(self.span())
+    }
+
+    /// The comment after this node on its last line.
+    pub fn trailing_comment<'c, 'src>(&self, comments: &'c Comments<'src>) -> Option<&'c Token<'src>> {
+        comments.trailing(self.span())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Comments;
+    use crate::ast::{parse_program, tokenizer, Decl};
+
+    #[test]
+    fn test_comments() {
+        let src = "\
+// Not attached: a blank line follows.
+
+// Computes fibonacci
+// numbers.
+fib (int) int {
+    // The base case.
+    :if 1 < 2 { :0 } // base
+    /* recurse */
+    :self(1)
+}
+limit int = 3 /* max */ // trailing
+";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let comments = Comments::new(src).unwrap();
+
+        let Decl::Func(_, func) = &decls[0] else {
+            panic!("expected a function");
+        };
+        assert_eq!(decls[0].doc(&comments).as_deref(), Some("Computes fibonacci\nnumbers."));
+        assert!(decls[0].trailing_comment(&comments).is_none());
+
+        let [first, second] = func.body.items.as_slice() else {
+            panic!("expected two statements");
+        };
+        assert_eq!(first.doc(&comments).as_deref(), Some("The base case."));
+        assert_eq!(first.trailing_comment(&comments).unwrap().content(), "// base");
+        // `// base` trails the first statement, so only the block comment documents the second.
+        assert_eq!(second.doc(&comments).as_deref(), Some("recurse"));
+
+        assert!(decls[1].doc(&comments).is_none());
+        assert_eq!(decls[1].trailing_comment(&comments).unwrap().content(), "/* max */");
+    }
+}
@@ -0,0 +1,333 @@
+//! A lossless concrete syntax tree: every token of the source, punctuation, whitespace
+//! and comments included, grouped under nodes that mirror the [`ast`] nodes they cover.
+//! Concatenating the tokens gives back the source byte for byte, which is what
+//! formatters and refactoring tools need and the AST throws away.
+//!
+//! The tree is built from a regular parse, so it only exists for valid input, and
+//! [`SyntaxTree::lower`] turns it back into the typed AST. The `${}` holes of an
+//! interpolated string aren't split out of their string's tokens.
+
+use crate::ast::{self, Block, Decl, Expr, FnType, If, Macro, Stmt, Type};
+use crate::visit::{self, Visit};
+use crate::{Error, Location, ParseResultExt, Span, Token};
+
+/// The AST node a [`Node`] stands for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The whole file, including trivia before the first and after the last declaration.
+    Root,
+    Decl,
+    Type,
+    FnType,
+    Stmt,
+    Block,
+    If,
+    Macro,
+    Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node<'src> {
+    pub kind: NodeKind,
+    pub span: Span,
+    /// Tokens and nodes in source order. Trivia sits in the innermost node that
+    /// contains the tokens on both sides of it.
+    pub children: Vec<Element<'src>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Element<'src> {
+    Node(Node<'src>),
+    Token(Token<'src>),
+}
+
+pub struct SyntaxTree<'src> {
+    pub root: Node<'src>,
+}
+
+/// Parses `source` into a concrete syntax tree, failing like [`ast::parse_program`].
+pub fn parse(source: &str) -> Result<SyntaxTree<'_>, Vec<Error>> {
+    let decls = ast::parse_program(&ast::tokenizer(source))?;
+    let (tokens, span) = ast::tokenizer(source)
+        .raw(|tok| {
+            let start = tok.location();
+            let tokens = tok.iter().collect::<Result<Vec<_>, _>>()?;
+            Ok((tokens, Span { start, end: tok.location() }))
+        })
+        .map_err(|error| vec![error])?;
+
+    let mut spans = Spans { stack: vec![Shape::new(NodeKind::Root, span)] };
+    for decl in &decls {
+        spans.visit_decl(decl);
+    }
+    let root = spans.stack.pop().expect("the root is never popped");
+    let root = build(root, &mut tokens.into_iter().peekable());
+    Ok(SyntaxTree { root })
+}
+
+/// The nodes enclosing byte `offset` of `source`, innermost first: say an expression,
+/// its statement, the block and the function's declaration. Meant for completion, so
+/// `source` needn't parse: declarations that don't are skipped, and if that leaves
+/// nothing around `offset`, the source is cut off there with its open brackets closed,
+/// since the cursor is usually at the end of unfinished code.
+pub fn parse_at(source: &str, offset: usize) -> Vec<(NodeKind, Span)> {
+    let chain = enclosing(source, offset);
+    if !chain.is_empty() {
+        return chain;
+    }
+    let Some(prefix) = source.get(..offset) else {
+        return chain;
+    };
+    let mut open = Vec::new();
+    ast::tokenizer(prefix).raw(|tok| {
+        for token in tok.iter().map_while(Result::ok) {
+            match token.content() {
+                "(" => open.push(")"),
+                "[" => open.push("]"),
+                "{" => open.push("}"),
+                ")" | "]" | "}" => {
+                    open.pop();
+                }
+                _ => {}
+            }
+        }
+    });
+    let closed = open.into_iter().rev().fold(prefix.to_string(), |source, close| source + close);
+    enclosing(&closed, offset)
+}
+
+fn enclosing(source: &str, offset: usize) -> Vec<(NodeKind, Span)> {
+    let tok = ast::tokenizer(source);
+    let here = Span { start: tok.location(), end: tok.location() };
+    let (decls, _) = ast::parse_program_recovering(&tok);
+    let mut spans = Spans { stack: vec![Shape::new(NodeKind::Root, here)] };
+    for decl in &decls {
+        spans.visit_decl(decl);
+    }
+    let mut chain = Vec::new();
+    let mut shapes = spans.stack.pop().expect("the root is never popped").children;
+    // A cursor right after a node is still in it, as when completing a name.
+    let covers = |shape: &Shape| shape.span.start.index <= offset && offset <= shape.span.end.index;
+    while let Some(shape) = shapes.into_iter().find(covers) {
+        chain.push((shape.kind, shape.span));
+        shapes = shape.children;
+    }
+    chain.reverse();
+    chain
+}
+
+impl<'src> SyntaxTree<'src> {
+    /// The source the tree was parsed from, rebuilt from its tokens.
+    pub fn text(&self) -> String {
+        self.root.text()
+    }
+
+    /// Parses each declaration node back into its [`Decl`] from the tokens it holds
+    /// now, so edits to the tree carry over. Spans refer to [`SyntaxTree::text`].
+    pub fn lower(&self) -> Result<Vec<Decl<'static>>, Error> {
+        let text = self.text();
+        let tok = ast::tokenizer(&text);
+        let mut end = Location::zero();
+        let mut decls = Vec::new();
+        for child in &self.root.children {
+            let start = end;
+            match child {
+                Element::Token(token) => tok.adv_str(&mut end, token.content()),
+                Element::Node(node) => {
+                    tok.adv_str(&mut end, &node.text());
+                    if node.kind != NodeKind::Decl {
+                        continue;
+                    }
+                    let sub = tok.sub_tokenizer(Span { start, end });
+                    let decl = ast::parse_decl(&sub)
+                        .required(&sub)
+                        .map_err(|error| error.into_error().expect("`required` never leaves a no-match"))?;
+                    if sub.has_more_tokens() {
+                        return Err(Error::at(sub.location(), "expected the declaration to end here"));
+                    }
+                    decls.push(decl.into_owned());
+                }
+            }
+        }
+        Ok(decls)
+    }
+}
+
+impl<'src> Node<'src> {
+    /// The nodes directly below this one.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<'src>> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    /// Every token under this node, in source order.
+    pub fn tokens(&self) -> Vec<&Token<'src>> {
+        let mut out = Vec::new();
+        self.collect_tokens(&mut out);
+        out
+    }
+
+    fn collect_tokens<'a>(&'a self, out: &mut Vec<&'a Token<'src>>) {
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.collect_tokens(out),
+                Element::Token(token) => out.push(token),
+            }
+        }
+    }
+
+    /// The exact source text under this node.
+    pub fn text(&self) -> String {
+        self.tokens().into_iter().map(Token::content).collect()
+    }
+}
+
+/// A node's kind and extent before tokens are assigned to it.
+struct Shape {
+    kind: NodeKind,
+    span: Span,
+    children: Vec<Shape>,
+}
+
+impl Shape {
+    fn new(kind: NodeKind, span: Span) -> Self {
+        Self { kind, span, children: Vec::new() }
+    }
+}
+
+/// Collects the extent of every AST node, nested the way the nodes are.
+struct Spans {
+    stack: Vec<Shape>,
+}
+
+impl Spans {
+    fn node(&mut self, kind: NodeKind, span: Span, children: impl FnOnce(&mut Self)) {
+        self.stack.push(Shape::new(kind, span));
+        children(self);
+        let shape = self.stack.pop().expect("pushed above");
+        self.stack.last_mut().expect("the root is never popped").children.push(shape);
+    }
+}
+
+impl<'ast> Visit<'ast> for Spans {
+    fn visit_decl(&mut self, decl: &'ast Decl<'ast>) {
+        self.node(NodeKind::Decl, decl.span(), |v| visit::walk_decl(v, decl));
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type<'ast>) {
+        self.node(NodeKind::Type, ty.span(), |v| visit::walk_type(v, ty));
+    }
+
+    fn visit_fn_type(&mut self, ty: &'ast FnType<'ast>) {
+        self.node(NodeKind::FnType, ty.span, |v| visit::walk_fn_type(v, ty));
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt<'ast>) {
+        self.node(NodeKind::Stmt, stmt.span(), |v| visit::walk_stmt(v, stmt));
+    }
+
+    fn visit_block(&mut self, block: &'ast Block<'ast>) {
+        self.node(NodeKind::Block, block.span(), |v| visit::walk_block(v, block));
+    }
+
+    fn visit_if(&mut self, stmt: &'ast If<'ast>) {
+        self.node(NodeKind::If, stmt.span, |v| visit::walk_if(v, stmt));
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro<'ast>) {
+        self.node(NodeKind::Macro, mac.span, |_| {});
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr<'ast>) {
+        self.node(NodeKind::Expr, expr.span(), |v| visit::walk_expr(v, expr));
+    }
+}
+
+/// Hands out the tokens inside `shape`, descending into a child once the tokens
+/// reach it. Children that start inside a single token are dropped.
+fn build<'src>(shape: Shape, tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token<'src>>>) -> Node<'src> {
+    let Shape { kind, span, children: shapes } = shape;
+    let mut shapes = shapes.into_iter().peekable();
+    let mut children = Vec::new();
+    while let Some(token) = tokens.peek() {
+        if token.span.start >= span.end && kind != NodeKind::Root {
+            break;
+        }
+        while shapes.next_if(|shape| shape.span.start < token.span.start).is_some() {}
+        match shapes.next_if(|shape| shape.span.start == token.span.start && !shape.span.is_empty()) {
+            Some(shape) => children.push(Element::Node(build(shape, tokens))),
+            None => children.push(Element::Token(tokens.next().expect("peeked above"))),
+        }
+    }
+    Node { kind, span, children }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless() {
+        let src = "// entry\nmain (int, char**) int {\n    args! n\n    :if n < 2 { :0 } // base\n}\n\nlimit int = 1 + 2 /* sum */\n";
+        let tree = parse(src).unwrap_or_else(|errors| panic!("{}", errors[0]));
+        assert_eq!(tree.text(), src);
+
+        let decls = tree.root.nodes().collect::<Vec<_>>();
+        assert_eq!(decls.len(), 2);
+        assert!(decls.iter().all(|node| node.kind == NodeKind::Decl));
+        assert!(decls[0].text().starts_with("main (int"));
+        assert!(decls[0].text().ends_with("// base\n}"));
+        assert_eq!(decls[1].text(), "limit int = 1 + 2");
+
+        // The parameter list keeps its comma and pointer stars.
+        let fn_type = decls[0].nodes().next().unwrap();
+        assert_eq!(fn_type.kind, NodeKind::FnType);
+        let tokens = fn_type.tokens().into_iter().map(Token::content).collect::<Vec<_>>();
+        assert_eq!(tokens, ["(", "int", ",", " ", "char", "*", "*", ")", " ", "int"]);
+
+        let lowered = tree.lower().unwrap_or_else(|error| panic!("{error}"));
+        let parsed = ast::parse_program(&ast::tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let sexprs = |decls: &[Decl]| decls.iter().map(Decl::to_sexpr).collect::<Vec<_>>();
+        assert_eq!(sexprs(&lowered), sexprs(&parsed));
+
+        // Lowering reads the tree, so an edited token shows up in the AST.
+        let mut tree = tree;
+        let Some(Element::Node(limit)) = tree.root.children.iter_mut().rev().find(|child| matches!(child, Element::Node(_))) else {
+            panic!("expected a declaration node");
+        };
+        let Element::Token(name) = &mut limit.children[0] else {
+            panic!("expected the declaration to start with its name");
+        };
+        name.content = "cap".into();
+        let lowered = tree.lower().unwrap_or_else(|error| panic!("{error}"));
+        let text = tree.text();
+        assert_eq!(&text[lowered[1].span()], "cap int = 1 + 2");
+        let expected = ast::parse_decl(&ast::tokenizer("cap int = 1 + 2")).unwrap_or_else(|_| panic!("expected a declaration"));
+        assert_eq!(lowered[1].to_sexpr(), expected.to_sexpr());
+
+        assert!(parse("main ( {").is_err());
+    }
+
+    #[test]
+    fn test_parse_at() {
+        let kinds = |chain: Vec<(NodeKind, Span)>| chain.into_iter().map(|(kind, _)| kind).collect::<Vec<_>>();
+        let src = "main () int {\n    let x = f(1 + 2)\n}\n";
+        let offset = src.find('2').unwrap();
+        let chain = parse_at(src, offset);
+        assert_eq!(&src[chain[1].1], "1 + 2");
+        assert_eq!(kinds(chain), [NodeKind::Expr, NodeKind::Expr, NodeKind::Expr, NodeKind::Stmt, NodeKind::Block, NodeKind::Decl]);
+
+        // Unfinished code is closed off at the cursor.
+        let src = "main () int {\n    :if n < 2 { :f(n";
+        let chain = parse_at(src, src.len());
+        assert_eq!(&src[chain[1].1.start.index..], "f(n");
+        assert_eq!(kinds(chain), [
+            NodeKind::Expr, NodeKind::Expr, NodeKind::Stmt, NodeKind::Block, NodeKind::If,
+            NodeKind::Expr, NodeKind::Stmt, NodeKind::Block, NodeKind::Decl,
+        ]);
+
+        assert!(parse_at("main () int {}\n\n", 16).is_empty());
+    }
+}
@@ -0,0 +1,543 @@
+//! Lowers the surface AST to a smaller core IR, so code generation and analysis only
+//! have to handle a handful of nodes:
+//!
+//! - every binary operator becomes [`Expr::Binary`], every unary one [`Expr::Unary`];
+//! - an interpolated string becomes a [`Expr::Concat`] of its pieces;
+//! - an `if` in expression position becomes an `if` statement that assigns a fresh
+//!   temporary in each branch, hoisted in front of the statement using its value;
+//! - `&&` and `||` become a temporary holding the left operand and an `if` that only
+//!   evaluates the right one when it decides the result, hoisted the same way;
+//! - `while c { ... }` becomes a [`Stmt::Loop`] starting with `if !c { break }`, so
+//!   whatever `c` hoists runs again on every iteration.
+//!
+//! Operands evaluated before hoisted statements are spilled to temporaries of their
+//! own, so the left-to-right evaluation order of the source is kept.
+
+use crate::ast::{self, Block, Else, FnType, Func, Macro, NumLit, StrPart, Type, UnaryOp};
+use crate::Token;
+use std::fmt::Write;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Var<'a> {
+    Named(&'a Token<'a>),
+    /// A temporary introduced by lowering, printed as `%n`.
+    Temp(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BinOp<'a> {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Eq,
+    Ne,
+    Le,
+    Gt,
+    Ge,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    /// An operator from [`ParserConfig::binary_ops`](crate::ast::ParserConfig::binary_ops)
+    /// without a variant of its own.
+    Custom(&'a Token<'a>),
+}
+
+pub enum Expr<'a> {
+    Num(&'a NumLit<'a>),
+    Str(&'a Token<'a>),
+    Bool(&'a Token<'a>),
+    Var(Var<'a>),
+    SelfRef(&'a Token<'a>),
+    Call(Box<Self>, Vec<Self>),
+    Index(Box<(Self, Self)>),
+    Array(Vec<Self>),
+    Binary(BinOp<'a>, Box<(Self, Self)>),
+    Unary(UnaryOp, Box<Self>),
+    /// The pieces of an interpolated string, joined in order.
+    Concat(Vec<Self>),
+    /// A lambda, with its body lowered on its own.
+    Lambda(&'a FnType<'a>, Vec<Stmt<'a>>),
+}
+
+pub enum Stmt<'a> {
+    Let { var: Var<'a>, ty: Option<&'a Type<'a>>, init: Option<Expr<'a>> },
+    Assign { target: Expr<'a>, value: Expr<'a> },
+    Expr(Expr<'a>),
+    /// An `if` without an `else` has an empty `otherwise`.
+    If { condition: Expr<'a>, then: Vec<Self>, otherwise: Vec<Self> },
+    Block(Vec<Self>),
+    Return(Expr<'a>),
+    Macro(&'a Macro<'a>),
+    /// Runs `body` until a `break`.
+    Loop { label: Option<&'a Token<'a>>, body: Vec<Self> },
+    Break(Option<&'a Token<'a>>),
+    Continue(Option<&'a Token<'a>>),
+    /// A function declared inside a block, with its body lowered on its own.
+    Func { name: &'a Token<'a>, func: &'a Func<'a>, body: Vec<Self> },
+}
+
+/// Lowers the body of `func`.
+pub fn desugar_func<'a>(func: &'a Func<'a>) -> Vec<Stmt<'a>> {
+    Desugar::default().block(&func.body)
+}
+
+/// Lowers a block on its own, numbering temporaries from zero.
+pub fn desugar_block<'a>(block: &'a Block<'a>) -> Vec<Stmt<'a>> {
+    Desugar::default().block(block)
+}
+
+#[derive(Default)]
+struct Desugar {
+    temps: usize,
+    /// The temporary a `:` assigns while lowering the branches of an `if` expression.
+    yield_to: Option<usize>,
+}
+
+impl Desugar {
+    fn temp(&mut self) -> usize {
+        self.temps += 1;
+        self.temps - 1
+    }
+
+    fn block<'a>(&mut self, block: &'a Block<'a>) -> Vec<Stmt<'a>> {
+        let mut out = Vec::new();
+        for stmt in &block.items {
+            self.stmt(stmt, &mut out);
+        }
+        out
+    }
+
+    fn stmt<'a>(&mut self, stmt: &'a ast::Stmt<'a>, out: &mut Vec<Stmt<'a>>) {
+        let lowered = match stmt {
+            ast::Stmt::If(s) => self.if_(s, out),
+            ast::Stmt::Return(value, _) => {
+                let value = self.expr(value, out);
+                match self.yield_to {
+                    Some(temp) => Stmt::Assign { target: Expr::Var(Var::Temp(temp)), value },
+                    None => Stmt::Return(value),
+                }
+            }
+            ast::Stmt::Block(block) => Stmt::Block(self.block(block)),
+            ast::Stmt::Assign { target, value } => {
+                let target = self.place(target, &[value], out);
+                let value = self.expr(value, out);
+                Stmt::Assign { target, value }
+            }
+            ast::Stmt::Expr(e) => Stmt::Expr(self.expr(e, out)),
+            ast::Stmt::Macro(mac) => Stmt::Macro(mac),
+            ast::Stmt::Let { name, ty, init, .. } => Stmt::Let {
+                var: Var::Named(name),
+                ty: ty.as_ref(),
+                init: init.as_ref().map(|init| self.expr(init, out)),
+            },
+            ast::Stmt::While(s) => {
+                let mut body = Vec::new();
+                let condition = self.expr(&s.condition, &mut body);
+                let exit = Expr::Unary(UnaryOp::Not, Box::new(condition));
+                body.push(Stmt::If { condition: exit, then: vec![Stmt::Break(None)], otherwise: Vec::new() });
+                body.extend(self.block(&s.body));
+                Stmt::Loop { label: s.label.as_ref(), body }
+            }
+            ast::Stmt::Break(label, _) => Stmt::Break(label.as_ref()),
+            ast::Stmt::Continue(label, _) => Stmt::Continue(label.as_ref()),
+            ast::Stmt::Func(name, func) => Stmt::Func { name, func, body: desugar_func(func) },
+        };
+        out.push(lowered);
+    }
+
+    fn if_<'a>(&mut self, s: &'a ast::If<'a>, out: &mut Vec<Stmt<'a>>) -> Stmt<'a> {
+        let condition = self.expr(&s.condition, out);
+        let then = self.block(&s.then);
+        let otherwise = match &s.otherwise {
+            Some(Else::Block(block)) => self.block(block),
+            // The condition of an `else if` is only evaluated inside the `else`.
+            Some(Else::If(s)) => {
+                let mut otherwise = Vec::new();
+                let lowered = self.if_(s, &mut otherwise);
+                otherwise.push(lowered);
+                otherwise
+            }
+            None => Vec::new(),
+        };
+        Stmt::If { condition, then, otherwise }
+    }
+
+    /// Lowers `expr`, pushing whatever has to run before it onto `out`.
+    fn expr<'a>(&mut self, expr: &'a ast::Expr<'a>, out: &mut Vec<Stmt<'a>>) -> Expr<'a> {
+        use ast::Expr as E;
+        let (op, operands) = match expr {
+            E::Num(lit) => return Expr::Num(lit),
+            E::Str(token) => return Expr::Str(token),
+            E::Bool(token) => return Expr::Bool(token),
+            E::Name(token) => return Expr::Var(Var::Named(token)),
+            E::SelfRef(token) => return Expr::SelfRef(token),
+            E::Lambda { ty, body } => return Expr::Lambda(ty, desugar_block(body)),
+            E::Interp(parts, _) => {
+                let mut pieces = Vec::new();
+                for (i, part) in parts.iter().enumerate() {
+                    let piece = match part {
+                        StrPart::Lit(lit) => Expr::Str(lit),
+                        StrPart::Expr(e) => self.expr(e, out),
+                    };
+                    let later = parts[i + 1..].iter().filter_map(|part| match part {
+                        StrPart::Expr(e) => Some(e),
+                        StrPart::Lit(_) => None,
+                    });
+                    pieces.push(self.spill_before_any(piece, later, out));
+                }
+                return Expr::Concat(pieces);
+            }
+            E::Call(callee, args, _) => {
+                let callee = self.expr(callee, out);
+                let callee = self.spill_before_any(callee, args.iter(), out);
+                let mut lowered = Vec::new();
+                for (i, arg) in args.iter().enumerate() {
+                    let arg = self.expr(arg, out);
+                    lowered.push(self.spill_before_any(arg, args[i + 1..].iter(), out));
+                }
+                return Expr::Call(Box::new(callee), lowered);
+            }
+            E::Index(operands, _) => {
+                let base = self.expr(&operands.0, out);
+                let base = self.spill_before(base, &operands.1, out);
+                let index = self.expr(&operands.1, out);
+                return Expr::Index(Box::new((base, index)));
+            }
+            E::ArrayLit(items, _) => {
+                let mut lowered = Vec::new();
+                for (i, item) in items.iter().enumerate() {
+                    let item = self.expr(item, out);
+                    lowered.push(self.spill_before_any(item, items[i + 1..].iter(), out));
+                }
+                return Expr::Array(lowered);
+            }
+            E::If(s) => {
+                let temp = self.temp();
+                out.push(Stmt::Let { var: Var::Temp(temp), ty: None, init: None });
+                let outer = self.yield_to.replace(temp);
+                let lowered = self.if_(s, out);
+                self.yield_to = outer;
+                out.push(lowered);
+                return Expr::Var(Var::Temp(temp));
+            }
+            E::And(operands) | E::Or(operands) => {
+                let lhs = self.expr(&operands.0, out);
+                let temp = self.temp();
+                out.push(Stmt::Let { var: Var::Temp(temp), ty: None, init: Some(lhs) });
+                let mut then = Vec::new();
+                let rhs = self.expr(&operands.1, &mut then);
+                then.push(Stmt::Assign { target: Expr::Var(Var::Temp(temp)), value: rhs });
+                let mut condition = Expr::Var(Var::Temp(temp));
+                if let E::Or(_) = expr {
+                    condition = Expr::Unary(UnaryOp::Not, Box::new(condition));
+                }
+                out.push(Stmt::If { condition, then, otherwise: Vec::new() });
+                return Expr::Var(Var::Temp(temp));
+            }
+            E::Unary(op, operand, _) => return Expr::Unary(*op, Box::new(self.expr(operand, out))),
+            E::Add(operands) => (BinOp::Add, operands),
+            E::Sub(operands) => (BinOp::Sub, operands),
+            E::Mul(operands) => (BinOp::Mul, operands),
+            E::Div(operands) => (BinOp::Div, operands),
+            E::Rem(operands) => (BinOp::Rem, operands),
+            E::Lt(operands) => (BinOp::Lt, operands),
+            E::Eq(operands) => (BinOp::Eq, operands),
+            E::Ne(operands) => (BinOp::Ne, operands),
+            E::Le(operands) => (BinOp::Le, operands),
+            E::Gt(operands) => (BinOp::Gt, operands),
+            E::Ge(operands) => (BinOp::Ge, operands),
+            E::BitAnd(operands) => (BinOp::BitAnd, operands),
+            E::BitOr(operands) => (BinOp::BitOr, operands),
+            E::BitXor(operands) => (BinOp::BitXor, operands),
+            E::Shl(operands) => (BinOp::Shl, operands),
+            E::Shr(operands) => (BinOp::Shr, operands),
+            E::Binary(op, operands) => (BinOp::Custom(op), operands),
+        };
+        let lhs = self.expr(&operands.0, out);
+        let lhs = self.spill_before(lhs, &operands.1, out);
+        let rhs = self.expr(&operands.1, out);
+        Expr::Binary(op, Box::new((lhs, rhs)))
+    }
+
+    /// Lowers the target of an assignment, ahead of the expressions in `later`. The
+    /// place itself is never spilled, as assigning a copy would leave it unchanged;
+    /// only what it's computed from is, like the pointer in `*p` or the index in `a[i]`.
+    fn place<'a>(&mut self, target: &'a ast::Expr<'a>, later: &[&ast::Expr], out: &mut Vec<Stmt<'a>>) -> Expr<'a> {
+        match target {
+            ast::Expr::Unary(UnaryOp::Deref, operand, _) => {
+                let operand = self.expr(operand, out);
+                let operand = self.spill_before_any(operand, later.iter().copied(), out);
+                Expr::Unary(UnaryOp::Deref, Box::new(operand))
+            }
+            ast::Expr::Index(operands, _) => {
+                let mut after_base = vec![&operands.1];
+                after_base.extend(later.iter().copied());
+                let base = self.place(&operands.0, &after_base, out);
+                let index = self.expr(&operands.1, out);
+                let index = self.spill_before_any(index, later.iter().copied(), out);
+                Expr::Index(Box::new((base, index)))
+            }
+            _ => self.expr(target, out),
+        }
+    }
+
+    /// Moves `value` into a temporary if lowering `next` hoists statements, so that
+    /// it's still evaluated first.
+    fn spill_before<'a>(&mut self, value: Expr<'a>, next: &ast::Expr, out: &mut Vec<Stmt<'a>>) -> Expr<'a> {
+        self.spill_before_any(value, std::iter::once(next), out)
+    }
+
+    fn spill_before_any<'a, 'b>(
+        &mut self,
+        value: Expr<'a>,
+        mut next: impl Iterator<Item = &'b ast::Expr<'b>>,
+        out: &mut Vec<Stmt<'a>>,
+    ) -> Expr<'a> {
+        if matches!(value, Expr::Num(_) | Expr::Str(_) | Expr::Bool(_) | Expr::Var(Var::Temp(_)))
+            || !next.any(hoists)
+        {
+            return value;
+        }
+        let temp = self.temp();
+        out.push(Stmt::Let { var: Var::Temp(temp), ty: None, init: Some(value) });
+        Expr::Var(Var::Temp(temp))
+    }
+}
+
+/// Whether lowering `expr` pushes statements in front of it.
+fn hoists(expr: &ast::Expr) -> bool {
+    use ast::Expr as E;
+    match expr {
+        E::Num(_) | E::Str(_) | E::Bool(_) | E::Name(_) | E::SelfRef(_) | E::Lambda { .. } => false,
+        E::If(_) | E::And(_) | E::Or(_) => true,
+        E::Interp(parts, _) => parts.iter().any(|part| matches!(part, StrPart::Expr(e) if hoists(e))),
+        E::Call(callee, args, _) => hoists(callee) || args.iter().any(hoists),
+        E::ArrayLit(items, _) => items.iter().any(hoists),
+        E::Unary(_, operand, _) => hoists(operand),
+        E::Add(operands) | E::Sub(operands) | E::Mul(operands) | E::Div(operands) | E::Rem(operands)
+        | E::Lt(operands) | E::Eq(operands) | E::Ne(operands) | E::Le(operands) | E::Gt(operands) | E::Ge(operands)
+        | E::BitAnd(operands) | E::BitOr(operands) | E::BitXor(operands) | E::Shl(operands) | E::Shr(operands)
+        | E::Binary(_, operands) | E::Index(operands, _) => hoists(&operands.0) || hoists(&operands.1),
+    }
+}
+
+impl std::fmt::Display for Var<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Var::Named(name) => write!(f, "{name}"),
+            Var::Temp(n) => write!(f, "%{n}"),
+        }
+    }
+}
+
+impl Stmt<'_> {
+    /// Renders the statement the way [`ast::Stmt::to_sexpr`] does.
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        stmt(&mut out, self);
+        out
+    }
+}
+
+impl Expr<'_> {
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        expr(&mut out, self);
+        out
+    }
+}
+
+fn stmt(out: &mut String, s: &Stmt) {
+    match s {
+        Stmt::Let { var, ty, init } => {
+            write!(out, "(let {var} ").unwrap();
+            match ty {
+                Some(ty) => out.push_str(&ty.to_sexpr()),
+                None => out.push('_'),
+            }
+            out.push(' ');
+            match init {
+                Some(init) => expr(out, init),
+                None => out.push('_'),
+            }
+            out.push(')');
+        }
+        Stmt::Assign { target, value } => {
+            out.push_str("(assign ");
+            expr(out, target);
+            out.push(' ');
+            expr(out, value);
+            out.push(')');
+        }
+        Stmt::Expr(e) => expr(out, e),
+        Stmt::If { condition, then, otherwise } => {
+            out.push_str("(if ");
+            expr(out, condition);
+            out.push(' ');
+            block(out, then);
+            if !otherwise.is_empty() {
+                out.push(' ');
+                block(out, otherwise);
+            }
+            out.push(')');
+        }
+        Stmt::Block(items) => block(out, items),
+        Stmt::Return(value) => {
+            out.push_str("(ret ");
+            expr(out, value);
+            out.push(')');
+        }
+        Stmt::Macro(mac) => {
+            write!(out, "(macro {}", mac.name).unwrap();
+            for arg in &mac.args {
+                write!(out, " {arg}").unwrap();
+            }
+            out.push(')');
+        }
+        Stmt::Loop { label, body } => {
+            out.push_str("(loop ");
+            if let Some(label) = label {
+                write!(out, "{label} ").unwrap();
+            }
+            block(out, body);
+            out.push(')');
+        }
+        Stmt::Break(None) => out.push_str("(break)"),
+        Stmt::Break(Some(label)) => write!(out, "(break {label})").unwrap(),
+        Stmt::Continue(None) => out.push_str("(continue)"),
+        Stmt::Continue(Some(label)) => write!(out, "(continue {label})").unwrap(),
+        Stmt::Func { name, body, .. } => {
+            write!(out, "(fn {name} ").unwrap();
+            block(out, body);
+            out.push(')');
+        }
+    }
+}
+
+fn block(out: &mut String, items: &[Stmt]) {
+    out.push_str("(block");
+    for item in items {
+        out.push(' ');
+        stmt(out, item);
+    }
+    out.push(')');
+}
+
+fn expr(out: &mut String, e: &Expr) {
+    let (head, operands): (&str, Vec<&Expr>) = match e {
+        Expr::Num(lit) => {
+            out.push_str(lit.token.content());
+            return;
+        }
+        Expr::Bool(token) | Expr::SelfRef(token) => {
+            out.push_str(token.content());
+            return;
+        }
+        Expr::Str(token) => {
+            write!(out, "{:?}", token.content()).unwrap();
+            return;
+        }
+        Expr::Var(var) => {
+            write!(out, "{var}").unwrap();
+            return;
+        }
+        Expr::Call(callee, args) => ("call", std::iter::once(&**callee).chain(args).collect()),
+        Expr::Index(operands) => ("index", vec![&operands.0, &operands.1]),
+        Expr::Array(items) => ("array", items.iter().collect()),
+        Expr::Binary(op, operands) => {
+            let op = match op {
+                BinOp::Add => "add",
+                BinOp::Sub => "sub",
+                BinOp::Mul => "mul",
+                BinOp::Div => "div",
+                BinOp::Rem => "rem",
+                BinOp::Lt => "lt",
+                BinOp::Eq => "eq",
+                BinOp::Ne => "ne",
+                BinOp::Le => "le",
+                BinOp::Gt => "gt",
+                BinOp::Ge => "ge",
+                BinOp::BitAnd => "bitand",
+                BinOp::BitOr => "bitor",
+                BinOp::BitXor => "bitxor",
+                BinOp::Shl => "shl",
+                BinOp::Shr => "shr",
+                BinOp::Custom(op) => op.content(),
+            };
+            (op, vec![&operands.0, &operands.1])
+        }
+        Expr::Unary(op, operand) => {
+            let op = match op {
+                UnaryOp::Neg => "neg",
+                UnaryOp::Not => "not",
+                UnaryOp::Deref => "deref",
+                UnaryOp::Addr => "addr",
+            };
+            (op, vec![&**operand])
+        }
+        Expr::Concat(pieces) => ("concat", pieces.iter().collect()),
+        Expr::Lambda(ty, body) => {
+            write!(out, "(lambda {} ", ty.to_sexpr()).unwrap();
+            block(out, body);
+            out.push(')');
+            return;
+        }
+    };
+    out.push('(');
+    out.push_str(head);
+    for operand in operands {
+        out.push(' ');
+        expr(out, operand);
+    }
+    out.push(')');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse_program, tokenizer, Decl};
+
+    #[test]
+    fn test_desugar() {
+        let src = "fib (int) int {\n    let x = f(1) + if n < 2 { :1 } else { :2 } * 3\n    x = \"${x}!\"\n    if n < 2 { :0 }\n    'l: while a && b { continue 'l }\n    *f(x) = a || b\n    x = if n < 2 { :1 } else { :2 }\n    xs[f(x)] = a || b\n    :x < 1 || f(x) && y\n}";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let Decl::Func(_, func) = &decls[0] else {
+            panic!("expected a function");
+        };
+        let lowered = desugar_func(func).iter().map(Stmt::to_sexpr).collect::<Vec<_>>();
+        assert_eq!(lowered, [
+            // `f(1)` runs before the branches, so it's kept in a temporary.
+            "(let %0 _ (call f 1))",
+            "(let %1 _ _)",
+            "(if (lt n 2) (block (assign %1 1)) (block (assign %1 2)))",
+            "(let x _ (add %0 (mul %1 3)))",
+            "(assign x (concat x \"!\"))",
+            "(if (lt n 2) (block (ret 0)))",
+            // The condition is evaluated again on every iteration.
+            "(loop 'l (block (let %2 _ a) (if %2 (block (assign %2 b))) (if (not %2) (block (break))) (continue 'l)))",
+            // Only the pointer is spilled; the place it points to is assigned.
+            "(let %3 _ (call f x))",
+            "(let %4 _ a)",
+            "(if (not %4) (block (assign %4 b)))",
+            "(assign (deref %3) %4)",
+            // A variable is assigned as it is, not through a copy.
+            "(let %5 _ _)",
+            "(if (lt n 2) (block (assign %5 1)) (block (assign %5 2)))",
+            "(assign x %5)",
+            // So is the array an element is assigned in; only the index is spilled.
+            "(let %6 _ (call f x))",
+            "(let %7 _ a)",
+            "(if (not %7) (block (assign %7 b)))",
+            "(assign (index xs %6) %7)",
+            // `f(x) && y` only runs when `x < 1` is false.
+            "(let %8 _ (lt x 1))",
+            "(if (not %8) (block (let %9 _ (call f x)) (if %9 (block (assign %9 y))) (assign %8 %9)))",
+            "(ret %8)",
+        ]);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{Error, ErrorInner, Span};
+
+/// How much a [`Diagnostic`] matters. Only errors fail a compilation; the rest are
+/// reported and compilation goes on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    /// Extra context, like where something was declared.
+    Note,
+    /// A suggestion for fixing the problem.
+    Help,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        })
+    }
+}
+
+/// A message about the source with a [`Severity`], and notes or help attached to it.
+/// Parse and type errors are [`Error`]s and convert into error diagnostics, their
+/// notes becoming children; lints produce warnings directly.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// Notes and help about this diagnostic, each pointing at its own span.
+    pub children: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, span: Span, message: impl Into<String>) -> Self {
+        Self { severity, message: message.into(), span, children: Vec::new() }
+    }
+
+    #[inline]
+    pub fn error(span: Span, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, span, message)
+    }
+
+    #[inline]
+    pub fn warning(span: Span, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, span, message)
+    }
+
+    /// Adds a note pointing at `span`.
+    pub fn with_note(mut self, span: Span, note: impl Into<String>) -> Self {
+        self.children.push(Self::new(Severity::Note, span, note));
+        self
+    }
+
+    /// Adds a suggestion pointing at `span`.
+    pub fn with_help(mut self, span: Span, help: impl Into<String>) -> Self {
+        self.children.push(Self::new(Severity::Help, span, help));
+        self
+    }
+
+    /// Whether this diagnostic fails compilation.
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Turns a warning into an error, for drivers with a `-Werror` switch. Other
+    /// severities are left as they are.
+    pub fn deny_warnings(mut self) -> Self {
+        if self.severity == Severity::Warning {
+            self.severity = Severity::Error;
+        }
+        self
+    }
+}
+
+impl From<Error> for Diagnostic {
+    fn from(error: Error) -> Self {
+        let ErrorInner { message, span, notes, .. } = error.into_inner();
+        notes.into_iter().fold(Self::error(span, message), |diagnostic, (span, note)| diagnostic.with_note(span, note))
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    /// `line:column: severity: message`, then each child the same way on its own
+    /// indented line.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.span.start, self.severity, self.message)?;
+        for child in &self.children {
+            write!(f, "\n    {child}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Location;
+
+    #[test]
+    fn test_diagnostic() {
+        let span = |line, column| {
+            let location = Location { line, column, ..Location::zero() };
+            Span { start: location, end: location }
+        };
+        let error = Error::new(span(1, 8), "expected `int`, found `char*`").with_note(span(0, 4), "declared here");
+        let diagnostic = Diagnostic::from(error);
+        assert!(diagnostic.is_error());
+        assert_eq!(diagnostic.to_string(), "1:8: error: expected `int`, found `char*`\n    0:4: note: declared here");
+
+        let warning = Diagnostic::warning(span(0, 4), "unused variable `x`").with_help(span(0, 4), "rename it to `_x`");
+        assert!(!warning.is_error());
+        assert_eq!(warning.children[0].severity, Severity::Help);
+        let denied = warning.deny_warnings();
+        assert!(denied.is_error());
+        assert_eq!(denied.to_string(), "0:4: error: unused variable `x`\n    0:4: help: rename it to `_x`");
+    }
+}
@@ -0,0 +1,201 @@
+//! GraphViz rendering of the syntax tree, see [`to_dot`].
+
+use crate::ast::{Block, Decl, Expr, FnType, Func, If, Macro, Stmt, StrPart, Type, UnaryOp};
+use crate::visit::{self, Visit};
+use std::fmt::Write;
+
+/// Renders `decl` as a GraphViz digraph, one node per syntax node labelled with its
+/// kind and, for leaves and named nodes, its source text. Pipe the result through
+/// `dot -Tpng` to get an image of the parse tree.
+pub fn to_dot(decl: &Decl) -> String {
+    let mut dot = Dot {
+        out: String::from("digraph ast {\n    node [shape=box, fontname=monospace];\n"),
+        parents: Vec::new(),
+        next: 0,
+    };
+    dot.visit_decl(decl);
+    dot.out.push_str("}\n");
+    dot.out
+}
+
+struct Dot {
+    out: String,
+    /// The node ids of the enclosing nodes, innermost last.
+    parents: Vec<usize>,
+    next: usize,
+}
+
+impl Dot {
+    /// Emits a node below the current parent and makes it the parent while `children`
+    /// runs.
+    fn node(&mut self, kind: &str, lexeme: Option<&str>, children: impl FnOnce(&mut Self)) {
+        let id = self.next;
+        self.next += 1;
+
+        write!(self.out, "    n{id} [label=\"{kind}").unwrap();
+        if let Some(lexeme) = lexeme {
+            self.out.push_str("\\n");
+            for c in lexeme.chars() {
+                match c {
+                    '"' => self.out.push_str("\\\""),
+                    '\\' => self.out.push_str("\\\\"),
+                    '\n' => self.out.push_str("\\n"),
+                    c => self.out.push(c),
+                }
+            }
+        }
+        self.out.push_str("\"];\n");
+        if let Some(parent) = self.parents.last() {
+            writeln!(self.out, "    n{parent} -> n{id};").unwrap();
+        }
+
+        self.parents.push(id);
+        children(self);
+        self.parents.pop();
+    }
+}
+
+impl<'ast> Visit<'ast> for Dot {
+    fn visit_decl(&mut self, decl: &'ast Decl<'ast>) {
+        let (kind, lexeme) = match decl {
+            Decl::Func(name, _) => ("Func", name),
+            Decl::Global { name, .. } => ("Global", name),
+            Decl::TypeAlias { name, .. } => ("TypeAlias", name),
+            Decl::Import(path, _) => ("Import", path),
+            Decl::Mod(name, _) => ("Mod", name),
+        };
+        self.node(kind, Some(lexeme.content()), |v| visit::walk_decl(v, decl));
+    }
+
+    fn visit_func(&mut self, func: &'ast Func<'ast>) {
+        for param in &func.generics {
+            self.node("TypeParam", Some(param.content()), |_| {});
+        }
+        visit::walk_func(self, func);
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type<'ast>) {
+        let (kind, lexeme) = match ty {
+            Type::Name(name) => ("Name", Some(name.content())),
+            Type::Ptr(..) => ("Ptr", None),
+            Type::Array(..) => ("Array", None),
+            // The function type's own node is added by `visit_fn_type`.
+            Type::Func(_) => return visit::walk_type(self, ty),
+            Type::Generic(name, ..) => ("Generic", Some(name.content())),
+        };
+        self.node(kind, lexeme, |v| visit::walk_type(v, ty));
+    }
+
+    fn visit_fn_type(&mut self, ty: &'ast FnType<'ast>) {
+        self.node("FnType", None, |v| visit::walk_fn_type(v, ty));
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt<'ast>) {
+        let (kind, lexeme) = match stmt {
+            Stmt::If(_) | Stmt::Block(_) | Stmt::Macro(_) => return visit::walk_stmt(self, stmt),
+            Stmt::Return(..) => ("Return", None),
+            Stmt::Assign { .. } => ("Assign", None),
+            Stmt::Expr(_) => ("ExprStmt", None),
+            Stmt::Let { name, .. } => ("Let", Some(name.content())),
+            Stmt::While(stmt) => ("While", stmt.label.as_ref().map(|label| label.content())),
+            Stmt::Break(label, _) => ("Break", label.as_ref().map(|label| label.content())),
+            Stmt::Continue(label, _) => ("Continue", label.as_ref().map(|label| label.content())),
+            Stmt::Func(name, _) => ("Func", Some(name.content())),
+        };
+        self.node(kind, lexeme, |v| visit::walk_stmt(v, stmt));
+    }
+
+    fn visit_block(&mut self, block: &'ast Block<'ast>) {
+        self.node("Block", None, |v| visit::walk_block(v, block));
+    }
+
+    fn visit_if(&mut self, stmt: &'ast If<'ast>) {
+        self.node("If", None, |v| visit::walk_if(v, stmt));
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro<'ast>) {
+        self.node("Macro", Some(mac.name.content()), |v| {
+            for arg in &mac.args {
+                v.node("Arg", Some(arg.content()), |_| {});
+            }
+        });
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr<'ast>) {
+        let (kind, lexeme) = match expr {
+            Expr::Num(lit) => ("Num", Some(lit.token.content())),
+            Expr::Str(token) => ("Str", Some(token.content())),
+            Expr::Bool(token) => ("Bool", Some(token.content())),
+            Expr::Name(token) => ("Name", Some(token.content())),
+            Expr::SelfRef(_) => ("SelfRef", None),
+            Expr::Interp(..) => ("Interp", None),
+            Expr::Call(..) => ("Call", None),
+            Expr::Index(..) => ("Index", None),
+            Expr::ArrayLit(..) => ("ArrayLit", None),
+            Expr::If(_) => return visit::walk_expr(self, expr),
+            Expr::Lambda { .. } => ("Lambda", None),
+            Expr::Add(_) => ("Add", Some("+")),
+            Expr::Sub(_) => ("Sub", Some("-")),
+            Expr::Mul(_) => ("Mul", Some("*")),
+            Expr::Div(_) => ("Div", Some("/")),
+            Expr::Rem(_) => ("Rem", Some("%")),
+            Expr::Lt(_) => ("Lt", Some("<")),
+            Expr::Eq(_) => ("Eq", Some("==")),
+            Expr::Ne(_) => ("Ne", Some("!=")),
+            Expr::Le(_) => ("Le", Some("<=")),
+            Expr::Gt(_) => ("Gt", Some(">")),
+            Expr::Ge(_) => ("Ge", Some(">=")),
+            Expr::BitAnd(_) => ("BitAnd", Some("&")),
+            Expr::BitOr(_) => ("BitOr", Some("|")),
+            Expr::BitXor(_) => ("BitXor", Some("^")),
+            Expr::Shl(_) => ("Shl", Some("<<")),
+            Expr::Shr(_) => ("Shr", Some(">>")),
+            Expr::And(_) => ("And", Some("&&")),
+            Expr::Or(_) => ("Or", Some("||")),
+            Expr::Binary(op, _) => ("Binary", Some(op.content())),
+            Expr::Unary(op, ..) => ("Unary", Some(match op {
+                UnaryOp::Neg => "-",
+                UnaryOp::Not => "!",
+                UnaryOp::Deref => "*",
+                UnaryOp::Addr => "&",
+            })),
+        };
+        self.node(kind, lexeme, |v| visit::walk_expr(v, expr));
+    }
+
+    fn visit_str_part(&mut self, part: &'ast StrPart<'ast>) {
+        match part {
+            StrPart::Lit(lit) => self.node("Lit", Some(lit.content()), |_| {}),
+            StrPart::Expr(_) => visit::walk_str_part(self, part),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_dot;
+    use crate::ast::{parse_decl, tokenizer};
+
+    #[test]
+    fn test_to_dot() {
+        let tok = tokenizer("fib<T> (int) int {\n    args! n\n    :if 2 < 1 { :\"a\\\"${1}\" }\n}");
+        let decl = parse_decl(&tok).unwrap_or_else(|_| panic!("expected a declaration"));
+        let dot = to_dot(&decl);
+
+        assert!(dot.starts_with("digraph ast {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("    n0 [label=\"Func\\nfib\"];\n"));
+        assert!(dot.contains("    n1 [label=\"TypeParam\\nT\"];\n    n0 -> n1;\n"));
+        assert!(dot.contains("[label=\"Macro\\nargs\"];"));
+        assert!(dot.contains("[label=\"Lt\\n<\"];"));
+        assert!(dot.contains("[label=\"Lit\\na\\\"\"];"));
+
+        let nodes = dot.matches("[label=").count();
+        let edges = dot.matches(" -> ").count();
+        assert_eq!(edges, nodes - 1);
+        // Func, T, FnType, two `int`s, the body, the macro and its argument, return,
+        // if, `<` with its operands, the inner block and return, the string, its
+        // literal part and `1`.
+        assert_eq!(nodes, 18);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{Location, Span, TokenKind, Tokenizer, TokenizerConfig, Error};
+use std::ops::Range;
+
+/// A token kept by an [`IncrementalLexer`]; its text lives in the lexer's buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LexedToken {
+    pub span: Span,
+    pub kind: TokenKind,
+}
+
+/// An owned source buffer whose token list is kept up to date across edits,
+/// re-lexing only the neighbourhood of each change.
+pub struct IncrementalLexer {
+    text: String,
+    config: TokenizerConfig,
+    tokens: Vec<LexedToken>,
+    errors: Vec<Error>,
+}
+
+impl IncrementalLexer {
+    #[inline]
+    pub fn new(text: impl Into<String>) -> Self {
+        Self::new_with_config(text, TokenizerConfig::default())
+    }
+
+    pub fn new_with_config(text: impl Into<String>, config: TokenizerConfig) -> Self {
+        let mut out = Self {
+            text: text.into(),
+            config,
+            tokens: Vec::new(),
+            errors: Vec::new(),
+        };
+        out.relex(0, Location::zero(), 0, 0, 0);
+        out
+    }
+
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    #[inline]
+    pub fn tokens(&self) -> &[LexedToken] {
+        &self.tokens
+    }
+
+    #[inline]
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    #[inline]
+    pub fn token_text(&self, token: &LexedToken) -> &str {
+        &self.text[token.span.start.index..token.span.end.index]
+    }
+
+    /// Replaces the bytes in `range` with `new_text` and re-lexes from the last token
+    /// boundary before the edit until the new tokens line up with the old ones again.
+    /// Returns the indices in [`IncrementalLexer::tokens`] that were re-lexed.
+    pub fn apply_edit(&mut self, range: Range<usize>, new_text: &str) -> Result<Range<usize>, Error> {
+        if range.start > range.end
+            || range.end > self.text.len()
+            || !self.text.is_char_boundary(range.start)
+            || !self.text.is_char_boundary(range.end)
+        {
+            return Err(Error::at(self.location_of(range.start.min(self.text.len())), format!("invalid edit range {}..{}", range.start, range.end)));
+        }
+
+        // Tokens ending before the edit are untouched, but the last of them is re-lexed
+        // too in case the edit glues onto it (`1.` + `5`).
+        let before = self.tokens.partition_point(|t| t.span.end.index < range.start);
+        let first = before.saturating_sub(1);
+        let restart = match first {
+            0 => Location::zero(),
+            _ => self.tokens[first].span.start,
+        };
+
+        let removed = &self.text[range.clone()];
+        let delta = new_text.len() as isize - removed.len() as isize;
+        let line_delta = new_text.matches('\n').count() as isize - removed.matches('\n').count() as isize;
+        self.text.replace_range(range.clone(), new_text);
+
+        let edit_end = range.start + new_text.len();
+        let relexed = self.relex(first, restart, edit_end, delta, line_delta);
+        Ok(first..first + relexed)
+    }
+
+    /// Re-lexes from `restart`, replacing `self.tokens[first..]` up to the first old token
+    /// that the new lexing reproduces (shifted by `delta`) on a line after the edit.
+    /// The reusable old tail, and its errors, are moved by the edit's byte and line deltas.
+    /// Returns the number of fresh tokens.
+    fn relex(&mut self, first: usize, restart: Location, edit_end: usize, delta: isize, line_delta: isize) -> usize {
+        let tok = Tokenizer::new_with_config(&self.text, self.config.clone());
+        let mut edit_end_loc = restart;
+        for c in self.text[restart.index..edit_end].chars() {
+            tok.adv(&mut edit_end_loc, c);
+        }
+
+        let mut fresh = Vec::new();
+        let mut errors = Vec::new();
+        let mut old = first;
+        let mut synced = None;
+        tok.seek(restart);
+        loop {
+            match tok.next_token() {
+                Ok(token) if token.kind == TokenKind::Eof => break,
+                Ok(token) => {
+                    let span = token.span;
+                    if span.start.index >= edit_end && span.start.line > edit_end_loc.line {
+                        let target = span.start.index as isize - delta;
+                        while old < self.tokens.len() && (self.tokens[old].span.start.index as isize) < target {
+                            old += 1;
+                        }
+                        let reusable = self.tokens.get(old).is_some_and(|t| {
+                            t.span.start.index as isize == target
+                                && t.span.len() == span.len()
+                                && t.kind == token.kind
+                        });
+                        if reusable {
+                            synced = Some(old);
+                            break;
+                        }
+                    }
+                    fresh.push(LexedToken { span, kind: token.kind });
+                }
+                Err(e) => {
+                    tok.skip_invalid(e.location());
+                    errors.push(e);
+                }
+            }
+        }
+
+        let count = fresh.len();
+        let tail = synced.unwrap_or(self.tokens.len());
+        let tail_start = self.tokens.get(tail).map(|t| t.span.start.index);
+        let shift = |l: &mut Location| {
+            l.index = (l.index as isize + delta) as usize;
+            l.line = (l.line as isize + line_delta) as usize;
+        };
+
+        for token in &mut self.tokens[tail..] {
+            shift(&mut token.span.start);
+            shift(&mut token.span.end);
+        }
+        self.tokens.splice(first..tail, fresh);
+
+        self.errors.retain(|e| {
+            e.location().index < restart.index
+                || tail_start.is_some_and(|start| e.location().index >= start)
+        });
+        for error in &mut self.errors {
+            if error.location().index >= restart.index {
+                shift(&mut error.span.start);
+                shift(&mut error.span.end);
+            }
+        }
+        let at = self.errors.partition_point(|e| e.location().index < restart.index);
+        self.errors.splice(at..at, errors);
+        count
+    }
+
+    fn location_of(&self, index: usize) -> Location {
+        let tok = Tokenizer::new_with_config(&self.text, self.config.clone());
+        let mut location = Location::zero();
+        for c in self.text[..index].chars() {
+            tok.adv(&mut location, c);
+        }
+        location
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IncrementalLexer;
+    use crate::Tokenizer;
+
+    fn assert_fresh(lexer: &IncrementalLexer) {
+        let (tokens, errors) = Tokenizer::new(lexer.text()).tokenize_all();
+        let spans = tokens.iter().map(|t| (t.span.start, t.span.end, t.kind)).collect::<Vec<_>>();
+        let cached = lexer.tokens().iter().map(|t| (t.span.start, t.span.end, t.kind)).collect::<Vec<_>>();
+        assert_eq!(spans, cached);
+        assert_eq!(
+            errors.iter().map(|e| e.location()).collect::<Vec<_>>(),
+            lexer.errors().iter().map(|e| e.location()).collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn test_apply_edit() {
+        let src = "fn main() {\n    a + b\n}\n\nfn other() {\n    \"s\" * 2\n}\n";
+        let mut lexer = IncrementalLexer::new(src);
+        assert_fresh(&lexer);
+
+        let changed = lexer.apply_edit(16..17, "xyz\n    q").unwrap();
+        assert_fresh(&lexer);
+        assert!(changed.len() < lexer.tokens().len() / 2);
+
+        let at = lexer.text().find("b\n").unwrap();
+        lexer.apply_edit(at..at + 1, "").unwrap();
+        assert_fresh(&lexer);
+
+        lexer.apply_edit(0..0, "/* ").unwrap();
+        assert_fresh(&lexer);
+        assert_eq!(lexer.errors().len(), 1);
+        lexer.apply_edit(0..3, "").unwrap();
+        assert_fresh(&lexer);
+        assert!(lexer.errors().is_empty());
+
+        let end = lexer.text().len();
+        lexer.apply_edit(end..end, "\"open").unwrap();
+        assert_fresh(&lexer);
+        assert!(lexer.apply_edit(0..10_000, "").is_err());
+    }
+}
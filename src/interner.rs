@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// An interned string, see [`Interner`]. Comparing symbols is a `u32` comparison.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    #[inline]
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+/// Deduplicates strings such as identifier names so each distinct one is stored once
+/// and can be referred to by a [`Symbol`].
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl Interner {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(s) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        let s: Rc<str> = Rc::from(s);
+        self.strings.push(s.clone());
+        self.symbols.insert(s, symbol);
+        symbol
+    }
+
+    /// The symbol for `s` if it has been interned, without interning it.
+    #[inline]
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.symbols.get(s).copied()
+    }
+
+    /// The string behind `symbol`. Panics if `symbol` came from another interner.
+    #[inline]
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Interner;
+    use crate::Tokenizer;
+
+    #[test]
+    fn test_intern() {
+        let mut interner = Interner::new();
+        let tok = Tokenizer::new("a b a c b a");
+        let symbols = tok.iter()
+            .map(|t| t.unwrap().intern(&mut interner))
+            .collect::<Vec<_>>();
+        assert_eq!(interner.len(), 3);
+        assert_eq!(symbols[0], symbols[2]);
+        assert_eq!(symbols[1], symbols[4]);
+        assert_ne!(symbols[0], symbols[1]);
+        assert_eq!(interner.resolve(symbols[3]), "c");
+        assert_eq!(interner.get("b"), Some(symbols[1]));
+        assert_eq!(interner.get("d"), None);
+    }
+}
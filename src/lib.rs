@@ -1,8 +1,31 @@
 #![allow(unused)]
 
 mod token;
+mod diagnostic;
 mod tokenizer;
+mod parse_result;
 mod llvm;
+mod incremental;
+mod source_map;
+mod pattern;
+mod interner;
+pub mod ast;
+pub mod visit;
+pub mod cst;
+pub mod desugar;
+pub mod sema;
+mod sexpr;
+mod dot;
+mod comments;
+mod metrics;
+#[cfg(feature = "arena")]
+pub mod arena;
 
-pub use token::{Location, Span, Token, Error};
-pub use tokenizer::{Tokenizer};
+pub use token::{FileId, Location, Span, Token, TokenKind, Trivia, TriviaKind, Number, NumberKind, StringParts, StringPart, Error, ErrorInner};
+pub use diagnostic::{Diagnostic, Severity};
+pub use interner::{Interner, Symbol};
+pub use pattern::{TokenPattern, Ident, Chars, Blanks, Opt};
+pub use source_map::{SourceManager, SourceMap, Line};
+pub use incremental::{IncrementalLexer, LexedToken};
+pub use tokenizer::{Checkpoint, Tokenizer, TokenizerConfig, Tokens, is_xid_ident_start, is_xid_ident_continue};
+pub use parse_result::{ParseResult, ParseError, ParseResultExt};
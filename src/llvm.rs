@@ -124,7 +124,7 @@ mod ffi {
 
         pub fn width(&self) -> u32 {
             unsafe {
-                LLVMGetIntTypeWidth(self.0).into()
+                LLVMGetIntTypeWidth(self.0)
             }
         }
     }
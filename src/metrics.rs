@@ -0,0 +1,218 @@
+//! Size and complexity figures for the syntax tree, see [`metrics`].
+
+use crate::ast::{Block, Decl, Expr, FnType, If, Macro, Stmt, StrPart, Type};
+use crate::visit::{self, Visit};
+use crate::Token;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Nodes by kind, named after their variants like the nodes of [`to_dot`](crate::ast::to_dot),
+    /// e.g. `"Let"` or `"Add"`.
+    pub nodes: BTreeMap<&'static str, usize>,
+    /// How deep blocks nest, a function body being 1.
+    pub max_depth: usize,
+    pub functions: usize,
+    /// The cyclomatic complexity of each function in order, one plus the number of
+    /// `if`s and `while`s in its body. Functions declared inside another come after it,
+    /// and don't add to its complexity.
+    pub complexity: Vec<(String, usize)>,
+}
+
+/// Computes the [`Metrics`] of `decl`. Use [`Metrics::add`] to cover a whole program.
+pub fn metrics(decl: &Decl) -> Metrics {
+    let mut metrics = Metrics::default();
+    metrics.add(decl);
+    metrics
+}
+
+impl Metrics {
+    /// Counts `decl` in with what's been measured so far.
+    pub fn add(&mut self, decl: &Decl) {
+        Measure { metrics: self, depth: 0, function: None }.visit_decl(decl);
+    }
+
+    fn count(&mut self, kind: &'static str) {
+        *self.nodes.entry(kind).or_default() += 1;
+    }
+}
+
+struct Measure<'m> {
+    metrics: &'m mut Metrics,
+    depth: usize,
+    /// The index in `complexity` of the function being measured.
+    function: Option<usize>,
+}
+
+impl Measure<'_> {
+    fn func(&mut self, name: &Token, walk: impl FnOnce(&mut Self)) {
+        self.metrics.functions += 1;
+        self.metrics.complexity.push((name.content().to_string(), 1));
+        let outer = self.function.replace(self.metrics.complexity.len() - 1);
+        walk(self);
+        self.function = outer;
+    }
+
+    fn branch(&mut self) {
+        if let Some(function) = self.function {
+            self.metrics.complexity[function].1 += 1;
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Measure<'_> {
+    fn visit_decl(&mut self, decl: &'ast Decl<'ast>) {
+        let kind = match decl {
+            Decl::Func(name, _) => {
+                self.metrics.count("Func");
+                return self.func(name, |v| visit::walk_decl(v, decl));
+            }
+            Decl::Global { .. } => "Global",
+            Decl::TypeAlias { .. } => "TypeAlias",
+            Decl::Import(..) => "Import",
+            Decl::Mod(..) => "Mod",
+        };
+        self.metrics.count(kind);
+        visit::walk_decl(self, decl);
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type<'ast>) {
+        match ty {
+            Type::Name(_) => self.metrics.count("Name"),
+            Type::Ptr(..) => self.metrics.count("Ptr"),
+            Type::Array(..) => self.metrics.count("Array"),
+            // Counted by `visit_fn_type`.
+            Type::Func(_) => {}
+            Type::Generic(..) => self.metrics.count("Generic"),
+        }
+        visit::walk_type(self, ty);
+    }
+
+    fn visit_fn_type(&mut self, ty: &'ast FnType<'ast>) {
+        self.metrics.count("FnType");
+        visit::walk_fn_type(self, ty);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt<'ast>) {
+        match stmt {
+            Stmt::If(_) | Stmt::Block(_) | Stmt::Macro(_) => {}
+            Stmt::Return(..) => self.metrics.count("Return"),
+            Stmt::Assign { .. } => self.metrics.count("Assign"),
+            Stmt::Expr(_) => self.metrics.count("ExprStmt"),
+            Stmt::Let { .. } => self.metrics.count("Let"),
+            Stmt::While(_) => {
+                self.metrics.count("While");
+                self.branch();
+            }
+            Stmt::Break(..) => self.metrics.count("Break"),
+            Stmt::Continue(..) => self.metrics.count("Continue"),
+            Stmt::Func(name, _) => {
+                self.metrics.count("Func");
+                return self.func(name, |v| visit::walk_stmt(v, stmt));
+            }
+        }
+        visit::walk_stmt(self, stmt);
+    }
+
+    fn visit_block(&mut self, block: &'ast Block<'ast>) {
+        self.metrics.count("Block");
+        self.depth += 1;
+        self.metrics.max_depth = self.metrics.max_depth.max(self.depth);
+        visit::walk_block(self, block);
+        self.depth -= 1;
+    }
+
+    fn visit_if(&mut self, stmt: &'ast If<'ast>) {
+        self.metrics.count("If");
+        self.branch();
+        visit::walk_if(self, stmt);
+    }
+
+    fn visit_macro(&mut self, _mac: &'ast Macro<'ast>) {
+        self.metrics.count("Macro");
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr<'ast>) {
+        match expr {
+            Expr::Num(_) => self.metrics.count("Num"),
+            Expr::Str(_) => self.metrics.count("Str"),
+            Expr::Bool(_) => self.metrics.count("Bool"),
+            Expr::Name(_) => self.metrics.count("Name"),
+            Expr::SelfRef(_) => self.metrics.count("SelfRef"),
+            Expr::Interp(..) => self.metrics.count("Interp"),
+            Expr::Call(..) => self.metrics.count("Call"),
+            Expr::Index(..) => self.metrics.count("Index"),
+            Expr::ArrayLit(..) => self.metrics.count("ArrayLit"),
+            // Counted by `visit_if`.
+            Expr::If(_) => {}
+            // Its branches count towards the enclosing function.
+            Expr::Lambda { .. } => self.metrics.count("Lambda"),
+            Expr::Add(_) => self.metrics.count("Add"),
+            Expr::Sub(_) => self.metrics.count("Sub"),
+            Expr::Mul(_) => self.metrics.count("Mul"),
+            Expr::Div(_) => self.metrics.count("Div"),
+            Expr::Rem(_) => self.metrics.count("Rem"),
+            Expr::Lt(_) => self.metrics.count("Lt"),
+            Expr::Eq(_) => self.metrics.count("Eq"),
+            Expr::Ne(_) => self.metrics.count("Ne"),
+            Expr::Le(_) => self.metrics.count("Le"),
+            Expr::Gt(_) => self.metrics.count("Gt"),
+            Expr::Ge(_) => self.metrics.count("Ge"),
+            Expr::BitAnd(_) => self.metrics.count("BitAnd"),
+            Expr::BitOr(_) => self.metrics.count("BitOr"),
+            Expr::BitXor(_) => self.metrics.count("BitXor"),
+            Expr::Shl(_) => self.metrics.count("Shl"),
+            Expr::Shr(_) => self.metrics.count("Shr"),
+            Expr::And(_) => self.metrics.count("And"),
+            Expr::Or(_) => self.metrics.count("Or"),
+            Expr::Binary(..) => self.metrics.count("Binary"),
+            Expr::Unary(..) => self.metrics.count("Unary"),
+        }
+        visit::walk_expr(self, expr);
+    }
+
+    fn visit_str_part(&mut self, part: &'ast StrPart<'ast>) {
+        if let StrPart::Lit(_) = part {
+            self.metrics.count("Lit");
+        }
+        visit::walk_str_part(self, part);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse_program, tokenizer};
+
+    #[test]
+    fn test_metrics() {
+        let src = "fib (int) int {\n    :if 2 < 1 { :0 } else { :if 1 < 2 { :1 } else { :2 } }\n}\nmain () int {\n    { :fib(3) }\n}\nlimit int = 3\n";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+
+        let fib = metrics(&decls[0]);
+        assert_eq!(fib.functions, 1);
+        assert_eq!(fib.complexity, [("fib".to_string(), 3)]);
+        assert_eq!(fib.max_depth, 3);
+        assert_eq!(fib.nodes["If"], 2);
+        assert_eq!(fib.nodes["Lt"], 2);
+        assert_eq!(fib.nodes["Return"], 5);
+
+        let mut program = Metrics::default();
+        for decl in &decls {
+            program.add(decl);
+        }
+        assert_eq!(program.functions, 2);
+        assert_eq!(program.complexity, [("fib".to_string(), 3), ("main".to_string(), 1)]);
+        assert_eq!(program.max_depth, 3);
+        assert_eq!(program.nodes["Global"], 1);
+        assert_eq!(program.nodes["Call"], 1);
+        assert_eq!(program.nodes["Block"], 7);
+
+        // A nested function's branches count towards its own complexity.
+        let src = "outer () int {\n    inner () int { if a { } }\n    while b { }\n    if c { }\n}";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let nested = metrics(&decls[0]);
+        assert_eq!(nested.functions, 2);
+        assert_eq!(nested.complexity, [("outer".to_string(), 3), ("inner".to_string(), 2)]);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{Error, Tokenizer};
+
+/// The outcome of a parser: `Ok` when the input matched, or a [`ParseError`] saying
+/// whether it didn't match at all or matched partway and then failed.
+pub type ParseResult<T> = Result<T, ParseError>;
+
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    /// The input doesn't start with this construct, so another alternative can be
+    /// tried. Wrap the parser in [`Tokenizer::try_with`] to rewind anything it consumed.
+    NoMatch,
+    /// The input started like this construct but is malformed.
+    Invalid(Error),
+}
+
+impl ParseError {
+    /// The hard error, if there is one.
+    #[inline]
+    pub fn into_error(self) -> Option<Error> {
+        match self {
+            Self::NoMatch => None,
+            Self::Invalid(error) => Some(error),
+        }
+    }
+}
+
+impl From<Error> for ParseError {
+    #[inline]
+    fn from(error: Error) -> Self {
+        Self::Invalid(error)
+    }
+}
+
+/// Combinators for chaining parsers on a [`ParseResult`].
+pub trait ParseResultExt<T> {
+    /// Turns a no-match into `Ok(None)` while keeping hard errors, for use with `?`.
+    fn optional(self) -> Result<Option<T>, ParseError>;
+
+    /// Turns a no-match into a hard error listing what `tok` [expected](Tokenizer::expected).
+    fn required(self, tok: &Tokenizer) -> ParseResult<T>;
+
+    /// Runs `f` as the next alternative if this parser didn't match.
+    fn or_else_parse(self, f: impl FnOnce() -> ParseResult<T>) -> ParseResult<T>;
+}
+
+impl<T> ParseResultExt<T> for ParseResult<T> {
+    #[inline]
+    fn optional(self) -> Result<Option<T>, ParseError> {
+        match self {
+            Ok(value) => Ok(Some(value)),
+            Err(ParseError::NoMatch) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    #[inline]
+    fn required(self, tok: &Tokenizer) -> ParseResult<T> {
+        match self {
+            Err(ParseError::NoMatch) => Err(ParseError::Invalid(tok.expectation_error())),
+            other => other,
+        }
+    }
+
+    #[inline]
+    fn or_else_parse(self, f: impl FnOnce() -> ParseResult<T>) -> ParseResult<T> {
+        match self {
+            Err(ParseError::NoMatch) => f(),
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digit(tok: &Tokenizer) -> ParseResult<u32> {
+        let token = tok.consume_while(|c| c.is_ascii_digit()).ok_or_else(|| tok.expected("digit"))?;
+        Ok(token.content().parse().unwrap())
+    }
+
+    #[test]
+    fn test_combinators() {
+        let tok = Tokenizer::new("x 12");
+        assert!(matches!(digit(&tok).optional(), Ok(None)));
+        let letter = tok.try_with(digit).or_else_parse(|| {
+            tok.consume("x").ok_or(ParseError::NoMatch)?;
+            Ok(0)
+        });
+        assert_eq!(letter.unwrap(), 0);
+        assert_eq!(digit(&tok).required(&tok).unwrap(), 12);
+
+        let error = digit(&tok).required(&tok).unwrap_err();
+        let error = error.into_error().unwrap();
+        assert_eq!(error.message, "expected digit, found end of input");
+        assert_eq!(error.location().index, 4);
+        assert!(ParseError::NoMatch.into_error().is_none());
+    }
+
+    #[test]
+    fn test_expectations() {
+        let tok = Tokenizer::new("a ) b");
+        tok.consume("a");
+        let _ = tok.try_with(digit).or_else_parse(|| Err(tok.expected("`(`")));
+        let error = tok.expectation_error();
+        assert_eq!(error.message, "expected digit or `(`, found `)`");
+        assert_eq!(error.expected, ["digit", "`(`"]);
+        assert_eq!(error.location().index, 2);
+
+        // Only the alternatives tried furthest in count.
+        let _ = tok.labelled("anything", |tok| tok.try_with(|tok| {
+            tok.consume(")");
+            Err::<(), _>(tok.expected("digit"))
+        }));
+        tok.expected("`(`");
+        assert_eq!(tok.expectation_error().message, "expected digit, found `b`");
+
+        let _ = tok.labelled("number", |tok| tok.try_with(digit).or_else_parse(|| Err(tok.expected("`-`"))));
+        assert_eq!(tok.expectation_error().message, "expected number, found `)`");
+    }
+}
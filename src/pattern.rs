@@ -0,0 +1,119 @@
+use crate::Tokenizer;
+
+/// A small lexical shape that [`Tokenizer::consume_matching`] can consume as one token.
+///
+/// Patterns compose: tuples match their parts back to back, so `(Ident, ":")` is an
+/// identifier immediately followed by a colon and `(Ident, Blanks, ":")` allows spaces
+/// between them.
+pub trait TokenPattern {
+    /// The length in bytes of the match at the very start of `input`, if there is one.
+    fn match_len(&self, tok: &Tokenizer, input: &str) -> Option<usize>;
+}
+
+impl TokenPattern for &str {
+    fn match_len(&self, _: &Tokenizer, input: &str) -> Option<usize> {
+        input.starts_with(*self).then_some(self.len())
+    }
+}
+
+impl TokenPattern for char {
+    fn match_len(&self, _: &Tokenizer, input: &str) -> Option<usize> {
+        input.starts_with(*self).then_some(self.len_utf8())
+    }
+}
+
+/// An identifier as configured by [`TokenizerConfig::ident_start`](crate::TokenizerConfig::ident_start)
+/// and [`TokenizerConfig::ident_continue`](crate::TokenizerConfig::ident_continue).
+/// Keywords match too.
+#[derive(Debug, Copy, Clone)]
+pub struct Ident;
+
+impl TokenPattern for Ident {
+    fn match_len(&self, tok: &Tokenizer, input: &str) -> Option<usize> {
+        let first = input.chars().next().filter(|c| tok.is_ident_start(*c))?;
+        let rest = &input[first.len_utf8()..];
+        let len = rest.char_indices()
+            .find(|(_, c)| !tok.is_ident_continue(*c))
+            .map_or(rest.len(), |(i, _)| i);
+        Some(first.len_utf8() + len)
+    }
+}
+
+/// One or more characters accepted by the predicate, e.g. `Chars(|c| c.is_ascii_hexdigit())`.
+#[derive(Debug, Copy, Clone)]
+pub struct Chars<F>(pub F);
+
+impl<F: Fn(char) -> bool> TokenPattern for Chars<F> {
+    fn match_len(&self, _: &Tokenizer, input: &str) -> Option<usize> {
+        let len = input.char_indices()
+            .find(|(_, c)| !(self.0)(*c))
+            .map_or(input.len(), |(i, _)| i);
+        (len > 0).then_some(len)
+    }
+}
+
+/// Zero or more spaces and tabs.
+#[derive(Debug, Copy, Clone)]
+pub struct Blanks;
+
+impl TokenPattern for Blanks {
+    fn match_len(&self, _: &Tokenizer, input: &str) -> Option<usize> {
+        Some(input.len() - input.trim_start_matches([' ', '\t']).len())
+    }
+}
+
+/// The inner pattern, or nothing.
+#[derive(Debug, Copy, Clone)]
+pub struct Opt<P>(pub P);
+
+impl<P: TokenPattern> TokenPattern for Opt<P> {
+    fn match_len(&self, tok: &Tokenizer, input: &str) -> Option<usize> {
+        Some(self.0.match_len(tok, input).unwrap_or(0))
+    }
+}
+
+macro_rules! sequence_impl {
+    ($($p:ident),+) => {
+        impl<$($p: TokenPattern),+> TokenPattern for ($($p,)+) {
+            #[allow(non_snake_case)]
+            fn match_len(&self, tok: &Tokenizer, input: &str) -> Option<usize> {
+                let ($($p,)+) = self;
+                let mut len = 0;
+                $(
+                    len += $p.match_len(tok, &input[len..])?;
+                )+
+                Some(len)
+            }
+        }
+    }
+}
+
+sequence_impl!(A, B);
+sequence_impl!(A, B, C);
+sequence_impl!(A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use super::{Blanks, Chars, Ident, Opt};
+    use crate::{TokenKind, Tokenizer};
+
+    #[test]
+    fn test_consume_matching() {
+        let tok = Tokenizer::new("label: x  :y #ff00aa -12");
+        let label = tok.consume_matching((Ident, ':')).unwrap();
+        assert_eq!(label.content, "label:");
+        assert_eq!(label.kind, TokenKind::Ident);
+
+        assert!(tok.consume_matching((Ident, ":")).is_none());
+        assert_eq!(tok.consume_matching((Ident, Blanks, ':')).unwrap().content, "x  :");
+        assert_eq!(tok.consume_ident().unwrap().content, "y");
+
+        let color = tok.consume_matching(('#', Chars(|c: char| c.is_ascii_hexdigit()))).unwrap();
+        assert_eq!(color.content, "#ff00aa");
+        assert_eq!(color.span.start.index, 13);
+
+        let number = tok.consume_matching((Opt('-'), Chars(|c: char| c.is_ascii_digit()))).unwrap();
+        assert_eq!(number.content, "-12");
+        assert!(tok.consume_matching(Opt("z")).is_none());
+    }
+}
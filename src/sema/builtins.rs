@@ -0,0 +1,113 @@
+//! The types every program starts with, see [`Builtins`].
+
+use super::Ty;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct BuiltinType {
+    pub name: &'static str,
+    /// In bytes.
+    pub size: usize,
+}
+
+const TYPES: &[BuiltinType] = &[
+    BuiltinType { name: "int", size: 4 },
+    BuiltinType { name: "char", size: 1 },
+    BuiltinType { name: "bool", size: 1 },
+    BuiltinType { name: "void", size: 0 },
+    // The types numeric literals can name with a suffix.
+    BuiltinType { name: "u8", size: 1 },
+    BuiltinType { name: "u16", size: 2 },
+    BuiltinType { name: "u32", size: 4 },
+    BuiltinType { name: "u64", size: 8 },
+    BuiltinType { name: "usize", size: 8 },
+    BuiltinType { name: "i8", size: 1 },
+    BuiltinType { name: "i16", size: 2 },
+    BuiltinType { name: "i32", size: 4 },
+    BuiltinType { name: "i64", size: 8 },
+    BuiltinType { name: "isize", size: 8 },
+    BuiltinType { name: "f32", size: 4 },
+    BuiltinType { name: "f64", size: 8 },
+];
+
+/// Conversions that happen without a cast, from the first type to the second. None
+/// of them loses information.
+const CONVERSIONS: &[(&str, &str)] = &[
+    ("char", "int"),
+    ("bool", "int"),
+    ("u8", "int"),
+    ("u16", "int"),
+    ("i8", "int"),
+    ("i16", "int"),
+    ("i32", "int"),
+    ("int", "i32"),
+    ("int", "i64"),
+    ("int", "f64"),
+    ("f32", "f64"),
+];
+
+const POINTER_SIZE: usize = 8;
+
+/// The builtin types with their sizes, and which of them convert into which. A
+/// [`Type::Name`](crate::ast::Type::Name) naming one of them becomes a [`Ty::Builtin`].
+#[derive(Debug)]
+pub struct Builtins {
+    types: &'static [BuiltinType],
+    conversions: &'static [(&'static str, &'static str)],
+}
+
+impl Default for Builtins {
+    fn default() -> Self {
+        Self { types: TYPES, conversions: CONVERSIONS }
+    }
+}
+
+impl Builtins {
+    pub fn get(&self, name: &str) -> Option<&'static BuiltinType> {
+        self.types.iter().find(|ty| ty.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'static BuiltinType> {
+        self.types.iter()
+    }
+
+    /// Whether a value of type `from` can be used where `to` is expected.
+    pub fn converts(&self, from: &Ty, to: &Ty) -> bool {
+        match (from, to) {
+            (Ty::Builtin(from), Ty::Builtin(to)) => {
+                from == to || self.conversions.contains(&(from.name, to.name))
+            }
+            _ => from == to,
+        }
+    }
+
+    /// The size of `ty` in bytes, if it's made of builtins and pointers.
+    pub fn size_of(&self, ty: &Ty) -> Option<usize> {
+        match ty {
+            Ty::Builtin(ty) => Some(ty.size),
+            Ty::Ptr(_) | Ty::Func(..) => Some(POINTER_SIZE),
+            Ty::Name(_) | Ty::Array(_) | Ty::Generic(..) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtins() {
+        let builtins = Builtins::default();
+        let ty = |name| Ty::Builtin(builtins.get(name).unwrap());
+        assert!(builtins.get("string").is_none());
+        assert_eq!(builtins.iter().map(|ty| ty.name).take(4).collect::<Vec<_>>(), ["int", "char", "bool", "void"]);
+
+        assert_eq!(builtins.size_of(&ty("int")), Some(4));
+        assert_eq!(builtins.size_of(&Ty::Ptr(Box::new(ty("char")))), Some(8));
+        assert_eq!(builtins.size_of(&Ty::Name("T".to_string())), None);
+
+        assert!(builtins.converts(&ty("char"), &ty("int")));
+        assert!(builtins.converts(&ty("bool"), &ty("bool")));
+        assert!(!builtins.converts(&ty("int"), &ty("char")));
+        assert!(!builtins.converts(&Ty::Ptr(Box::new(ty("char"))), &Ty::Ptr(Box::new(ty("int")))));
+    }
+}
@@ -0,0 +1,221 @@
+//! Which functions call which, see [`call_graph`].
+
+use super::{BindingKind, Resolution};
+use crate::ast::{Decl, Expr, Stmt};
+use crate::visit::{self, Visit};
+use crate::{Span, Token};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A function in a [`CallGraph`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FuncId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Recursion {
+    /// The function calls itself, by name or as `self`.
+    Direct,
+    /// The function calls itself through others.
+    Indirect,
+}
+
+/// The functions of a program, at file scope and nested, with an edge from each to
+/// every function it refers to. A function that's only referred to, like one passed
+/// as a callback, counts as called since it may be. Calls inside a lambda count as
+/// calls from the function the lambda is written in.
+#[derive(Debug, Default)]
+pub struct CallGraph<'a> {
+    names: Vec<&'a Token<'a>>,
+    callees: Vec<BTreeSet<FuncId>>,
+}
+
+/// Builds the call graph of `decls`, using `resolution` to tell which function each
+/// name refers to.
+pub fn call_graph<'a>(decls: &'a [Decl<'a>], resolution: &Resolution<'a>) -> CallGraph<'a> {
+    let mut builder = Builder { resolution, ids: BTreeMap::new(), frames: Vec::new(), graph: CallGraph::default() };
+    for decl in decls {
+        if let Decl::Func(name, _) = decl {
+            builder.add(name);
+        }
+    }
+    for decl in decls {
+        builder.visit_decl(decl);
+    }
+    builder.graph
+}
+
+impl<'a> CallGraph<'a> {
+    pub fn functions(&self) -> impl Iterator<Item = FuncId> {
+        (0..self.names.len()).map(FuncId)
+    }
+
+    /// The name of `func` where it's declared.
+    pub fn name(&self, func: FuncId) -> &'a Token<'a> {
+        self.names[func.0]
+    }
+
+    /// The first function declared with the name `name`.
+    pub fn find(&self, name: &str) -> Option<FuncId> {
+        self.names.iter().position(|other| other.content() == name).map(FuncId)
+    }
+
+    pub fn callees(&self, func: FuncId) -> impl Iterator<Item = FuncId> + '_ {
+        self.callees[func.0].iter().copied()
+    }
+
+    pub fn callers(&self, func: FuncId) -> impl Iterator<Item = FuncId> + '_ {
+        self.functions().filter(move |caller| self.callees[caller.0].contains(&func))
+    }
+
+    /// The functions `func` can end up calling, `func` itself included.
+    pub fn reachable_from(&self, func: FuncId) -> BTreeSet<FuncId> {
+        let mut seen = BTreeSet::from([func]);
+        let mut stack = vec![func];
+        while let Some(next) = stack.pop() {
+            for callee in self.callees(next) {
+                if seen.insert(callee) {
+                    stack.push(callee);
+                }
+            }
+        }
+        seen
+    }
+
+    /// The functions a program starting at `main` can call, empty without a `main`.
+    pub fn reachable_from_main(&self) -> BTreeSet<FuncId> {
+        self.find("main").map(|main| self.reachable_from(main)).unwrap_or_default()
+    }
+
+    /// Whether `func` can call itself, and how.
+    pub fn recursion(&self, func: FuncId) -> Option<Recursion> {
+        if self.callees[func.0].contains(&func) {
+            Some(Recursion::Direct)
+        } else if self.callees(func).any(|callee| self.reachable_from(callee).contains(&func)) {
+            Some(Recursion::Indirect)
+        } else {
+            None
+        }
+    }
+}
+
+struct Builder<'r, 'a> {
+    resolution: &'r Resolution<'a>,
+    /// Functions by the span of their name.
+    ids: BTreeMap<Span, FuncId>,
+    /// The function or lambda each enclosing body belongs to, `None` for lambdas.
+    frames: Vec<Option<FuncId>>,
+    graph: CallGraph<'a>,
+}
+
+impl<'a> Builder<'_, 'a> {
+    fn add(&mut self, name: &'a Token<'a>) -> FuncId {
+        let id = FuncId(self.graph.names.len());
+        self.graph.names.push(name);
+        self.graph.callees.push(BTreeSet::new());
+        self.ids.insert(name.span, id);
+        id
+    }
+
+    fn caller(&self) -> Option<FuncId> {
+        self.frames.iter().rev().find_map(|frame| *frame)
+    }
+
+    fn func(&mut self, id: FuncId, walk: impl FnOnce(&mut Self)) {
+        self.frames.push(Some(id));
+        walk(self);
+        self.frames.pop();
+    }
+}
+
+impl<'a> Visit<'a> for Builder<'_, 'a> {
+    fn visit_decl(&mut self, decl: &'a Decl<'a>) {
+        match decl {
+            Decl::Func(name, _) => {
+                let id = self.ids[&name.span];
+                self.func(id, |v| visit::walk_decl(v, decl));
+            }
+            _ => visit::walk_decl(self, decl),
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &'a Stmt<'a>) {
+        match stmt {
+            Stmt::Func(name, _) => {
+                let id = self.add(name);
+                self.func(id, |v| visit::walk_stmt(v, stmt));
+            }
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr<'a>) {
+        let callee = match expr {
+            Expr::Name(name) => self.resolution.binding(name)
+                .filter(|binding| binding.kind == BindingKind::Func)
+                .and_then(|binding| self.ids.get(&binding.name.span).copied()),
+            // Inside a lambda, `self` is the lambda.
+            Expr::SelfRef(_) => self.frames.last().copied().flatten(),
+            Expr::Lambda { .. } => {
+                self.frames.push(None);
+                visit::walk_expr(self, expr);
+                self.frames.pop();
+                return;
+            }
+            _ => None,
+        };
+        if let (Some(caller), Some(callee)) = (self.caller(), callee) {
+            self.graph.callees[caller.0].insert(callee);
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse_program, tokenizer};
+    use crate::sema::Resolver;
+
+    #[test]
+    fn test_call_graph() {
+        let src = "\
+main () int {
+    let a = fact(3) + even(4)
+    let b = (int) int { :self(1) + helper() }
+    :apply(square)
+}
+fact (int) int { :self(1) }
+even (int) bool { :odd(1) }
+odd (int) bool { :even(1) }
+square (int) int { :1 }
+apply ((int) int) int { :0 }
+helper () int { :0 }
+unused () int {
+    inner () int { :inner() }
+    :inner()
+}
+";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let resolution = Resolver::new().resolve(&decls);
+        let graph = call_graph(&decls, &resolution);
+        let find = |name| graph.find(name).unwrap();
+        let names = |ids: &mut dyn Iterator<Item = FuncId>| ids.map(|id| graph.name(id).content()).collect::<Vec<_>>();
+
+        assert_eq!(names(&mut graph.callees(find("main"))), ["fact", "even", "square", "apply", "helper"]);
+        assert_eq!(names(&mut graph.callers(find("even"))), ["main", "odd"]);
+        assert_eq!(
+            names(&mut graph.reachable_from_main().into_iter()),
+            ["main", "fact", "even", "odd", "square", "apply", "helper"],
+        );
+
+        let recursion = graph.functions()
+            .filter_map(|id| Some((graph.name(id).content(), graph.recursion(id)?)))
+            .collect::<Vec<_>>();
+        // The lambda's `self` is the lambda, not `main`.
+        assert_eq!(recursion, [
+            ("fact", Recursion::Direct),
+            ("even", Recursion::Indirect),
+            ("odd", Recursion::Indirect),
+            ("inner", Recursion::Direct),
+        ]);
+    }
+}
@@ -0,0 +1,382 @@
+//! Evaluation of constant expressions, like global initializers and array lengths, see
+//! [`ConstEval`].
+
+use crate::ast::{Decl, Expr, NumValue, Type, UnaryOp};
+use crate::{Error, Span, Token};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Const {
+    Int(i64),
+    Bool(bool),
+}
+
+impl std::fmt::Display for Const {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Const::Int(value) => write!(f, "{value}"),
+            Const::Bool(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// Evaluates integer and boolean expressions at compile time. Integers are 64-bit and
+/// signed; an operation that overflows, divides by zero, or shifts by more than 63 bits
+/// is an error rather than wrapping. `!` is a logical not, and treats an integer as true
+/// when it isn't zero, as [`TypeChecker`](super::TypeChecker) types it `bool`. `&&` and
+/// `||` short-circuit as they do at run time, so `false && 1 / 0` is `false`.
+///
+/// Names refer to globals, which are evaluated when first used and remembered. A
+/// global annotated with a sized integer type like `u8`, directly or through an alias,
+/// has to fit in it, and arithmetic on it or on a literal with a suffix like `255u8`
+/// stays in that type: `255u8 + 1` overflows.
+pub struct ConstEval<'a> {
+    globals: BTreeMap<&'a str, (&'a Token<'a>, Option<&'a Type<'a>>, &'a Expr<'a>)>,
+    aliases: BTreeMap<&'a str, &'a Type<'a>>,
+    values: BTreeMap<&'a str, Result<Value, Error>>,
+    /// The globals being evaluated, to catch initializers that depend on themselves.
+    pending: Vec<&'a str>,
+}
+
+/// A constant with the sized integer type it has to stay within, if any.
+#[derive(Copy, Clone)]
+struct Value(Const, Option<IntTy>);
+
+impl<'a> ConstEval<'a> {
+    /// An evaluator that can refer to the initialized globals and the type aliases of
+    /// `decls`.
+    pub fn new(decls: &'a [Decl<'a>]) -> Self {
+        let mut globals = BTreeMap::new();
+        let mut aliases = BTreeMap::new();
+        for decl in decls {
+            match decl {
+                Decl::Global { name, ty, init: Some(init) } => {
+                    globals.insert(name.content(), (name, ty.as_ref(), init));
+                }
+                Decl::TypeAlias { name, ty, .. } => {
+                    aliases.insert(name.content(), ty);
+                }
+                _ => {}
+            }
+        }
+        Self { globals, aliases, values: BTreeMap::new(), pending: Vec::new() }
+    }
+
+    /// The value of the global named `name`.
+    pub fn global(&mut self, name: &Token) -> Result<Const, Error> {
+        self.global_value(name).map(|value| value.0)
+    }
+
+    fn global_value(&mut self, name: &Token) -> Result<Value, Error> {
+        let Some(&(decl, ty, init)) = self.globals.get(name.content()) else {
+            return Err(Error::new(name.span, format!("`{name}` is not a constant")));
+        };
+        if let Some(value) = self.values.get(decl.content()) {
+            return value.clone();
+        }
+        if self.pending.contains(&decl.content()) {
+            return Err(Error::new(name.span, format!("the value of `{name}` depends on itself")));
+        }
+        self.pending.push(decl.content());
+        let value = self.value(init).and_then(|value| self.fits(value, ty, init.span()));
+        self.pending.pop();
+        self.values.insert(decl.content(), value.clone());
+        value
+    }
+
+    /// Every initialized global with its value, in declaration order.
+    pub fn globals(&mut self) -> Vec<(&'a Token<'a>, Result<Const, Error>)> {
+        let mut names = self.globals.values().map(|&(name, _, _)| name).collect::<Vec<_>>();
+        names.sort_by_key(|name| name.span);
+        names.into_iter().map(|name| (name, self.global(name))).collect()
+    }
+
+    /// The length in an array type like `int[N]`.
+    pub fn array_len(&mut self, len: &Expr) -> Result<u64, Error> {
+        match self.eval(len)? {
+            Const::Int(value) if value >= 0 => Ok(value as u64),
+            value => Err(Error::new(len.span(), format!("an array length has to be a non-negative integer, not `{value}`"))),
+        }
+    }
+
+    pub fn eval(&mut self, expr: &Expr) -> Result<Const, Error> {
+        self.value(expr).map(|value| value.0)
+    }
+
+    fn value(&mut self, expr: &Expr) -> Result<Value, Error> {
+        let span = expr.span();
+        let (operands, op) = match expr {
+            Expr::Num(lit) => {
+                let value = match lit.value {
+                    NumValue::Int(value) => i64::try_from(value)
+                        .map(Const::Int)
+                        .map_err(|_| Error::new(span, "literal is too large for a constant"))?,
+                    NumValue::Float(_) => return Err(Error::new(span, "only integers and booleans can be evaluated at compile time")),
+                };
+                // The parser already made sure the literal fits its suffix.
+                return Ok(Value(value, lit.suffix.as_deref().and_then(int_ty)));
+            }
+            Expr::Bool(token) => return Ok(Value(Const::Bool(token.content() == "true"), None)),
+            Expr::Name(name) => return self.global_value(name),
+            Expr::Unary(op, operand, _) => {
+                let Value(value, ty) = self.value(operand)?;
+                return match (op, value) {
+                    (UnaryOp::Neg, Const::Int(value)) => {
+                        let negated = value.checked_neg().ok_or_else(|| overflow("negation", ty, span))?;
+                        checked(Const::Int(negated), ty, || overflow("negation", ty, span))
+                    }
+                    (UnaryOp::Not, Const::Int(value)) => Ok(Value(Const::Bool(value == 0), None)),
+                    (UnaryOp::Not, Const::Bool(value)) => Ok(Value(Const::Bool(!value), None)),
+                    _ => Err(not_constant(span)),
+                };
+            }
+            Expr::And(operands) | Expr::Or(operands) => {
+                let want = matches!(expr, Expr::Or(_));
+                let value = match self.eval(&operands.0)? {
+                    Const::Bool(lhs) if lhs == want => lhs,
+                    Const::Bool(_) => match self.eval(&operands.1)? {
+                        Const::Bool(rhs) => rhs,
+                        Const::Int(_) => return Err(expected_bool(operands.1.span())),
+                    },
+                    Const::Int(_) => return Err(expected_bool(operands.0.span())),
+                };
+                return Ok(Value(Const::Bool(value), None));
+            }
+            Expr::Add(operands) => (operands, Op::Add),
+            Expr::Sub(operands) => (operands, Op::Sub),
+            Expr::Mul(operands) => (operands, Op::Mul),
+            Expr::Div(operands) => (operands, Op::Div),
+            Expr::Rem(operands) => (operands, Op::Rem),
+            Expr::Shl(operands) => (operands, Op::Shl),
+            Expr::Shr(operands) => (operands, Op::Shr),
+            Expr::BitAnd(operands) => (operands, Op::BitAnd),
+            Expr::BitOr(operands) => (operands, Op::BitOr),
+            Expr::BitXor(operands) => (operands, Op::BitXor),
+            Expr::Lt(operands) => (operands, Op::Lt),
+            Expr::Le(operands) => (operands, Op::Le),
+            Expr::Gt(operands) => (operands, Op::Gt),
+            Expr::Ge(operands) => (operands, Op::Ge),
+            Expr::Eq(operands) => (operands, Op::Eq),
+            Expr::Ne(operands) => (operands, Op::Ne),
+            _ => return Err(not_constant(span)),
+        };
+        let Value(lhs, lhs_ty) = self.value(&operands.0)?;
+        let Value(rhs, rhs_ty) = self.value(&operands.1)?;
+        let ty = lhs_ty.or(rhs_ty);
+        match binary(op, lhs, rhs, ty, span)? {
+            value @ Const::Int(_) => checked(value, ty, || overflow(op.name(), ty, span)),
+            value @ Const::Bool(_) => Ok(Value(value, None)),
+        }
+    }
+
+    /// Checks that `value` fits the integer type `ty` a global was annotated with.
+    fn fits(&self, value: Value, ty: Option<&Type>, span: Span) -> Result<Value, Error> {
+        let (Value(Const::Int(int), _), Some(Type::Name(name))) = (value, ty) else {
+            return Ok(value);
+        };
+        match self.int_ty(name.content()) {
+            Some(ty) => ty.check(value.0, span, || format!("`{int}` doesn't fit in `{name}`")),
+            None => Ok(value),
+        }
+    }
+
+    /// The sized integer type `name` stands for, following aliases.
+    fn int_ty<'n>(&'n self, mut name: &'n str) -> Option<IntTy> {
+        // Bounded, so a cycle of aliases gives up rather than looping.
+        for _ in 0..=self.aliases.len() {
+            match self.aliases.get(name) {
+                Some(Type::Name(target)) => name = target.content(),
+                Some(_) => return None,
+                None => return int_ty(name),
+            }
+        }
+        None
+    }
+}
+
+/// A sized integer type and the values it holds.
+#[derive(Copy, Clone)]
+struct IntTy {
+    name: &'static str,
+    min: i64,
+    max: i64,
+}
+
+impl IntTy {
+    fn check(self, value: Const, span: Span, message: impl FnOnce() -> String) -> Result<Value, Error> {
+        checked(value, Some(self), || Error::new(span, message()))
+    }
+}
+
+const INT_TYPES: &[IntTy] = &[
+    IntTy { name: "u8", min: 0, max: u8::MAX as i64 },
+    IntTy { name: "u16", min: 0, max: u16::MAX as i64 },
+    IntTy { name: "u32", min: 0, max: u32::MAX as i64 },
+    IntTy { name: "u64", min: 0, max: i64::MAX },
+    IntTy { name: "usize", min: 0, max: i64::MAX },
+    IntTy { name: "i8", min: i8::MIN as i64, max: i8::MAX as i64 },
+    IntTy { name: "i16", min: i16::MIN as i64, max: i16::MAX as i64 },
+    IntTy { name: "i32", min: i32::MIN as i64, max: i32::MAX as i64 },
+    IntTy { name: "i64", min: i64::MIN, max: i64::MAX },
+    IntTy { name: "isize", min: i64::MIN, max: i64::MAX },
+    IntTy { name: "int", min: i32::MIN as i64, max: i32::MAX as i64 },
+    IntTy { name: "char", min: 0, max: u8::MAX as i64 },
+];
+
+fn int_ty(name: &str) -> Option<IntTy> {
+    INT_TYPES.iter().find(|ty| ty.name == name).copied()
+}
+
+/// `value` as a value of `ty`, or `error` if it's out of its range.
+fn checked(value: Const, ty: Option<IntTy>, error: impl FnOnce() -> Error) -> Result<Value, Error> {
+    match (value, ty) {
+        (Const::Int(int), Some(ty)) if !(ty.min..=ty.max).contains(&int) => Err(error()),
+        _ => Ok(Value(value, ty)),
+    }
+}
+
+/// ``addition overflows `u8` ``, or just `addition overflows` for plain integers.
+fn overflow(what: &str, ty: Option<IntTy>, span: Span) -> Error {
+    match ty {
+        Some(ty) => Error::new(span, format!("{what} overflows `{}`", ty.name)),
+        None => Error::new(span, format!("{what} overflows")),
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    /// What overflowing the operation is called.
+    fn name(self) -> &'static str {
+        match self {
+            Op::Add => "addition",
+            Op::Sub => "subtraction",
+            Op::Mul => "multiplication",
+            Op::Div => "division",
+            Op::Rem => "remainder",
+            Op::Shl => "left shift",
+            Op::Shr => "right shift",
+            _ => "operation",
+        }
+    }
+}
+
+fn binary(op: Op, lhs: Const, rhs: Const, ty: Option<IntTy>, span: Span) -> Result<Const, Error> {
+    let overflow = || overflow(op.name(), ty, span);
+    match (lhs, rhs) {
+        (Const::Int(lhs), Const::Int(rhs)) => {
+            let value = match op {
+                Op::Add => lhs.checked_add(rhs).ok_or_else(overflow)?,
+                Op::Sub => lhs.checked_sub(rhs).ok_or_else(overflow)?,
+                Op::Mul => lhs.checked_mul(rhs).ok_or_else(overflow)?,
+                Op::Div | Op::Rem if rhs == 0 => return Err(Error::new(span, "division by zero")),
+                Op::Div => lhs.checked_div(rhs).ok_or_else(overflow)?,
+                Op::Rem => lhs.checked_rem(rhs).ok_or_else(overflow)?,
+                Op::Shl | Op::Shr if !(0..64).contains(&rhs) => {
+                    return Err(Error::new(span, format!("can't shift by {rhs} bits")));
+                }
+                // Shifting bits out, or into the sign bit, doesn't shift back.
+                Op::Shl => lhs.checked_shl(rhs as u32).filter(|value| value >> rhs == lhs).ok_or_else(overflow)?,
+                Op::Shr => lhs >> rhs,
+                Op::BitAnd => lhs & rhs,
+                Op::BitOr => lhs | rhs,
+                Op::BitXor => lhs ^ rhs,
+                Op::Lt => return Ok(Const::Bool(lhs < rhs)),
+                Op::Le => return Ok(Const::Bool(lhs <= rhs)),
+                Op::Gt => return Ok(Const::Bool(lhs > rhs)),
+                Op::Ge => return Ok(Const::Bool(lhs >= rhs)),
+                Op::Eq => return Ok(Const::Bool(lhs == rhs)),
+                Op::Ne => return Ok(Const::Bool(lhs != rhs)),
+            };
+            Ok(Const::Int(value))
+        }
+        (Const::Bool(lhs), Const::Bool(rhs)) => match op {
+            Op::BitAnd => Ok(Const::Bool(lhs & rhs)),
+            Op::BitOr => Ok(Const::Bool(lhs | rhs)),
+            Op::BitXor | Op::Ne => Ok(Const::Bool(lhs != rhs)),
+            Op::Eq => Ok(Const::Bool(lhs == rhs)),
+            _ => Err(Error::new(span, "this operator needs integer operands")),
+        },
+        _ => Err(Error::new(span, "can't combine an integer and a boolean")),
+    }
+}
+
+fn not_constant(span: Span) -> Error {
+    Error::new(span, "this can't be evaluated at compile time")
+}
+
+fn expected_bool(span: Span) -> Error {
+    Error::new(span, "expected a boolean")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse_expr, parse_program, tokenizer};
+
+    #[test]
+    fn test_consteval() {
+        let src = "\
+size int = width * 2 + 1
+width int = 1 << 4
+small u8 = 255 + size
+loop int = loop + 1
+flag = size > 32 || 1 / 0 == 0
+broken int = width % 0
+type byte = u8
+tiny byte = 300
+max byte = 255
+";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let mut eval = ConstEval::new(&decls);
+        let values = eval.globals().into_iter()
+            .map(|(name, value)| (name.content(), value.map_err(|e| e.into_inner().message)))
+            .collect::<Vec<_>>();
+        assert_eq!(values, [
+            ("size", Ok(Const::Int(33))),
+            ("width", Ok(Const::Int(16))),
+            ("small", Err("`288` doesn't fit in `u8`".to_string())),
+            ("loop", Err("the value of `loop` depends on itself".to_string())),
+            ("flag", Ok(Const::Bool(true))),
+            ("broken", Err("division by zero".to_string())),
+            ("tiny", Err("`300` doesn't fit in `byte`".to_string())),
+            ("max", Ok(Const::Int(255))),
+        ]);
+
+        let expr = |src| parse_expr(&tokenizer(src)).unwrap_or_else(|_| panic!("expected an expression"));
+        let mut message = |src| eval.eval(&expr(src)).unwrap_err().into_inner().message;
+        assert_eq!(message("9223372036854775807 + 1"), "addition overflows");
+        assert_eq!(message("1 << 64"), "can't shift by 64 bits");
+        assert_eq!(message("3 << 62"), "left shift overflows");
+        assert_eq!(message("1 << 63"), "left shift overflows");
+        assert_eq!(message("f(1)"), "this can't be evaluated at compile time");
+        assert_eq!(message("nope"), "`nope` is not a constant");
+        assert_eq!(message("255u8 + 1"), "addition overflows `u8`");
+        assert_eq!(message("max + 1"), "addition overflows `u8`");
+        assert_eq!(message("0 - 1u8"), "subtraction overflows `u8`");
+        assert_eq!(eval.eval(&expr("-width % 5")).unwrap(), Const::Int(-1));
+        assert_eq!(eval.eval(&expr("-1 << 63")).unwrap(), Const::Int(i64::MIN));
+        assert_eq!(eval.eval(&expr("!5")).unwrap(), Const::Bool(false));
+        assert_eq!(eval.eval(&expr("!0 && !false")).unwrap(), Const::Bool(true));
+        assert_eq!(eval.array_len(&expr("size - 1")).unwrap(), 32);
+        assert_eq!(eval.array_len(&expr("0 - 1")).unwrap_err().message, "an array length has to be a non-negative integer, not `-1`");
+    }
+}
@@ -0,0 +1,87 @@
+//! Warnings about code that's valid but likely wrong, see [`unused_bindings`].
+
+use super::{BindingKind, Resolution};
+use crate::ast::{Decl, Expr, Stmt};
+use crate::visit::{self, Visit};
+use crate::Diagnostic;
+use std::collections::BTreeSet;
+
+/// Warns about each local and parameter that's never read, assigning to it doesn't
+/// count. Names starting with `_` are left alone, so the warning can be silenced by
+/// renaming, which the message suggests.
+pub fn unused_bindings<'a>(decls: &'a [Decl<'a>], resolution: &Resolution<'a>) -> Vec<Diagnostic> {
+    let mut reads = Reads { resolution, read: BTreeSet::new() };
+    for decl in decls {
+        reads.visit_decl(decl);
+    }
+    resolution.bindings.iter()
+        .enumerate()
+        .filter(|(id, binding)| {
+            matches!(binding.kind, BindingKind::Local | BindingKind::Param)
+                && !binding.name.content().starts_with('_')
+                && !reads.read.contains(id)
+        })
+        .map(|(_, binding)| {
+            let what = match binding.kind {
+                BindingKind::Param => "parameter",
+                _ => "variable",
+            };
+            let name = binding.name;
+            Diagnostic::warning(name.span, format!("unused {what} `{name}`, prefix it with an underscore if that's intended: `_{name}`"))
+        })
+        .collect()
+}
+
+/// The bindings whose value is used somewhere.
+struct Reads<'r, 'a> {
+    resolution: &'r Resolution<'a>,
+    read: BTreeSet<usize>,
+}
+
+impl<'a> Visit<'a> for Reads<'_, 'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt<'a>) {
+        match stmt {
+            Stmt::Assign { target: Expr::Name(_), value } => self.visit_expr(value),
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr<'a>) {
+        if let Expr::Name(name) = expr {
+            if let Some(&id) = self.resolution.uses.get(&name.span) {
+                self.read.insert(id);
+            }
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse_program, tokenizer};
+    use crate::sema::Resolver;
+
+    #[test]
+    fn test_unused_bindings() {
+        let src = "\
+main (int, int, int) int {
+    args! argc, argv, _env
+    let total = 0
+    let written = 1
+    written = 2
+    let _ignored = 3
+    total = total + argc
+    :total
+}
+";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let resolution = Resolver::new().resolve(&decls);
+        let warnings = unused_bindings(&decls, &resolution);
+        let warnings = warnings.iter().map(|e| (&src[e.span], e.message.as_str())).collect::<Vec<_>>();
+        assert_eq!(warnings, [
+            ("argv", "unused parameter `argv`, prefix it with an underscore if that's intended: `_argv`"),
+            ("written", "unused variable `written`, prefix it with an underscore if that's intended: `_written`"),
+        ]);
+    }
+}
@@ -0,0 +1,18 @@
+//! Semantic analysis of parsed programs, for checks that need more than the shape of
+//! the tree: name resolution, see [`Resolver`], types, see [`TypeChecker`], and lints
+//! like [`unused_bindings`] whose findings are [warnings](crate::Severity::Warning)
+//! rather than errors. Constant expressions are evaluated by [`consteval`], and
+//! [`call_graph`] tells which functions call which.
+
+mod builtins;
+mod call_graph;
+pub mod consteval;
+mod lint;
+mod resolve;
+mod typeck;
+
+pub use builtins::{BuiltinType, Builtins};
+pub use call_graph::{call_graph, CallGraph, FuncId, Recursion};
+pub use lint::unused_bindings;
+pub use resolve::{Binding, BindingKind, Resolution, Resolver};
+pub use typeck::{Ty, TypeChecker, Types};
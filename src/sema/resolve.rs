@@ -0,0 +1,286 @@
+//! Binds each use of a name to its declaration, see [`Resolver`].
+
+use crate::ast::{Block, Decl, Expr, Func, Macro, Stmt};
+use crate::visit::{self, Visit};
+use crate::{Diagnostic, Error, Span, Token, TokenKind};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BindingKind {
+    /// A name given to an argument by `args!` in a function body.
+    Param,
+    /// `let` or `var`
+    Local,
+    Global,
+    /// A function at file scope or declared inside a block.
+    Func,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Binding<'a> {
+    /// The name where it's declared.
+    pub name: &'a Token<'a>,
+    pub kind: BindingKind,
+}
+
+/// What a [`Resolver`] found.
+#[derive(Debug, Default)]
+pub struct Resolution<'a> {
+    /// Every declaration of a value, in the order they were reached.
+    pub bindings: Vec<Binding<'a>>,
+    /// The declaration each name refers to, as an index into `bindings`, by the span
+    /// of the name.
+    pub uses: BTreeMap<Span, usize>,
+    /// Names used without a declaration in scope, and names declared twice at file scope.
+    pub errors: Vec<Error>,
+    /// Declarations shadowing another, if asked for with
+    /// [`Resolver::with_shadowing_warnings`].
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Resolution<'a> {
+    /// The declaration `name`, a use of a name in the program, refers to.
+    pub fn binding(&self, name: &Token) -> Option<&Binding<'a>> {
+        self.uses.get(&name.span).map(|&id| &self.bindings[id])
+    }
+}
+
+#[derive(Default)]
+struct Scope<'a> {
+    names: Vec<(&'a str, usize)>,
+    /// Whether this scope holds a function, whose body can't see the locals of the
+    /// scopes around it.
+    function: bool,
+}
+
+/// Resolves the names in a program. Scopes nest as the tree does: file scope holds the
+/// globals and functions, then each function and block opens one of its own. A `let`
+/// is visible from the statement after it, everything at file scope from anywhere.
+///
+/// Functions capture nothing, so from inside a function (nested or lambda) only the
+/// functions and globals of the scopes around it are visible, not their locals.
+///
+/// Any declaration inside a function shadows the ones of the same name visible where it
+/// appears, until the end of its block: a `let` shadows those of enclosing blocks and
+/// earlier ones in its own, and parameters shadow globals and functions. Names at file
+/// scope can't shadow each other and are reported as defined twice instead.
+pub struct Resolver<'a> {
+    scopes: Vec<Scope<'a>>,
+    warn_shadowing: bool,
+    out: Resolution<'a>,
+}
+
+impl Default for Resolver<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new() -> Self {
+        Self { scopes: vec![Scope::default()], warn_shadowing: false, out: Resolution::default() }
+    }
+
+    /// Reports each declaration that shadows another in [`Resolution::warnings`], to
+    /// catch reusing a name by accident.
+    pub fn with_shadowing_warnings(mut self) -> Self {
+        self.warn_shadowing = true;
+        self
+    }
+
+    /// Resolves every name in `decls`, which make up one program.
+    pub fn resolve(mut self, decls: &'a [Decl<'a>]) -> Resolution<'a> {
+        for decl in decls {
+            match decl {
+                Decl::Func(name, _) => self.declare(name, BindingKind::Func),
+                Decl::Global { name, .. } => self.declare(name, BindingKind::Global),
+                Decl::TypeAlias { .. } | Decl::Import(..) | Decl::Mod(..) => {}
+            }
+        }
+        for decl in decls {
+            self.visit_decl(decl);
+        }
+        self.out
+    }
+
+    fn declare(&mut self, name: &'a Token<'a>, kind: BindingKind) {
+        let file_scope = self.scopes.len() == 1;
+        if !file_scope && self.warn_shadowing {
+            if let Some(shadowed) = self.lookup(name.content()) {
+                let what = match self.out.bindings[shadowed].kind {
+                    BindingKind::Param => "a parameter",
+                    BindingKind::Local => "a local",
+                    BindingKind::Global => "a global",
+                    BindingKind::Func => "a function",
+                };
+                let warning = Diagnostic::warning(name.span, format!("`{name}` shadows {what} of the same name"))
+                    .with_note(self.out.bindings[shadowed].name.span, "shadowed declaration is here");
+                self.out.warnings.push(warning);
+            }
+        }
+        let scope = self.scopes.last_mut().expect("file scope is never left");
+        if file_scope && scope.names.iter().any(|&(other, _)| other == name.content()) {
+            self.out.errors.push(Error::new(name.span, format!("`{name}` is defined more than once")));
+            return;
+        }
+        scope.names.push((name.content(), self.out.bindings.len()));
+        self.out.bindings.push(Binding { name, kind });
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        let mut outside_function = false;
+        for scope in self.scopes.iter().rev() {
+            let found = scope.names.iter()
+                .rev()
+                .filter(|&&(other, _)| other == name)
+                .map(|&(_, id)| id)
+                .find(|&id| !outside_function || matches!(self.out.bindings[id].kind, BindingKind::Func | BindingKind::Global));
+            if found.is_some() {
+                return found;
+            }
+            outside_function |= scope.function;
+        }
+        None
+    }
+
+    fn scope(&mut self, function: bool, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Scope { names: Vec::new(), function });
+        f(self);
+        self.scopes.pop();
+    }
+}
+
+impl<'a> Visit<'a> for Resolver<'a> {
+    fn visit_func(&mut self, func: &'a Func<'a>) {
+        self.scope(true, |v| visit::walk_func(v, func));
+    }
+
+    fn visit_block(&mut self, block: &'a Block<'a>) {
+        self.scope(false, |v| visit::walk_block(v, block));
+    }
+
+    fn visit_stmt(&mut self, stmt: &'a Stmt<'a>) {
+        match stmt {
+            // The initializer can't see the name it initializes.
+            Stmt::Let { name, .. } => {
+                visit::walk_stmt(self, stmt);
+                self.declare(name, BindingKind::Local);
+            }
+            Stmt::Func(name, _) => {
+                self.declare(name, BindingKind::Func);
+                visit::walk_stmt(self, stmt);
+            }
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_macro(&mut self, mac: &'a Macro<'a>) {
+        if mac.name.content() == "args" {
+            for arg in mac.args.iter().filter(|arg| arg.kind == TokenKind::Ident) {
+                self.declare(arg, BindingKind::Param);
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr<'a>) {
+        match expr {
+            Expr::Name(name) => match self.lookup(name.content()) {
+                Some(id) => {
+                    self.out.uses.insert(name.span, id);
+                }
+                None => self.out.errors.push(Error::new(name.span, format!("cannot find `{name}` in this scope"))),
+            },
+            Expr::Lambda { .. } => self.scope(true, |v| visit::walk_expr(v, expr)),
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse_program, tokenizer};
+
+    #[test]
+    fn test_resolve() {
+        let src = "\
+limit int = 3
+main (int) int {
+    args! n
+    let x = n + limit
+    {
+        let x = x * 2
+        helper () int { :limit + x }
+        :helper() + later
+    }
+    let y = (int) int { :n }
+    :x + y(1)
+}
+later int = 1
+limit int = 4
+";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let resolution = Resolver::new().resolve(&decls);
+
+        let kinds = resolution.bindings.iter().map(|b| (b.name.content(), b.kind)).collect::<Vec<_>>();
+        assert_eq!(kinds, [
+            ("limit", BindingKind::Global),
+            ("main", BindingKind::Func),
+            ("later", BindingKind::Global),
+            ("n", BindingKind::Param),
+            ("x", BindingKind::Local),
+            ("x", BindingKind::Local),
+            ("helper", BindingKind::Func),
+            ("y", BindingKind::Local),
+        ]);
+        // The inner `x` is initialized from the outer one, and the last line sees the outer one again.
+        let line_of = |id: usize| resolution.bindings[id].name.span.start.line;
+        let uses = resolution.uses.iter().map(|(span, &id)| (&src[*span], span.start.line, line_of(id))).collect::<Vec<_>>();
+        assert!(uses.contains(&("x", 5, 3)));
+        assert!(uses.contains(&("x", 10, 3)));
+        assert!(uses.contains(&("later", 7, 12)));
+        assert!(uses.contains(&("helper", 7, 6)));
+
+        // `helper` and the lambda can't see the locals around them.
+        let messages = resolution.errors.iter().map(|e| (e.message.as_str(), e.span.start.line)).collect::<Vec<_>>();
+        assert_eq!(messages, [
+            ("`limit` is defined more than once", 13),
+            ("cannot find `x` in this scope", 6),
+            ("cannot find `n` in this scope", 9),
+        ]);
+        assert!(resolution.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let src = "\
+n int = 1
+f () int {
+    args! n
+    let x = n
+    let x = x + 1
+    { let n = x }
+    g () int { let x = 2 }
+    :x
+}
+";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let resolution = Resolver::new().with_shadowing_warnings().resolve(&decls);
+        assert!(resolution.errors.is_empty());
+        let warnings = resolution.warnings.iter().map(|e| (e.message.as_str(), e.span.start.line)).collect::<Vec<_>>();
+        // `g` can't see the locals of `f`, so its `x` shadows nothing.
+        assert_eq!(warnings, [
+            ("`n` shadows a global of the same name", 2),
+            ("`x` shadows a local of the same name", 4),
+            ("`n` shadows a parameter of the same name", 5),
+        ]);
+        let note = &resolution.warnings[1].children[0];
+        assert_eq!((note.message.as_str(), note.span.start.line), ("shadowed declaration is here", 3));
+        // The last line sees the second `x`.
+        let uses = resolution.uses.iter()
+            .map(|(span, &id)| (span.start.line, resolution.bindings[id].name.span.start.line))
+            .collect::<Vec<_>>();
+        assert!(uses.contains(&(7, 4)));
+        assert!(uses.contains(&(3, 2)));
+    }
+}
@@ -0,0 +1,465 @@
+//! Works out the type of every binding and checks calls against them, see [`TypeChecker`].
+
+use super::consteval::ConstEval;
+use super::{BuiltinType, Builtins, Resolution};
+use crate::ast::{Decl, Expr, FnType, Func, Macro, NumValue, Stmt, Type, UnaryOp};
+use crate::visit::{self, Visit};
+use crate::{Error, Span, Token, TokenKind};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A type with aliases expanded, as [`TypeChecker`] works with them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ty {
+    Builtin(&'static BuiltinType),
+    /// A name that isn't a builtin, like a type parameter.
+    Name(String),
+    Ptr(Box<Ty>),
+    /// An array of any length.
+    Array(Box<Ty>),
+    /// Arguments and return type, `void` when the function returns nothing.
+    Func(Vec<Ty>, Box<Ty>),
+    Generic(String, Vec<Ty>),
+}
+
+impl fmt::Display for Ty {
+    /// Prints the type as it's written in source, e.g. `char*` or `(int) bool`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ty::Builtin(ty) => f.write_str(ty.name),
+            Ty::Name(name) => f.write_str(name),
+            Ty::Ptr(inner) => write!(f, "{inner}*"),
+            Ty::Array(elem) => write!(f, "{elem}[]"),
+            Ty::Func(args, ret) => {
+                f.write_str("(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ") {ret}")
+            }
+            Ty::Generic(name, args) => {
+                write!(f, "{name}<")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                f.write_str(">")
+            }
+        }
+    }
+}
+
+/// What a [`TypeChecker`] found.
+#[derive(Debug, Default)]
+pub struct Types {
+    /// The type of each binding of the [`Resolution`], by the span of its name.
+    pub bindings: BTreeMap<Span, Ty>,
+    pub errors: Vec<Error>,
+}
+
+impl Types {
+    /// The type of the binding declared as `name`.
+    pub fn of(&self, name: &Token) -> Option<&Ty> {
+        self.bindings.get(&name.span)
+    }
+}
+
+/// The function whose body is being checked.
+struct Frame {
+    ty: Ty,
+    /// Its name, or its type for a lambda.
+    decl: Span,
+    /// How many arguments `args!` has named so far.
+    named: usize,
+}
+
+/// Gives each binding a type: its annotation if it has one, otherwise the type of its
+/// initializer. Literals have the type their suffix names, or `int` and `f64` without
+/// one; strings are `char*`, and comparisons `bool`. A call has the return type of its
+/// callee, arithmetic the type of its left operand.
+///
+/// Calls are checked against the type of their callee: they have to pass as many
+/// arguments as it takes, and each argument whose type is known has to have the type
+/// of its parameter or one of the [`Builtins`] converting to it. Arguments to generic
+/// functions are only counted.
+///
+/// Global initializers and array lengths have to be constants, see [`ConstEval`].
+pub struct TypeChecker<'r, 'a> {
+    resolution: &'r Resolution<'a>,
+    builtins: Builtins,
+    aliases: BTreeMap<&'a str, Ty>,
+    /// The names of the functions with type parameters.
+    generic: BTreeSet<Span>,
+    frames: Vec<Frame>,
+    consts: ConstEval<'a>,
+    out: Types,
+}
+
+impl<'r, 'a> TypeChecker<'r, 'a> {
+    /// A checker for the program `resolution` was computed from.
+    pub fn new(resolution: &'r Resolution<'a>) -> Self {
+        Self {
+            resolution,
+            builtins: Builtins::default(),
+            aliases: BTreeMap::new(),
+            generic: BTreeSet::new(),
+            frames: Vec::new(),
+            consts: ConstEval::new(&[]),
+            out: Types::default(),
+        }
+    }
+
+    pub fn check(mut self, decls: &'a [Decl<'a>]) -> Types {
+        self.consts = ConstEval::new(decls);
+        for decl in decls {
+            if let Decl::TypeAlias { name, ty, .. } = decl {
+                let ty = self.lower(ty);
+                self.aliases.insert(name.content(), ty);
+            }
+        }
+        // Functions and annotated globals first, so any initializer can refer to them.
+        for decl in decls {
+            match decl {
+                Decl::Func(name, func) => self.declare_func(name, func),
+                Decl::Global { name, ty: Some(ty), .. } => {
+                    let ty = self.lower(ty);
+                    self.out.bindings.insert(name.span, ty);
+                }
+                _ => {}
+            }
+        }
+        for decl in decls {
+            self.visit_decl(decl);
+        }
+        let errors = self.consts.globals().into_iter().filter_map(|(_, value)| value.err());
+        self.out.errors.extend(errors);
+        self.out
+    }
+
+    fn declare_func(&mut self, name: &Token, func: &Func) {
+        let ty = self.lower_fn(&func.ty);
+        self.out.bindings.insert(name.span, ty);
+        if !func.generics.is_empty() {
+            self.generic.insert(name.span);
+        }
+    }
+
+    fn named(&self, name: &str) -> Ty {
+        match self.builtins.get(name) {
+            Some(builtin) => Ty::Builtin(builtin),
+            None => Ty::Name(name.to_string()),
+        }
+    }
+
+    fn lower(&self, ty: &Type) -> Ty {
+        match ty {
+            Type::Name(name) => self.aliases.get(name.content()).cloned().unwrap_or_else(|| self.named(name.content())),
+            Type::Ptr(inner, _) => Ty::Ptr(Box::new(self.lower(inner))),
+            Type::Array(elem, _, _) => Ty::Array(Box::new(self.lower(elem))),
+            Type::Func(func) => self.lower_fn(func),
+            Type::Generic(name, args, _) => Ty::Generic(name.content().to_string(), args.iter().map(|arg| self.lower(arg)).collect()),
+        }
+    }
+
+    fn lower_fn(&self, ty: &FnType) -> Ty {
+        let args = ty.args.iter().map(|arg| self.lower(arg)).collect();
+        let ret = ty.ret.as_ref().map_or_else(|| self.named("void"), |ret| self.lower(ret));
+        Ty::Func(args, Box::new(ret))
+    }
+
+    /// Declares `name` with the type of `init`, or reports that it can't be worked out.
+    fn infer(&mut self, name: &Token, init: &Expr) {
+        match self.type_of(init) {
+            Some(ty) => {
+                self.out.bindings.insert(name.span, ty);
+            }
+            None => self.out.errors.push(Error::new(name.span, format!("cannot infer the type of `{name}`, add an annotation"))),
+        }
+    }
+
+    fn type_of(&self, expr: &Expr) -> Option<Ty> {
+        let ty = match expr {
+            Expr::Num(lit) => match (&lit.suffix, lit.value) {
+                (Some(suffix), _) => self.named(suffix),
+                (None, NumValue::Int(_)) => self.named("int"),
+                (None, NumValue::Float(_)) => self.named("f64"),
+            },
+            Expr::Str(_) | Expr::Interp(..) => Ty::Ptr(Box::new(self.named("char"))),
+            Expr::Bool(_) => self.named("bool"),
+            Expr::Name(name) => {
+                let binding = self.resolution.binding(name)?;
+                return self.out.bindings.get(&binding.name.span).cloned();
+            }
+            Expr::SelfRef(_) => return self.frames.last().map(|frame| frame.ty.clone()),
+            Expr::Call(callee, _, _) => match self.type_of(callee)? {
+                Ty::Func(_, ret) => *ret,
+                _ => return None,
+            },
+            Expr::Index(operands, _) => match self.type_of(&operands.0)? {
+                Ty::Array(item) | Ty::Ptr(item) => *item,
+                _ => return None,
+            },
+            // The value of the first top-level `:` in the `then` branch; the branches have
+            // to agree anyway.
+            Expr::If(stmt) => return stmt.then.items.iter().find_map(|item| match item {
+                Stmt::Return(value, _) => self.type_of(value),
+                _ => None,
+            }),
+            Expr::Lambda { ty, .. } => self.lower_fn(ty),
+            Expr::ArrayLit(items, _) => Ty::Array(Box::new(self.type_of(items.first()?)?)),
+            Expr::Lt(_) | Expr::Eq(_) | Expr::Ne(_) | Expr::Le(_) | Expr::Gt(_) | Expr::Ge(_) | Expr::And(_) | Expr::Or(_) => {
+                self.named("bool")
+            }
+            Expr::Add(operands) | Expr::Sub(operands) | Expr::Mul(operands) | Expr::Div(operands) | Expr::Rem(operands)
+            | Expr::BitAnd(operands) | Expr::BitOr(operands) | Expr::BitXor(operands) | Expr::Shl(operands)
+            | Expr::Shr(operands) | Expr::Binary(_, operands) => {
+                return self.type_of(&operands.0).or_else(|| self.type_of(&operands.1));
+            }
+            Expr::Unary(op, operand, _) => {
+                let operand = self.type_of(operand);
+                match op {
+                    UnaryOp::Neg => operand?,
+                    UnaryOp::Not => self.named("bool"),
+                    UnaryOp::Deref => match operand? {
+                        Ty::Ptr(inner) => *inner,
+                        _ => return None,
+                    },
+                    UnaryOp::Addr => Ty::Ptr(Box::new(operand?)),
+                }
+            }
+        };
+        Some(ty)
+    }
+
+    fn check_call(&mut self, callee: &Expr, args: &[Expr], span: Span) {
+        let Some(Ty::Func(params, _)) = self.type_of(callee) else {
+            return;
+        };
+        let decl = match callee {
+            Expr::Name(name) => self.resolution.binding(name).map(|binding| binding.name.span),
+            Expr::SelfRef(_) => self.frames.last().map(|frame| frame.decl),
+            Expr::Lambda { ty, .. } => Some(ty.span),
+            _ => None,
+        };
+        let noted = |error: Error, note: String| match decl {
+            Some(decl) => error.with_note(decl, note),
+            None => error,
+        };
+        if args.len() != params.len() {
+            let supplied = if args.len() == 1 { "was" } else { "were" };
+            let takes = count(params.len(), "argument");
+            let error = Error::new(span, format!("this function takes {takes} but {} {supplied} supplied", args.len()));
+            self.out.errors.push(noted(error, format!("function declared here takes {takes}")));
+            return;
+        }
+        if decl.is_some_and(|decl| self.generic.contains(&decl)) {
+            return;
+        }
+        for (arg, param) in args.iter().zip(&params) {
+            match self.type_of(arg) {
+                Some(ty) if !self.builtins.converts(&ty, param) => {
+                    let error = Error::new(arg.span(), format!("expected `{param}`, found `{ty}`"));
+                    self.out.errors.push(noted(error, "function declared here".to_string()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn frame(&mut self, ty: Ty, decl: Span, f: impl FnOnce(&mut Self)) {
+        self.frames.push(Frame { ty, decl, named: 0 });
+        f(self);
+        self.frames.pop();
+    }
+}
+
+impl<'a> Visit<'a> for TypeChecker<'_, 'a> {
+    fn visit_decl(&mut self, decl: &'a Decl<'a>) {
+        match decl {
+            // The parser makes sure there's at least a type or an initializer.
+            Decl::Global { name, ty: None, init: Some(init) } => self.infer(name, init),
+            Decl::Func(name, func) => {
+                let ty = self.lower_fn(&func.ty);
+                return self.frame(ty, name.span, |v| visit::walk_func(v, func));
+            }
+            _ => {}
+        }
+        visit::walk_decl(self, decl);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'a Stmt<'a>) {
+        if let Stmt::Func(name, func) = stmt {
+            self.declare_func(name, func);
+            let ty = self.lower_fn(&func.ty);
+            return self.frame(ty, name.span, |v| visit::walk_func(v, func));
+        }
+        // Walked first, so the types of bindings in a lambda initializer are known.
+        visit::walk_stmt(self, stmt);
+        match stmt {
+            Stmt::Let { name, ty: Some(ty), .. } => {
+                let ty = self.lower(ty);
+                self.out.bindings.insert(name.span, ty);
+            }
+            Stmt::Let { name, ty: None, init: Some(init), .. } => self.infer(name, init),
+            _ => {}
+        }
+    }
+
+    fn visit_macro(&mut self, mac: &'a Macro<'a>) {
+        if mac.name.content() != "args" {
+            return;
+        }
+        for arg in mac.args.iter().filter(|arg| arg.kind == TokenKind::Ident) {
+            let Some(Frame { ty: Ty::Func(args, _), named, .. }) = self.frames.last_mut() else {
+                return;
+            };
+            if let Some(ty) = args.get(*named) {
+                self.out.bindings.insert(arg.span, ty.clone());
+            }
+            *named += 1;
+        }
+    }
+
+    fn visit_type(&mut self, ty: &'a Type<'a>) {
+        if let Type::Array(_, Some(len), _) = ty {
+            if let Err(error) = self.consts.array_len(len) {
+                self.out.errors.push(error);
+            }
+        }
+        visit::walk_type(self, ty);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr<'a>) {
+        match expr {
+            Expr::Lambda { ty: fn_ty, .. } => {
+                let ty = self.lower_fn(fn_ty);
+                self.frame(ty, fn_ty.span, |v| visit::walk_expr(v, expr));
+            }
+            Expr::Call(callee, args, span) => {
+                self.check_call(callee, args, *span);
+                visit::walk_expr(self, expr);
+            }
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+}
+
+/// `n thing`, or `n things` when `n` isn't 1.
+fn count(n: usize, thing: &str) -> String {
+    if n == 1 {
+        format!("{n} {thing}")
+    } else {
+        format!("{n} {thing}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse_program, tokenizer};
+    use crate::sema::Resolver;
+
+    #[test]
+    fn test_infer_lets() {
+        let src = "\
+type str = char*
+limit = 10u8
+half (int) f64 { :0.5 }
+main (int, str) int {
+    args! n, name
+    let a = n * 2
+    let b = half(a)
+    let c = name
+    let d = a < limit && true
+    let e = [&a, &a]
+    let f = (int) bool { :self(1) }
+    let g = f(*e)
+    let h = if d { :\"yes\" } else { :\"no\" }
+    let i = missing
+}
+";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let resolution = Resolver::new().resolve(&decls);
+        let types = TypeChecker::new(&resolution).check(&decls);
+
+        let inferred = resolution.bindings.iter()
+            .filter_map(|binding| Some((binding.name.content(), types.of(binding.name)?.to_string())))
+            .collect::<Vec<_>>();
+        let inferred = inferred.iter().map(|(name, ty)| (*name, ty.as_str())).collect::<Vec<_>>();
+        assert_eq!(inferred, [
+            ("limit", "u8"),
+            ("half", "(int) f64"),
+            ("main", "(int, char*) int"),
+            ("n", "int"),
+            ("name", "char*"),
+            ("a", "int"),
+            ("b", "f64"),
+            ("c", "char*"),
+            ("d", "bool"),
+            ("e", "int*[]"),
+            ("f", "(int) bool"),
+            ("g", "bool"),
+            ("h", "char*"),
+        ]);
+        let messages = types.errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(messages, ["cannot infer the type of `i`, add an annotation"]);
+    }
+
+    #[test]
+    fn test_calls() {
+        let src = "\
+pair (int, char*) int { :0 }
+first<T> (T, T) T { :0 }
+main () int {
+    let z = pair(1)
+    let a = pair(1, \"x\", 2)
+    let b = pair(\"x\", 1)
+    let c = pair(1, \"x\") + first(true, 1)
+    let d = pair(true, \"x\")
+    :self(1)
+}
+";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let resolution = Resolver::new().resolve(&decls);
+        let types = TypeChecker::new(&resolution).check(&decls);
+        let errors = types.errors.iter()
+            .map(|e| (&src[e.span], e.message.as_str(), e.notes.iter().map(|(span, note)| (&src[*span], note.as_str())).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [
+            ("pair(1)", "this function takes 2 arguments but 1 was supplied", vec![("pair", "function declared here takes 2 arguments")]),
+            ("pair(1, \"x\", 2)", "this function takes 2 arguments but 3 were supplied", vec![("pair", "function declared here takes 2 arguments")]),
+            ("\"x\"", "expected `int`, found `char*`", vec![("pair", "function declared here")]),
+            ("1", "expected `char*`, found `int`", vec![("pair", "function declared here")]),
+            ("self(1)", "this function takes 0 arguments but 1 was supplied", vec![("main", "function declared here takes 0 arguments")]),
+        ]);
+    }
+
+    #[test]
+    fn test_constants() {
+        let src = "\
+width = 4
+limit u8 = width * 100
+table = half(1)
+half (int) int { :0 }
+main () int {
+    let buf char[width * 2]
+    let bad int[width - 5]
+    :0
+}
+";
+        let decls = parse_program(&tokenizer(src)).unwrap_or_else(|_| panic!("expected the program to parse"));
+        let resolution = Resolver::new().resolve(&decls);
+        let types = TypeChecker::new(&resolution).check(&decls);
+        let errors = types.errors.iter().map(|e| (&src[e.span], e.message.as_str())).collect::<Vec<_>>();
+        assert_eq!(errors, [
+            ("width - 5", "an array length has to be a non-negative integer, not `-1`"),
+            ("width * 100", "`400` doesn't fit in `u8`"),
+            ("half(1)", "this can't be evaluated at compile time"),
+        ]);
+    }
+}
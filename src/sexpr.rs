@@ -0,0 +1,348 @@
+//! Compact s-expression rendering of the syntax tree, for snapshot tests and quick
+//! inspection: `fib (int) int { :if n < 2 { :0 } }` comes out as
+//! `(fn fib (fn-type (int) int) (block (ret (if (lt n 2) (block (ret 0))))))`.
+//!
+//! Leaves print their source text, strings their unescaped content in quotes, and a
+//! left-out type or initializer prints as `_`.
+
+use crate::ast::{Block, Decl, Else, Expr, FnType, Func, If, Macro, Stmt, StrPart, Type, UnaryOp};
+use crate::Token;
+use std::fmt::Write;
+
+impl Decl<'_> {
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        decl(&mut out, self);
+        out
+    }
+}
+
+impl Type<'_> {
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        ty(&mut out, self);
+        out
+    }
+}
+
+impl FnType<'_> {
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        fn_type(&mut out, self);
+        out
+    }
+}
+
+impl Stmt<'_> {
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        stmt(&mut out, self);
+        out
+    }
+}
+
+impl Expr<'_> {
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        expr(&mut out, self);
+        out
+    }
+}
+
+fn decl(out: &mut String, decl: &Decl) {
+    match decl {
+        Decl::Func(name, f) => func(out, name, f),
+        Decl::Global { name, ty: t, init } => {
+            write!(out, "(global {name} ").unwrap();
+            optional(out, t.as_ref(), ty);
+            out.push(' ');
+            optional(out, init.as_ref(), expr);
+            out.push(')');
+        }
+        Decl::TypeAlias { name, ty: t, .. } => {
+            write!(out, "(type {name} ").unwrap();
+            ty(out, t);
+            out.push(')');
+        }
+        Decl::Import(path, _) => write!(out, "(import {:?})", path.content()).unwrap(),
+        Decl::Mod(name, _) => write!(out, "(mod {name})").unwrap(),
+    }
+}
+
+fn func(out: &mut String, name: &Token, f: &Func) {
+    write!(out, "(fn {name} ").unwrap();
+    if !f.generics.is_empty() {
+        out.push_str("(generics");
+        for param in &f.generics {
+            write!(out, " {param}").unwrap();
+        }
+        out.push_str(") ");
+    }
+    fn_type(out, &f.ty);
+    out.push(' ');
+    block(out, &f.body);
+    out.push(')');
+}
+
+fn ty(out: &mut String, t: &Type) {
+    match t {
+        Type::Name(name) => out.push_str(name.content()),
+        Type::Ptr(inner, _) => {
+            out.push_str("(ptr ");
+            ty(out, inner);
+            out.push(')');
+        }
+        Type::Array(elem, len, _) => {
+            out.push_str("(array ");
+            ty(out, elem);
+            if let Some(len) = len {
+                out.push(' ');
+                expr(out, len);
+            }
+            out.push(')');
+        }
+        Type::Func(func) => fn_type(out, func),
+        Type::Generic(name, args, _) => {
+            write!(out, "(generic {name}").unwrap();
+            for arg in args {
+                out.push(' ');
+                ty(out, arg);
+            }
+            out.push(')');
+        }
+    }
+}
+
+fn fn_type(out: &mut String, func: &FnType) {
+    out.push_str("(fn-type (");
+    for (i, arg) in func.args.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        ty(out, arg);
+    }
+    out.push(')');
+    if let Some(ret) = &func.ret {
+        out.push(' ');
+        ty(out, ret);
+    }
+    out.push(')');
+}
+
+fn stmt(out: &mut String, s: &Stmt) {
+    match s {
+        Stmt::If(s) => if_(out, s),
+        Stmt::Return(e, _) => {
+            out.push_str("(ret ");
+            expr(out, e);
+            out.push(')');
+        }
+        Stmt::Block(b) => block(out, b),
+        Stmt::Assign { target, value } => {
+            out.push_str("(assign ");
+            expr(out, target);
+            out.push(' ');
+            expr(out, value);
+            out.push(')');
+        }
+        Stmt::Expr(e) => expr(out, e),
+        Stmt::Macro(mac) => macro_(out, mac),
+        Stmt::Let { name, ty: t, init, .. } => {
+            write!(out, "(let {name} ").unwrap();
+            optional(out, t.as_ref(), ty);
+            out.push(' ');
+            optional(out, init.as_ref(), expr);
+            out.push(')');
+        }
+        Stmt::While(s) => {
+            out.push_str("(while ");
+            if let Some(label) = &s.label {
+                write!(out, "{label} ").unwrap();
+            }
+            expr(out, &s.condition);
+            out.push(' ');
+            block(out, &s.body);
+            out.push(')');
+        }
+        Stmt::Break(label, _) => jump(out, "break", label.as_ref()),
+        Stmt::Continue(label, _) => jump(out, "continue", label.as_ref()),
+        Stmt::Func(name, f) => func(out, name, f),
+    }
+}
+
+fn jump(out: &mut String, keyword: &str, label: Option<&Token>) {
+    match label {
+        Some(label) => write!(out, "({keyword} {label})").unwrap(),
+        None => write!(out, "({keyword})").unwrap(),
+    }
+}
+
+fn block(out: &mut String, b: &Block) {
+    out.push_str("(block");
+    for item in &b.items {
+        out.push(' ');
+        stmt(out, item);
+    }
+    out.push(')');
+}
+
+fn if_(out: &mut String, s: &If) {
+    out.push_str("(if ");
+    expr(out, &s.condition);
+    out.push(' ');
+    block(out, &s.then);
+    match &s.otherwise {
+        Some(Else::Block(otherwise)) => {
+            out.push(' ');
+            block(out, otherwise);
+        }
+        Some(Else::If(otherwise)) => {
+            out.push(' ');
+            if_(out, otherwise);
+        }
+        None => {}
+    }
+    out.push(')');
+}
+
+fn macro_(out: &mut String, mac: &Macro) {
+    write!(out, "(macro {}", mac.name).unwrap();
+    for arg in &mac.args {
+        write!(out, " {arg}").unwrap();
+    }
+    out.push(')');
+}
+
+fn expr(out: &mut String, e: &Expr) {
+    let (op, operands) = match e {
+        Expr::Num(lit) => {
+            out.push_str(lit.token.content());
+            return;
+        }
+        Expr::Bool(token) | Expr::Name(token) | Expr::SelfRef(token) => {
+            out.push_str(token.content());
+            return;
+        }
+        Expr::Str(token) => {
+            write!(out, "{:?}", token.content()).unwrap();
+            return;
+        }
+        Expr::Interp(parts, _) => {
+            out.push_str("(interp");
+            for part in parts {
+                match part {
+                    StrPart::Lit(lit) => write!(out, " {:?}", lit.content()).unwrap(),
+                    StrPart::Expr(e) => {
+                        out.push(' ');
+                        expr(out, e);
+                    }
+                }
+            }
+            out.push(')');
+            return;
+        }
+        Expr::Call(callee, args, _) => {
+            out.push_str("(call ");
+            expr(out, callee);
+            for arg in args {
+                out.push(' ');
+                expr(out, arg);
+            }
+            out.push(')');
+            return;
+        }
+        Expr::ArrayLit(items, _) => {
+            out.push_str("(array");
+            for item in items {
+                out.push(' ');
+                expr(out, item);
+            }
+            out.push(')');
+            return;
+        }
+        Expr::If(s) => return if_(out, s),
+        Expr::Lambda { ty, body } => {
+            out.push_str("(lambda ");
+            fn_type(out, ty);
+            out.push(' ');
+            block(out, body);
+            out.push(')');
+            return;
+        }
+        Expr::Unary(op, operand, _) => {
+            let op = match op {
+                UnaryOp::Neg => "neg",
+                UnaryOp::Not => "not",
+                UnaryOp::Deref => "deref",
+                UnaryOp::Addr => "addr",
+            };
+            write!(out, "({op} ").unwrap();
+            expr(out, operand);
+            out.push(')');
+            return;
+        }
+        Expr::Add(operands) => ("add", operands),
+        Expr::Sub(operands) => ("sub", operands),
+        Expr::Mul(operands) => ("mul", operands),
+        Expr::Div(operands) => ("div", operands),
+        Expr::Rem(operands) => ("rem", operands),
+        Expr::Lt(operands) => ("lt", operands),
+        Expr::Eq(operands) => ("eq", operands),
+        Expr::Ne(operands) => ("ne", operands),
+        Expr::Le(operands) => ("le", operands),
+        Expr::Gt(operands) => ("gt", operands),
+        Expr::Ge(operands) => ("ge", operands),
+        Expr::BitAnd(operands) => ("bitand", operands),
+        Expr::BitOr(operands) => ("bitor", operands),
+        Expr::BitXor(operands) => ("bitxor", operands),
+        Expr::Shl(operands) => ("shl", operands),
+        Expr::Shr(operands) => ("shr", operands),
+        Expr::And(operands) => ("and", operands),
+        Expr::Or(operands) => ("or", operands),
+        Expr::Binary(op, operands) => (op.content(), operands),
+        Expr::Index(operands, _) => ("index", operands),
+    };
+    write!(out, "({op} ").unwrap();
+    expr(out, &operands.0);
+    out.push(' ');
+    expr(out, &operands.1);
+    out.push(')');
+}
+
+fn optional<T>(out: &mut String, node: Option<&T>, f: fn(&mut String, &T)) {
+    match node {
+        Some(node) => f(out, node),
+        None => out.push('_'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{parse_program, tokenizer};
+
+    fn sexprs(src: &str) -> Vec<String> {
+        let tok = tokenizer(src);
+        parse_program(&tok).unwrap().iter().map(|decl| decl.to_sexpr()).collect()
+    }
+
+    #[test]
+    fn test_sexpr() {
+        let src = r#"
+fib (int) int {
+    args! n
+    :if 2 < 1 { :0 } else { :self(1 - 1) + -self(2) }
+}
+type list = vec<int*>[4]
+greeting str = "hi\n${1 * 2}"
+count int
+import "std/io"
+"#;
+        assert_eq!(sexprs(src), [
+            "(fn fib (fn-type (int) int) (block (macro args n) (ret (if (lt 2 1) (block (ret 0)) (block (ret (add (call self (sub 1 1)) (neg (call self 2)))))))))",
+            "(type list (array (generic vec (ptr int)) 4))",
+            r#"(global greeting str (interp "hi\n" (mul 1 2)))"#,
+            "(global count int _)",
+            r#"(import "std/io")"#,
+        ]);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{FileId, Location, Tokenizer, TokenizerConfig};
+
+/// Line boundaries of a source text, for turning a [`Location`] or byte offset back into
+/// the line it sits on when rendering diagnostics.
+#[derive(Debug, Clone)]
+pub struct SourceMap<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+/// One line of a [`SourceMap`]'s source.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// 0-based, matching [`Location::line`].
+    pub number: usize,
+    /// Byte offset of the line's first character.
+    pub start: usize,
+    /// The line without its `\n` or `\r\n` ending.
+    pub text: &'a str,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(memchr::memchr_iter(b'\n', source.as_bytes()).map(|i| i + 1))
+            .collect();
+        Self { source, line_starts }
+    }
+
+    #[inline]
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    #[inline]
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The line numbered `number`, if the source has that many lines.
+    pub fn line(&self, number: usize) -> Option<Line<'a>> {
+        let start = *self.line_starts.get(number)?;
+        let end = self.line_starts.get(number + 1)
+            .map_or(self.source.len(), |next| next - 1);
+        let text = &self.source[start..end];
+        Some(Line {
+            number,
+            start,
+            text: text.strip_suffix('\r').unwrap_or(text),
+        })
+    }
+
+    /// The line containing byte `index`; offsets past the end resolve to the last line.
+    pub fn line_at(&self, index: usize) -> Line<'a> {
+        let number = self.line_starts.partition_point(|start| *start <= index) - 1;
+        self.line(number).expect("line_starts always holds the located line")
+    }
+
+    #[inline]
+    pub fn line_of(&self, location: Location) -> Line<'a> {
+        self.line_at(location.index)
+    }
+}
+
+/// Owns every named source in a compilation, handing out a [`FileId`] for each so
+/// diagnostics can say which file a [`Location`] came from.
+#[derive(Debug, Default)]
+pub struct SourceManager {
+    files: Vec<(String, String)>,
+}
+
+impl SourceManager {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        self.files.push((name.into(), source.into()));
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    #[inline]
+    pub fn name(&self, file: FileId) -> Option<&str> {
+        self.files.get(file.0 as usize).map(|(name, _)| name.as_str())
+    }
+
+    #[inline]
+    pub fn source(&self, file: FileId) -> Option<&str> {
+        self.files.get(file.0 as usize).map(|(_, source)| source.as_str())
+    }
+
+    /// The id of the first source registered under `name`.
+    pub fn find(&self, name: &str) -> Option<FileId> {
+        self.files.iter()
+            .position(|(n, _)| n == name)
+            .map(|i| FileId(i as u32))
+    }
+
+    pub fn source_map(&self, file: FileId) -> Option<SourceMap<'_>> {
+        self.source(file).map(SourceMap::new)
+    }
+
+    /// A tokenizer over `file` whose locations carry its id.
+    pub fn tokenizer(&self, file: FileId, config: TokenizerConfig) -> Option<Tokenizer<'_>> {
+        self.source(file).map(|source| Tokenizer::for_file((file, source), config))
+    }
+
+    /// `name:line:column` for a location in one of the managed files.
+    pub fn describe(&self, location: Location) -> String {
+        match self.name(location.file) {
+            Some(name) => format!("{name}:{location}"),
+            None => location.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SourceManager, SourceMap};
+    use crate::{FileId, Tokenizer, TokenizerConfig};
+
+    #[test]
+    fn test_lines() {
+        let src = "first\r\nsecond line\n\nlast";
+        let map = SourceMap::new(src);
+        assert_eq!(map.line_count(), 4);
+        assert_eq!(map.line(0).unwrap().text, "first");
+        assert_eq!(map.line(2).unwrap().text, "");
+        assert!(map.line(4).is_none());
+
+        let line = map.line_at(src.find("line").unwrap());
+        assert_eq!((line.number, line.start, line.text), (1, 7, "second line"));
+        assert_eq!(map.line_at(src.len()).text, "last");
+        assert_eq!(map.line_at(5).number, 0);
+
+        let tok = Tokenizer::new(src);
+        tok.consume_ident().unwrap();
+        let location = tok.consume_ident().unwrap().span.start;
+        let line = map.line_of(location);
+        assert_eq!(line.number, location.line);
+        assert_eq!(&line.text[location.index - line.start..], "second line");
+    }
+
+    #[test]
+    fn test_source_manager() {
+        let mut sources = SourceManager::new();
+        let main = sources.add("main.src", "import util");
+        let util = sources.add("util.src", "\n  helper");
+        assert_ne!(main, util);
+        assert_eq!(sources.find("util.src"), Some(util));
+        assert_eq!(sources.name(FileId(7)), None);
+
+        let tok = sources.tokenizer(util, TokenizerConfig::default()).unwrap();
+        let helper = tok.consume_ident().unwrap();
+        assert_eq!(helper.span.file(), util);
+        assert_eq!(helper.span.end.file, util);
+        assert_eq!(sources.describe(helper.span.start), "util.src:1:2");
+        assert_eq!(sources.source_map(util).unwrap().line_of(helper.span.start).text, "  helper");
+
+        let tok = Tokenizer::new("x");
+        assert_eq!(tok.consume_ident().unwrap().span.file(), FileId::default());
+    }
+}
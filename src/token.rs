@@ -1,42 +1,229 @@
+use crate::{Interner, Symbol};
 use std::borrow::Cow;
+use std::sync::Arc;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token<'a> {
     pub span: Span,
+    pub kind: TokenKind,
     pub content: Cow<'a, str>,
+    /// Trivia between the previous token's trailing trivia and this token. Only
+    /// filled in when [`TokenizerConfig::preserve_trivia`](crate::TokenizerConfig::preserve_trivia) is set.
+    pub leading: Vec<Trivia<'a>>,
+    /// Trivia after this token up to and including the end of its line.
+    pub trailing: Vec<Trivia<'a>>,
+}
+
+/// Whitespace or a comment skipped between tokens.
+#[derive(Debug, Clone)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub span: Span,
+    pub content: Cow<'a, str>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TokenKind {
+    Ident,
+    /// An identifier listed in [`TokenizerConfig::keywords`](crate::TokenizerConfig::keywords).
+    Keyword,
+    Number,
+    String,
+    Punct,
+    /// Whitespace or a comment, only produced inside [`Tokenizer::raw`](crate::Tokenizer::raw).
+    Trivia(TriviaKind),
+    /// Input that failed to lex, produced instead of an error when
+    /// [`TokenizerConfig::recover_errors`](crate::TokenizerConfig::recover_errors) is set.
+    Error,
+    Eof,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A numeric literal as lexed by [`Tokenizer::consume_number`](crate::Tokenizer::consume_number).
+#[derive(Debug)]
+pub struct Number<'a> {
+    pub token: Token<'a>,
+    pub kind: NumberKind,
+    pub radix: u32,
+    /// The digits with the radix prefix, `_` separators and suffix removed,
+    /// e.g. `1F` for `0x1_Fu8` or `3.14e2` for `3.14e2`.
+    pub value: String,
+    pub suffix: Option<&'a str>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum NumberKind {
+    Int,
+    Float,
+}
+
+/// A string literal split around its `${...}` interpolation holes,
+/// see [`Tokenizer::consume_interpolated_string`](crate::Tokenizer::consume_interpolated_string).
+#[derive(Debug)]
+pub struct StringParts<'a> {
+    pub span: Span,
+    pub parts: Vec<StringPart<'a>>,
+}
+
+#[derive(Debug)]
+pub enum StringPart<'a> {
+    /// Literal text exactly as written; escape sequences are left for the caller.
+    Literal(Token<'a>),
+    /// The source between `${` and the matching `}`.
+    Expr(Span),
+}
+
+/// Identifies one source registered with a [`SourceManager`](crate::SourceManager).
+/// Tokenizers built straight from a `&str` use `FileId(0)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FileId(pub u32);
+
+/// Identified by file and byte index: equality, hashing and ordering all ignore the
+/// line and columns, which only restate the index under some tab width.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Location {
+    pub file: FileId,
     pub line: usize,
     pub column: usize,
+    /// The column counted in UTF-16 code units, as expected by LSP clients.
+    pub utf16_column: usize,
     pub index: usize,
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Ordered by start, then end, so sorting spans sorts them by position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Span {
     pub start: Location,
     pub end: Location,
 }
 
+/// A lexical or parse error. The fields live in a box, reached through `Deref` as in
+/// `error.message`, so the `Result`s every parser returns stay a pointer wide.
 #[derive(Debug, Clone)]
-pub struct Error {
+pub struct Error(Box<ErrorInner>);
+
+/// The fields of an [`Error`].
+#[derive(Debug, Clone)]
+pub struct ErrorInner {
     pub message: String,
-    pub location: Location,
+    /// The offending source; empty when the error points between characters.
+    pub span: Span,
+    /// The underlying error, if this one wraps another.
+    pub source: Option<Arc<dyn std::error::Error + Send + Sync>>,
+    /// What would have been accepted instead, for errors built by
+    /// [`Tokenizer::expectation_error`](crate::Tokenizer::expectation_error); empty otherwise.
+    pub expected: Vec<String>,
+    /// Other places in the source that explain the error, like the declaration a
+    /// mismatched call refers to.
+    pub notes: Vec<(Span, String)>,
 }
 
 impl<'a> Token<'a> {
     pub fn content(&self) -> &str {
         self.content.as_ref()
     }
+
+    /// Whether both tokens have the same kind and text, wherever they appear and whatever
+    /// trivia surrounds them. This is what `==` and `Hash` use, so tokens work as map keys
+    /// and compare equal across re-lexes in snapshot tests.
+    #[inline]
+    pub fn eq_ignore_span(&self, other: &Token) -> bool {
+        self.kind == other.kind && self.content == other.content
+    }
+
+    /// Interns the token's content, e.g. an identifier for name resolution.
+    #[inline]
+    pub fn intern(&self, interner: &mut Interner) -> Symbol {
+        interner.intern(self.content())
+    }
+
+    /// Copies any borrowed text so the token no longer borrows the source, e.g. to keep it
+    /// in a cache that outlives the buffer or send it to another thread.
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            span: self.span,
+            kind: self.kind,
+            content: Cow::Owned(self.content.into_owned()),
+            leading: self.leading.into_iter().map(Trivia::into_owned).collect(),
+            trailing: self.trailing.into_iter().map(Trivia::into_owned).collect(),
+        }
+    }
+
+    /// The value of an integer literal such as `0xFF`, `1_000`, or `7u8`.
+    pub fn as_u64(&self) -> Result<u64, Error> {
+        let number = self.integer()?;
+        u64::from_str_radix(&number.value, number.radix)
+            .map_err(|_| self.error(format!("integer literal `{}` does not fit in u64", self.content)))
+    }
+
+    /// The value of an integer literal, as [`Token::as_u64`] but bounded by `i64::MAX`.
+    pub fn as_i64(&self) -> Result<i64, Error> {
+        let number = self.integer()?;
+        i64::from_str_radix(&number.value, number.radix)
+            .map_err(|_| self.error(format!("integer literal `{}` does not fit in i64", self.content)))
+    }
+
+    /// The value of a float or integer literal; non-decimal integers must fit in a `u64`.
+    pub fn as_f64(&self) -> Result<f64, Error> {
+        let number = self.number()?;
+        if number.radix != 10 {
+            return self.as_u64().map(|n| n as f64);
+        }
+        number.value.parse()
+            .map_err(|_| self.error(format!("`{}` is not a valid float", self.content)))
+    }
+
+    fn integer(&self) -> Result<Number<'_>, Error> {
+        let number = self.number()?;
+        match number.kind {
+            NumberKind::Int => Ok(number),
+            NumberKind::Float => Err(self.error(format!("expected an integer, found `{}`", self.content))),
+        }
+    }
+
+    /// Re-lexes the content as a numeric literal; the whole token must be the number.
+    fn number(&self) -> Result<Number<'_>, Error> {
+        match crate::Tokenizer::new(self.content()).consume_number() {
+            Ok(Some(number)) if number.token.span.len() == self.content.len() => Ok(number),
+            _ => Err(self.error(format!("expected a number, found `{}`", self.content))),
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::new(self.span, message)
+    }
+}
+
+impl<'a> Trivia<'a> {
+    pub fn into_owned(self) -> Trivia<'static> {
+        Trivia {
+            kind: self.kind,
+            span: self.span,
+            content: Cow::Owned(self.content.into_owned()),
+        }
+    }
 }
 
 impl Location {
     #[inline]
     pub fn zero() -> Self {
         Location {
+            file: FileId::default(),
             line: 0,
             column: 0,
+            utf16_column: 0,
             index: 0
         }
     }
@@ -47,6 +234,190 @@ impl Span {
     pub fn len(&self) -> usize {
         self.end.index - self.start.index
     }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start.index == self.end.index
+    }
+
+    #[inline]
+    pub fn file(&self) -> FileId {
+        self.start.file
+    }
+
+    /// The span's byte offsets into its source, for slicing with `&source[span]` or
+    /// `&source[span.byte_range()]`.
+    #[inline]
+    pub fn byte_range(&self) -> std::ops::Range<usize> {
+        self.start.index..self.end.index
+    }
+
+    /// The smallest span covering both `self` and `other`, e.g. a node from its first
+    /// and last child.
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: if other.start.index < self.start.index { other.start } else { self.start },
+            end: if other.end.index > self.end.index { other.end } else { self.end },
+        }
+    }
+
+    /// Whether the span ends at or before `other` starts.
+    #[inline]
+    pub fn before(&self, other: Span) -> bool {
+        self.end <= other.start
+    }
+
+    /// Whether the span starts at or after `other` ends.
+    #[inline]
+    pub fn after(&self, other: Span) -> bool {
+        other.before(*self)
+    }
+
+    /// Whether the two spans share at least one byte.
+    #[inline]
+    pub fn overlaps(&self, other: Span) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    /// Whether `location` falls inside the span. The end is exclusive, so adjacent
+    /// tokens never both claim the same location.
+    #[inline]
+    pub fn contains(&self, location: Location) -> bool {
+        self.start.index <= location.index && location.index < self.end.index
+    }
+}
+
+impl From<Span> for std::ops::Range<usize> {
+    #[inline]
+    fn from(span: Span) -> Self {
+        span.byte_range()
+    }
+}
+
+impl std::ops::Index<Span> for str {
+    type Output = str;
+
+    /// Slices the source a span was lexed from. Panics like any other `str` slicing if
+    /// the span is out of bounds or not on character boundaries.
+    #[inline]
+    fn index(&self, span: Span) -> &str {
+        &self[span.byte_range()]
+    }
+}
+
+impl std::ops::Index<Span> for String {
+    type Output = str;
+
+    #[inline]
+    fn index(&self, span: Span) -> &str {
+        &self.as_str()[span]
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+impl PartialEq for Location {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        (self.file, self.index) == (other.file, other.index)
+    }
+}
+
+impl Eq for Location {}
+
+impl std::hash::Hash for Location {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (self.file, self.index).hash(state);
+    }
+}
+
+impl PartialOrd for Location {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Location {
+    #[inline]
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.file, self.index).cmp(&(other.file, other.index))
+    }
+}
+
+impl Error {
+    #[inline]
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Error(Box::new(ErrorInner {
+            message: message.into(),
+            span,
+            source: None,
+            expected: Vec::new(),
+            notes: Vec::new(),
+        }))
+    }
+
+    /// An error pointing at a single location rather than a stretch of source.
+    #[inline]
+    pub fn at(location: Location, message: impl Into<String>) -> Self {
+        Self::new(Span { start: location, end: location }, message)
+    }
+
+    /// Records `source` as the cause of this error.
+    pub fn with_source(mut self, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    /// Adds a note pointing at `span`.
+    pub fn with_note(mut self, span: Span, note: impl Into<String>) -> Self {
+        self.notes.push((span, note.into()));
+        self
+    }
+
+    #[inline]
+    pub fn location(&self) -> Location {
+        self.span.start
+    }
+
+    /// Unboxes the fields, to move them out.
+    #[inline]
+    pub fn into_inner(self) -> ErrorInner {
+        *self.0
+    }
+}
+
+impl std::ops::Deref for Error {
+    type Target = ErrorInner;
+
+    #[inline]
+    fn deref(&self) -> &ErrorInner {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for Error {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut ErrorInner {
+        &mut self.0
+    }
+}
+
+impl std::fmt::Display for Error {
+    /// `line:column: message`
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.span.start, self.message)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_deref().map(|e| e as _)
+    }
 }
 
 impl std::fmt::Display for Location {
@@ -68,3 +439,40 @@ impl PartialEq<&'_ str> for Token<'_> {
         (*rhs) == self.content.as_ref()
     }
 }
+
+impl PartialEq for Token<'_> {
+    /// See [`Token::eq_ignore_span`].
+    #[inline]
+    fn eq(&self, rhs: &Token) -> bool {
+        self.eq_ignore_span(rhs)
+    }
+}
+
+impl Eq for Token<'_> {}
+
+impl std::hash::Hash for Token<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.kind.hash(state);
+        self.content().hash(state);
+    }
+}
+
+/// Serializes as the token's kind, content, and span; trivia is left out.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Token<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut out = serializer.serialize_struct("Token", 3)?;
+        out.serialize_field("kind", &self.kind)?;
+        out.serialize_field("content", self.content())?;
+        out.serialize_field("span", &self.span)?;
+        out.end()
+    }
+}
+
+impl std::fmt::Display for Token<'_> {
+    /// Prints the lexeme, e.g. `foo` or `+`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.content())
+    }
+}
@@ -1,25 +1,287 @@
-use crate::{Location, Span, Token, Error};
-use std::cell::Cell;
+use crate::{TokenPattern, FileId, Location, Span, Token, TokenKind, Trivia, TriviaKind, Number, NumberKind, StringParts, StringPart, Error, ParseError, ParseResult, ParseResultExt};
+use std::cell::{Cell, RefCell};
+use std::any::Any;
 use std::borrow::Cow;
+use std::rc::Rc;
+use unicode_xid::UnicodeXID;
+
+/// Logs a tokenizer operation through `tracing` when the `trace` feature is enabled,
+/// and compiles to nothing otherwise.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "trace")]
+        tracing::trace!(target: "parse_rs::tokenizer", $($arg)*);
+    };
+}
+
+/// Lexical rules shared by every [`Tokenizer`] primitive.
+#[derive(Debug, Clone)]
+pub struct TokenizerConfig {
+    /// Prefixes that start a comment running to the end of the line.
+    pub line_comments: Vec<String>,
+    /// `(open, close)` delimiter pairs for block comments, which may nest.
+    pub block_comments: Vec<(String, String)>,
+    /// When false, `\n` and `\r` are left in the input for the grammar to consume.
+    pub newlines_are_whitespace: bool,
+    /// Identifiers lexed as [`TokenKind::Keyword`] rather than [`TokenKind::Ident`].
+    pub keywords: Vec<String>,
+    /// Columns between tab stops.
+    pub tab_width: usize,
+    /// Characters that may begin an identifier.
+    pub ident_start: fn(char) -> bool,
+    /// Characters that may appear after the first character of an identifier.
+    pub ident_continue: fn(char) -> bool,
+    /// Strip the common leading indentation (and the blank first and last lines)
+    /// from `"""` multi-line strings.
+    pub dedent_multiline_strings: bool,
+    /// Attach the whitespace and comments around each token to it as
+    /// [`Token::leading`] and [`Token::trailing`] trivia.
+    pub preserve_trivia: bool,
+    /// Turn lexical errors into [`TokenKind::Error`] tokens covering the bad input and
+    /// keep lexing, rather than returning the error from [`Tokenizer::next_token`].
+    pub recover_errors: bool,
+    /// Reject any single token longer than this many bytes.
+    pub max_token_len: Option<usize>,
+    /// Reject delimiter nesting deeper than this, both in [`Tokenizer::consume_balanced`]
+    /// and in parsers that recurse through [`Tokenizer::nested`].
+    pub max_nesting: Option<usize>,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        Self {
+            line_comments: vec!["//".to_string()],
+            block_comments: vec![("/*".to_string(), "*/".to_string())],
+            newlines_are_whitespace: true,
+            keywords: Vec::new(),
+            tab_width: 4,
+            ident_start: is_xid_ident_start,
+            ident_continue: is_xid_ident_continue,
+            dedent_multiline_strings: true,
+            preserve_trivia: false,
+            recover_errors: false,
+            max_token_len: None,
+            max_nesting: None,
+        }
+    }
+}
+
+/// A saved cursor position, see [`Tokenizer::checkpoint`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    location: Location,
+    trivia_start: Location,
+    diagnostics: usize,
+}
+
+impl Checkpoint {
+    #[inline]
+    pub fn location(&self) -> Location {
+        self.location
+    }
+}
+
+/// The default identifier start rule: Unicode `XID_Start` or `_`.
+#[inline]
+pub fn is_xid_ident_start(c: char) -> bool {
+    c == '_' || UnicodeXID::is_xid_start(c)
+}
+
+/// The default identifier continue rule: Unicode `XID_Continue`.
+#[inline]
+pub fn is_xid_ident_continue(c: char) -> bool {
+    UnicodeXID::is_xid_continue(c)
+}
 
 pub struct Tokenizer<'src> {
     source: &'src str,
     location: Cell<Location>,
+    /// Where the next token's leading trivia begins: just past the last consumed
+    /// token's trailing trivia. Only maintained when preserving trivia.
+    trivia_start: Cell<Location>,
+    /// The location the last [`Tokenizer::shimmy`] stopped at; skipping again from
+    /// there is a no-op, so repeated peeks don't rescan trivia.
+    skipped: Cell<Option<Location>>,
+    /// Set inside [`Tokenizer::raw`]: nothing is skipped implicitly.
+    raw: Cell<bool>,
+    error: RefCell<Option<Error>>,
+    config: TokenizerConfig,
+    lookahead: RefCell<Lookahead<'src>>,
+    progress: RefCell<Option<Progress<'src>>>,
+    /// Current depth of [`Tokenizer::nested`] calls.
+    depth: Cell<usize>,
+    /// Errors parsers recovered from, see [`Tokenizer::report`].
+    diagnostics: RefCell<Vec<Error>>,
+    expected: RefCell<Expectations>,
+    /// See [`Tokenizer::with_extension`].
+    extensions: Vec<Rc<dyn Any>>,
+}
+
+/// A callback registered with [`Tokenizer::on_progress`].
+struct Progress<'src> {
+    every: usize,
+    tokens: usize,
+    furthest: usize,
+    callback: Box<dyn FnMut(usize, usize) + 'src>,
+}
+
+/// What parsers were looking for at the furthest location one of them failed to
+/// match, see [`Tokenizer::expected`].
+#[derive(Default)]
+struct Expectations {
+    location: Option<Location>,
+    items: Vec<String>,
+}
+
+/// Tokens already lexed ahead of `origin`, reused by [`Tokenizer::peek_nth`]
+/// until the cursor moves.
+#[derive(Default)]
+struct Lookahead<'src> {
+    origin: Option<Checkpoint>,
+    raw: bool,
+    tokens: Vec<Result<Token<'src>, Error>>,
+    resume: Option<Checkpoint>,
 }
 
 impl<'src> Tokenizer<'src> {
     #[inline]
     fn shimmy(&self) {
         let mut loc = self.loc();
-        let mut chars = self.source[loc.index..].chars();
-        while let Some(c) = chars.next() {
-            if c.is_whitespace() {
-                Self::adv(&mut loc, c);
-            } else {
-                break;
+        if self.raw.get() || self.skipped.get() == Some(loc) {
+            return;
+        }
+        loop {
+            match self.trivia_at(loc) {
+                Some(Ok((_, end))) => loc = end,
+                Some(Err(e)) => {
+                    *self.error.borrow_mut() = Some(e);
+                    break;
+                }
+                None => break,
             }
         }
         self.location.set(loc);
+        self.skipped.set(Some(loc));
+    }
+
+    /// The piece of whitespace or comment starting at `loc`, and where it ends.
+    fn trivia_at(&self, loc: Location) -> Option<Result<(TriviaKind, Location), Error>> {
+        let rest = &self.source[loc.index..];
+        let mut end = loc;
+        match rest.chars().next()? {
+            '\n' | '\r' if !self.config.newlines_are_whitespace => return None,
+            '\n' => {
+                self.adv(&mut end, '\n');
+                return Some(Ok((TriviaKind::Newline, end)));
+            }
+            '\r' => {
+                let newline = if rest.starts_with("\r\n") { "\r\n" } else { "\r" };
+                newline.chars().for_each(|c| self.adv(&mut end, c));
+                return Some(Ok((TriviaKind::Newline, end)));
+            }
+            c if c.is_whitespace() => {
+                let len = rest.bytes()
+                    .position(|b| !matches!(b, b' ' | b'\t' | 0x0b | 0x0c))
+                    .unwrap_or(rest.len());
+                let len = match rest.as_bytes().get(len) {
+                    Some(b) if !b.is_ascii() => rest.char_indices()
+                        .find(|(_, c)| !c.is_whitespace() || *c == '\n' || *c == '\r')
+                        .map_or(rest.len(), |(i, _)| i),
+                    _ => len,
+                };
+                self.adv_str(&mut end, &rest[..len]);
+                return Some(Ok((TriviaKind::Whitespace, end)));
+            }
+            _ => {}
+        }
+
+        if self.config.line_comments.iter().any(|p| rest.starts_with(p.as_str())) {
+            let len = memchr::memchr(b'\n', rest.as_bytes()).unwrap_or(rest.len());
+            self.adv_str(&mut end, &rest[..len]);
+            return Some(Ok((TriviaKind::LineComment, end)));
+        }
+
+        let (open, close) = self.config.block_comments.iter()
+            .find(|(open, _)| rest.starts_with(open.as_str()))?;
+        Some(match self.block_comment_end(loc, open, close) {
+            Some(end) => Ok((TriviaKind::BlockComment, end)),
+            None => Err(Error::at(loc, "unterminated block comment")),
+        })
+    }
+
+    /// The trivia pieces from `start` up to `until` (exclusive), stopping early after
+    /// the first newline when `until` is `None`.
+    fn collect_trivia(&self, start: Location, until: Option<Location>) -> (Vec<Trivia<'src>>, Location) {
+        let mut pieces = Vec::new();
+        let mut loc = start;
+        while until.is_none_or(|until| loc.index < until.index) {
+            let Some(Ok((kind, end))) = self.trivia_at(loc) else {
+                break;
+            };
+            pieces.push(Trivia {
+                kind,
+                span: Span { start: loc, end },
+                content: Cow::Borrowed(&self.source[loc.index..end.index]),
+            });
+            loc = end;
+            if until.is_none() && self.source[pieces.last().unwrap().span.start.index..end.index].contains('\n') {
+                break;
+            }
+        }
+        (pieces, loc)
+    }
+
+    /// Marks a token ending at `end` as consumed.
+    #[inline]
+    fn commit(&self, end: Location) {
+        self.location.set(end);
+        if self.config.preserve_trivia {
+            let (_, trivia_end) = self.collect_trivia(end, None);
+            self.trivia_start.set(trivia_end);
+        }
+        if let Some(progress) = self.progress.borrow_mut().as_mut() {
+            // Only count ground not covered before, so backtracking doesn't recount tokens.
+            if end.index > progress.furthest {
+                progress.furthest = end.index;
+                progress.tokens += 1;
+                if progress.tokens % progress.every == 0 {
+                    (progress.callback)(end.index, self.source.len());
+                }
+            }
+        }
+    }
+
+    /// Builds the token for `span` and consumes it.
+    #[inline]
+    fn bump(&self, span: Span) -> Token<'src> {
+        let token = self.token(span);
+        self.commit(span.end);
+        token
+    }
+
+    fn block_comment_end(&self, start: Location, open: &str, close: &str) -> Option<Location> {
+        let mut end = start;
+        let mut depth = 0usize;
+        while end.index < self.source.len() {
+            let rest = &self.source[end.index..];
+            let skip = if rest.starts_with(open) {
+                depth += 1;
+                open
+            } else if rest.starts_with(close) {
+                depth -= 1;
+                close
+            } else {
+                let c = rest.chars().next().unwrap();
+                &rest[..c.len_utf8()]
+            };
+            for c in skip.chars() {
+                self.adv(&mut end, c);
+            }
+            if depth == 0 {
+                return Some(end);
+            }
+        }
+        None
     }
 
     #[inline(always)]
@@ -27,18 +289,234 @@ impl<'src> Tokenizer<'src> {
         self.location.get()
     }
 
+    /// Steps `l` over `c`. `\r` takes no columns so `\r\n` ends a line exactly like `\n`,
+    /// and tabs advance to the next multiple of [`TokenizerConfig::tab_width`].
     #[inline]
-    pub fn adv(l: &mut Location, c: char) {
+    pub fn adv(&self, l: &mut Location, c: char) {
         l.index += c.len_utf8();
         match c {
             '\n' => {
                 l.line += 1;
                 l.column = 0;
+                l.utf16_column = 0;
+                return;
+            }
+            '\r' => return,
+            '\t' => {
+                let width = self.config.tab_width.max(1);
+                l.column += width - l.column % width;
             }
             _ => {
                 l.column += 1;
             }
         }
+        l.utf16_column += c.len_utf16();
+    }
+
+    /// Steps `l` over all of `s`. ASCII text is walked a line at a time with `memchr`,
+    /// only falling back to [`Tokenizer::adv`] per char for tabs, `\r`, and non-ASCII.
+    pub fn adv_str(&self, l: &mut Location, s: &str) {
+        if !s.is_ascii() {
+            s.chars().for_each(|c| self.adv(l, c));
+            return;
+        }
+        let bytes = s.as_bytes();
+        let line_start = match memchr::memrchr(b'\n', bytes) {
+            Some(last) => {
+                l.line += memchr::memchr_iter(b'\n', bytes).count();
+                l.column = 0;
+                l.utf16_column = 0;
+                last + 1
+            }
+            None => 0,
+        };
+        l.index += line_start;
+        let tail = &bytes[line_start..];
+        if memchr::memchr2(b'\t', b'\r', tail).is_some() {
+            tail.iter().for_each(|b| self.adv(l, *b as char));
+        } else {
+            l.index += tail.len();
+            l.column += tail.len();
+            l.utf16_column += tail.len();
+        }
+    }
+
+    fn token(&self, span: Span) -> Token<'src> {
+        let content = self.lex_for(span).unwrap_or("");
+        let (leading, trailing) = self.trivia(span);
+        Token {
+            span,
+            kind: self.classify(content),
+            content: Cow::Borrowed(content),
+            leading,
+            trailing,
+        }
+    }
+
+    /// The leading and trailing trivia of a token at `span`, if trivia is being preserved.
+    fn trivia(&self, span: Span) -> (Vec<Trivia<'src>>, Vec<Trivia<'src>>) {
+        if !self.config.preserve_trivia {
+            return (Vec::new(), Vec::new());
+        }
+        let (leading, _) = self.collect_trivia(self.trivia_start.get(), Some(span.start));
+        let (trailing, _) = self.collect_trivia(span.end, None);
+        (leading, trailing)
+    }
+
+    fn classify(&self, s: &str) -> TokenKind {
+        match s.chars().next() {
+            None => TokenKind::Eof,
+            Some('"') => TokenKind::String,
+            Some('r') if s.trim_start_matches('r').trim_start_matches('#').starts_with('"') => TokenKind::String,
+            Some(c) if c.is_ascii_digit() => TokenKind::Number,
+            Some(c) if self.is_ident_start(c) => {
+                if self.is_keyword(s) {
+                    TokenKind::Keyword
+                } else {
+                    TokenKind::Ident
+                }
+            }
+            Some(_) => TokenKind::Punct,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_ident_start(&self, c: char) -> bool {
+        (self.config.ident_start)(c)
+    }
+
+    #[inline]
+    pub(crate) fn is_ident_continue(&self, c: char) -> bool {
+        (self.config.ident_continue)(c)
+    }
+
+    fn ident_span(&self) -> Option<Span> {
+        let mut chars = self.cursor().chars();
+        let start = self.loc();
+        let mut end = start;
+        let first = chars.next().filter(|c| self.is_ident_start(*c))?;
+        self.adv(&mut end, first);
+        chars.take_while(|c| self.is_ident_continue(*c))
+            .for_each(|c| self.adv(&mut end, c));
+        Some(Span { start, end })
+    }
+
+    /// Lexes the numeric literal starting at `start`, if there is one, without consuming it.
+    fn number_at(&self, start: Location) -> Result<Option<Number<'src>>, Error> {
+        let rest = &self.source[start.index..];
+        let bytes = rest.as_bytes();
+        if !bytes.first().is_some_and(u8::is_ascii_digit) {
+            return Ok(None);
+        }
+        let error = |offset: usize, message: String| {
+            let mut location = start;
+            rest[..offset].chars().for_each(|c| self.adv(&mut location, c));
+            Error::at(location, message)
+        };
+
+        let (radix, mut i) = match (bytes[0], bytes.get(1)) {
+            (b'0', Some(b'x' | b'X')) => (16, 2),
+            (b'0', Some(b'b' | b'B')) => (2, 2),
+            (b'0', Some(b'o' | b'O')) => (8, 2),
+            _ => (10, 0),
+        };
+        let scan = |i: &mut usize| {
+            while bytes.get(*i).is_some_and(|b| *b == b'_' || (*b as char).is_digit(radix)) {
+                *i += 1;
+            }
+        };
+        let digits = |from: usize, to: usize| rest[from..to].chars().filter(|c| *c != '_');
+
+        let digits_start = i;
+        scan(&mut i);
+        let mut value = digits(digits_start, i).collect::<String>();
+        if value.is_empty() {
+            return Err(error(i, format!("expected digits after base {radix} prefix")));
+        }
+
+        let mut kind = NumberKind::Int;
+        if radix == 10 {
+            if bytes.get(i) == Some(&b'.') && bytes.get(i + 1).is_some_and(u8::is_ascii_digit) {
+                let fraction = i;
+                i += 1;
+                scan(&mut i);
+                value.extend(digits(fraction, i));
+                kind = NumberKind::Float;
+            }
+            if matches!(bytes.get(i), Some(b'e' | b'E')) {
+                let mut j = i + 1;
+                if matches!(bytes.get(j), Some(b'+' | b'-')) {
+                    j += 1;
+                }
+                if bytes.get(j).is_some_and(u8::is_ascii_digit) {
+                    let exponent = i;
+                    i = j;
+                    scan(&mut i);
+                    value.extend(digits(exponent, i));
+                    kind = NumberKind::Float;
+                }
+            }
+        }
+        if bytes.get(i).is_some_and(u8::is_ascii_digit) {
+            return Err(error(i, format!("invalid digit for a base {radix} literal")));
+        }
+
+        let suffix_start = i;
+        if bytes.get(i).is_some_and(|b| b.is_ascii_alphabetic() || *b == b'_') {
+            i += 1;
+            while bytes.get(i).is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_') {
+                i += 1;
+            }
+        }
+        let suffix = (suffix_start < i).then(|| &rest[suffix_start..i]);
+
+        let mut end = start;
+        rest[..i].chars().for_each(|c| self.adv(&mut end, c));
+        Ok(Some(Number {
+            token: self.token(Span { start, end }),
+            kind,
+            radix,
+            value,
+            suffix,
+        }))
+    }
+
+    /// Lexes a raw string (`r"..."`, `r#"..."#`, ...) starting at `start`, returning its
+    /// full span and the unescaped contents between the quotes.
+    fn raw_string_at(&self, start: Location) -> Result<Option<(Span, &'src str)>, Error> {
+        let rest = &self.source[start.index..];
+        let Some(after_r) = rest.strip_prefix('r') else {
+            return Ok(None);
+        };
+        let hashes = after_r.len() - after_r.trim_start_matches('#').len();
+        let Some(body) = after_r[hashes..].strip_prefix('"') else {
+            return Ok(None);
+        };
+        let close = format!("\"{}", "#".repeat(hashes));
+        let Some(len) = body.find(&close) else {
+            return Err(Error::at(start, "unterminated raw string"));
+        };
+
+        let lexeme = &rest[..1 + hashes + 1 + len + close.len()];
+        let mut end = start;
+        lexeme.chars().for_each(|c| self.adv(&mut end, c));
+        Ok(Some((Span { start, end }, &body[..len])))
+    }
+
+    /// Lexes a `"""` multi-line string starting at `start`, returning its full span
+    /// and verbatim contents.
+    fn multiline_string_at(&self, start: Location) -> Result<Option<(Span, &'src str)>, Error> {
+        let rest = &self.source[start.index..];
+        let Some(body) = rest.strip_prefix("\"\"\"") else {
+            return Ok(None);
+        };
+        let Some(len) = body.find("\"\"\"") else {
+            return Err(Error::at(start, "unterminated multi-line string"));
+        };
+
+        let mut end = start;
+        rest[..len + 6].chars().for_each(|c| self.adv(&mut end, c));
+        Ok(Some((Span { start, end }, &body[..len])))
     }
 
     fn word_span(&self, s: &str) -> Option<Span> {
@@ -47,13 +525,11 @@ impl<'src> Tokenizer<'src> {
         let mut end = start;
         let mut cursor = self.source[start.index..].chars();
         for c in s.chars() {
-            let Some(o) = cursor.next() else {
-                return None;
-            };
+            let o = cursor.next()?;
             if c != o {
                 return None;
             }
-            Self::adv(&mut end, c);
+            self.adv(&mut end, c);
         }
         match cursor.next() {
             Some(c) if !c.is_whitespace() => None,
@@ -65,12 +541,54 @@ impl<'src> Tokenizer<'src> {
 impl<'src> Tokenizer<'src> {
     #[inline]
     pub fn new(source: &'src str) -> Self {
+        Self::new_with_config(source, TokenizerConfig::default())
+    }
+
+    pub fn new_with_config(source: &'src str, config: TokenizerConfig) -> Self {
+        Self::for_file((FileId::default(), source), config)
+    }
+
+    /// A tokenizer whose locations are all tagged with `file`, e.g. from
+    /// [`SourceManager::tokenizer`](crate::SourceManager::tokenizer).
+    pub fn for_file((file, source): (FileId, &'src str), config: TokenizerConfig) -> Self {
+        let start = Location { file, ..Location::zero() };
         Self {
             source,
-            location: Cell::new(Location::zero()),
+            location: Cell::new(start),
+            trivia_start: Cell::new(start),
+            skipped: Cell::new(None),
+            raw: Cell::new(false),
+            error: RefCell::new(None),
+            config,
+            lookahead: RefCell::new(Lookahead::default()),
+            progress: RefCell::new(None),
+            depth: Cell::new(0),
+            diagnostics: RefCell::new(Vec::new()),
+            expected: RefCell::default(),
+            extensions: Vec::new(),
         }
     }
 
+    /// Attaches `value` for parsers to look up with [`Tokenizer::extension`], such as
+    /// settings of a grammar that aren't lexical. Attaching a second value of the same
+    /// type replaces the first.
+    pub fn with_extension<T: Any>(mut self, value: T) -> Self {
+        self.extensions.retain(|extension| !extension.is::<T>());
+        self.extensions.push(Rc::new(value));
+        self
+    }
+
+    /// The value of type `T` attached with [`Tokenizer::with_extension`].
+    #[inline]
+    pub fn extension<T: Any>(&self) -> Option<&T> {
+        self.extensions.iter().find_map(|extension| extension.downcast_ref())
+    }
+
+    #[inline]
+    pub fn config(&self) -> &TokenizerConfig {
+        &self.config
+    }
+
     #[inline]
     pub fn source(&self) -> &'src str {
         self.source
@@ -82,11 +600,98 @@ impl<'src> Tokenizer<'src> {
         &self.source[self.loc().index..]
     }
 
+    /// How far into [`Tokenizer::source`] the cursor is, in bytes.
+    #[inline]
+    pub fn bytes_consumed(&self) -> usize {
+        self.loc().index
+    }
+
+    #[inline]
+    pub fn bytes_total(&self) -> usize {
+        self.source.len()
+    }
+
+    /// Calls `callback(bytes_consumed, bytes_total)` after every `every` tokens consumed,
+    /// e.g. to drive a progress bar. Tokens re-read after backtracking aren't counted again.
+    pub fn on_progress(&self, every: usize, callback: impl FnMut(usize, usize) + 'src) {
+        *self.progress.borrow_mut() = Some(Progress {
+            every: every.max(1),
+            tokens: 0,
+            furthest: self.loc().index,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// The last lexical error hit while skipping whitespace and comments, if any.
+    pub fn error(&self) -> Option<Error> {
+        self.shimmy();
+        self.error.borrow().clone()
+    }
+
     pub fn location(&self) -> Location {
         self.shimmy();
         self.loc()
     }
 
+    /// Runs `f` with implicit whitespace and comment skipping turned off, for grammars
+    /// where layout matters. Inside, every primitive starts exactly at the cursor and
+    /// [`Tokenizer::next_token`] returns whitespace, newlines, and comments as
+    /// [`TokenKind::Trivia`] tokens.
+    pub fn raw<T>(&self, f: impl FnOnce(&Self) -> T) -> T {
+        let outer = self.raw.replace(true);
+        let result = f(self);
+        self.raw.set(outer);
+        result
+    }
+
+    /// Saves the current position so it can be returned to with [`Tokenizer::restore`].
+    #[inline]
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            location: self.loc(),
+            trivia_start: self.trivia_start.get(),
+            diagnostics: self.diagnostics.borrow().len(),
+        }
+    }
+
+    /// Returns to `checkpoint`, dropping anything [reported](Tokenizer::report) since.
+    #[inline]
+    pub fn restore(&self, checkpoint: Checkpoint) {
+        self.location.set(checkpoint.location);
+        self.trivia_start.set(checkpoint.trivia_start);
+        self.diagnostics.borrow_mut().truncate(checkpoint.diagnostics);
+    }
+
+    /// Records an error that a parser recovered from instead of returning it. Reports
+    /// made inside a parse that is later rewound are discarded along with it.
+    pub fn report(&self, error: Error) {
+        trace!(at = %error.location(), message = %error.message, "report");
+        self.diagnostics.borrow_mut().push(error);
+    }
+
+    /// Removes and returns the errors reported so far, in the order they were reported.
+    pub fn take_diagnostics(&self) -> Vec<Error> {
+        std::mem::take(&mut self.diagnostics.borrow_mut())
+    }
+
+    /// Moves the cursor to `location`, which must lie on a char boundary of the source.
+    #[inline]
+    pub(crate) fn seek(&self, location: Location) {
+        self.location.set(location);
+        self.trivia_start.set(location);
+    }
+
+    /// Runs a parser, rewinding to where it started if it reports [`ParseError::NoMatch`].
+    /// Hard errors leave the cursor where the failure happened.
+    pub fn try_with<T>(&self, f: impl FnOnce(&Self) -> ParseResult<T>) -> ParseResult<T> {
+        let checkpoint = self.checkpoint();
+        let result = f(self);
+        if let Err(ParseError::NoMatch) = result {
+            self.restore(checkpoint);
+        }
+        result
+    }
+
     #[inline]
     pub fn has_more_tokens(&self) -> bool {
         !self.cursor().is_empty()
@@ -101,7 +706,7 @@ impl<'src> Tokenizer<'src> {
     pub fn advance(&self) -> Option<char> {
         let c = self.peek()?;
         let mut loc = self.loc();
-        Self::adv(&mut loc, c);
+        self.adv(&mut loc, c);
         self.location.set(loc);
         Some(c)
     }
@@ -116,9 +721,9 @@ impl<'src> Tokenizer<'src> {
         let start = self.loc();
         let mut end = start;
         for c in cursor {
-            Self::adv(&mut end, c);
+            self.adv(&mut end, c);
         }
-        (start != end).then(|| Span { start, end })
+        (start != end).then_some(Span { start, end })
     }
 
     #[inline]
@@ -128,90 +733,1575 @@ impl<'src> Tokenizer<'src> {
 
     #[inline]
     pub fn consume_word(&self, s: &str) -> Option<Token<'src>> {
-        self.word_span(s)
-            .map(|span| {
-                self.location.set(span.end);
-                Token {
+        let token = self.word_span(s)
+            .map(|span| self.bump(span));
+        trace!(at = %self.loc(), word = s, matched = token.is_some(), "consume_word");
+        token
+    }
+
+    #[inline]
+    pub fn is_keyword(&self, s: &str) -> bool {
+        self.config.keywords.iter().any(|k| k == s)
+    }
+
+    /// Consumes an identifier that is not in the keyword table.
+    pub fn consume_ident(&self) -> Option<Token<'src>> {
+        let token = self.ident_span()
+            .filter(|span| self.lex_for(*span).is_some_and(|s| !self.is_keyword(s)))
+            .map(|span| self.bump(span));
+        trace!(at = %self.loc(), ident = ?token.as_ref().map(Token::content), "consume_ident");
+        token
+    }
+
+    /// Consumes a numeric literal such as `0x1F`, `0b1010`, `1_000`, `3.14`, `1e9` or `255u8`.
+    pub fn consume_number(&self) -> Result<Option<Number<'src>>, Error> {
+        let number = self.number_at(self.location())?;
+        if let Some(number) = &number {
+            self.commit(number.token.span.end);
+        }
+        Ok(number)
+    }
+
+    /// Consumes a raw string literal like `r"C:\path"` or `r#"say "hi""#`.
+    /// No escapes are processed, so the content borrows straight from the source.
+    pub fn consume_raw_string(&self) -> Result<Option<Token<'src>>, Error> {
+        let Some((span, content)) = self.raw_string_at(self.location())? else {
+            return Ok(None);
+        };
+        let (leading, trailing) = self.trivia(span);
+        self.commit(span.end);
+        Ok(Some(Token {
+            span,
+            kind: TokenKind::String,
+            content: Cow::Borrowed(content),
+            leading,
+            trailing,
+        }))
+    }
+
+    /// Consumes a `"""` multi-line string. Contents are verbatim apart from indentation
+    /// stripping, see [`TokenizerConfig::dedent_multiline_strings`].
+    pub fn consume_multiline_string(&self) -> Result<Option<Token<'src>>, Error> {
+        let Some((span, content)) = self.multiline_string_at(self.location())? else {
+            return Ok(None);
+        };
+        let (leading, trailing) = self.trivia(span);
+        self.commit(span.end);
+        let content = if self.config.dedent_multiline_strings {
+            dedent(content)
+        } else {
+            Cow::Borrowed(content)
+        };
+        Ok(Some(Token {
+            span,
+            kind: TokenKind::String,
+            content,
+            leading,
+            trailing,
+        }))
+    }
+
+    /// Consumes a `"..."` string containing at least one `${expr}` hole, splitting it into
+    /// literal segments and the spans of the embedded expressions. Strings without holes
+    /// are left alone. `\${` is an escaped, literal `${`.
+    pub fn consume_interpolated_string(&self) -> Result<Option<StringParts<'src>>, Error> {
+        let start = self.location();
+        let rest = &self.source[start.index..];
+        if !rest.starts_with('"') || rest.starts_with("\"\"\"") {
+            return Ok(None);
+        }
+
+        let unterminated = |location: Location, message: &str| Error::at(location, message);
+        let mut parts = Vec::new();
+        let mut literal = |parts: &mut Vec<StringPart<'src>>, span: Span| {
+            if !span.is_empty() {
+                let content = &self.source[span.start.index..span.end.index];
+                parts.push(StringPart::Literal(Token {
                     span,
-                    content: Cow::Borrowed(self.lex_for(span).unwrap()) 
+                    kind: TokenKind::String,
+                    content: Cow::Borrowed(content),
+                    leading: Vec::new(),
+                    trailing: Vec::new(),
+                }));
+            }
+        };
+
+        let mut end = start;
+        self.adv(&mut end, '"');
+        let mut segment_start = end;
+        let mut interpolated = false;
+        let mut chars = rest[1..].chars().peekable();
+        loop {
+            match chars.next() {
+                None | Some('\n') => return Err(unterminated(start, "unterminated string")),
+                Some('"') => {
+                    literal(&mut parts, Span { start: segment_start, end });
+                    self.adv(&mut end, '"');
+                    break;
                 }
-            })
+                Some('\\') => {
+                    self.adv(&mut end, '\\');
+                    if let Some(c) = chars.next() {
+                        self.adv(&mut end, c);
+                    }
+                }
+                Some('$') if chars.peek() == Some(&'{') => {
+                    interpolated = true;
+                    literal(&mut parts, Span { start: segment_start, end });
+                    let open = end;
+                    chars.next();
+                    self.adv(&mut end, '$');
+                    self.adv(&mut end, '{');
+
+                    let expr_start = end;
+                    let mut depth = 1usize;
+                    let mut in_string = false;
+                    loop {
+                        let Some(c) = chars.next() else {
+                            return Err(unterminated(open, "unterminated `${` in string"));
+                        };
+                        match c {
+                            '\\' if in_string => {
+                                self.adv(&mut end, c);
+                                if let Some(c) = chars.next() {
+                                    self.adv(&mut end, c);
+                                }
+                                continue;
+                            }
+                            '"' => in_string = !in_string,
+                            '{' if !in_string => depth += 1,
+                            '}' if !in_string => {
+                                depth -= 1;
+                                if depth == 0 {
+                                    break;
+                                }
+                            }
+                            _ => {}
+                        }
+                        self.adv(&mut end, c);
+                    }
+                    parts.push(StringPart::Expr(Span { start: expr_start, end }));
+                    self.adv(&mut end, '}');
+                    segment_start = end;
+                }
+                Some(c) => self.adv(&mut end, c),
+            }
+        }
+
+        if !interpolated {
+            return Ok(None);
+        }
+        self.commit(end);
+        Ok(Some(StringParts {
+            span: Span { start, end },
+            parts,
+        }))
+    }
+
+    /// A tokenizer over just the source covered by `span`, reporting locations relative
+    /// to the full source. Used to parse embedded fragments such as `${...}` holes.
+    /// Nesting continues from the current depth, so a hole can't reset the limit, and
+    /// the [extensions](Tokenizer::with_extension) carry over.
+    pub fn sub_tokenizer(&self, span: Span) -> Tokenizer<'src> {
+        Tokenizer {
+            source: &self.source[..span.end.index],
+            location: Cell::new(span.start),
+            trivia_start: Cell::new(span.start),
+            skipped: Cell::new(None),
+            raw: Cell::new(false),
+            error: RefCell::new(None),
+            config: self.config.clone(),
+            lookahead: RefCell::new(Lookahead::default()),
+            progress: RefCell::new(None),
+            depth: Cell::new(self.depth.get()),
+            diagnostics: RefCell::new(Vec::new()),
+            expected: RefCell::default(),
+            extensions: self.extensions.clone(),
+        }
+    }
+
+    /// Consumes `keyword` only when it is a whole identifier, so `ifx` never matches `if`.
+    pub fn consume_keyword(&self, keyword: &str) -> Option<Token<'src>> {
+        let token = self.ident_span()
+            .filter(|span| self.lex_for(*span) == Some(keyword))
+            .map(|span| self.bump(span));
+        trace!(at = %self.loc(), keyword, matched = token.is_some(), "consume_keyword");
+        token
     }
 
     #[inline]
     pub fn cursor_for(&self, loc: Location) -> Option<&'src str> {
-        (loc.index < self.source.len())
-            .then(|| &self.source[loc.index..])
+        self.try_cursor_for(loc).ok()
+            .filter(|cursor| !cursor.is_empty())
     }
 
     #[inline]
     pub fn lex_for(&self, span: Span) -> Option<&'src str> {
-        self.cursor_for(span.start)
-            .and_then(|cursor| {
-                (span.len() <= cursor.len())
-                    .then(|| &cursor[..span.len()])
-            })
+        self.try_lex_for(span).ok()
+    }
+
+    /// The source from `loc` on, or an error if `loc` is past the end or inside a
+    /// multi-byte character.
+    pub fn try_cursor_for(&self, loc: Location) -> Result<&'src str, Error> {
+        self.source.get(loc.index..)
+            .ok_or_else(|| Error::at(loc, format!("byte {} is not a character boundary in the source", loc.index)))
+    }
+
+    /// The source covered by `span`, or an error if the span is reversed, out of bounds,
+    /// or cuts through a multi-byte character.
+    pub fn try_lex_for(&self, span: Span) -> Result<&'src str, Error> {
+        self.source.get(span.start.index..span.end.index)
+            .ok_or_else(|| Error::new(span, format!("span {}..{} does not cover whole characters of the source", span.start.index, span.end.index)))
     }
 
     #[inline]
     pub fn peek_str(&self, str: &str) -> Option<Span> {
         let start = self.location();
-        let mut end = start;
-        for (a, b) in self.cursor().chars().zip(str.chars()) {
-            if a != b {
-                return None;
-            }
-            Self::adv(&mut end, a);
+        if str.is_empty() || !self.cursor().starts_with(str) {
+            return None;
         }
-        (start != end)
-            .then(|| Span { start, end })
+        let mut end = start;
+        self.adv_str(&mut end, str);
+        trace!(at = %start, pattern = str, "peek_str matched");
+        Some(Span { start, end })
+    }
+
+    /// Consumes whichever of `options` matches, preferring the longest, so `<=` is never
+    /// split into `<` and `=`.
+    pub fn consume_any(&self, options: &[&str]) -> Option<Token<'src>> {
+        let s = options.iter()
+            .filter(|s| self.peek_str(s).is_some())
+            .max_by_key(|s| s.len())?;
+        self.consume(s)
     }
 
     #[inline]
     pub fn consume_while(&self, f: impl Fn(char) -> bool) -> Option<Token<'src>> {
         let start = self.location();
+        let cursor = self.cursor();
+        let ascii = cursor.bytes()
+            .position(|b| !b.is_ascii() || !f(b as char))
+            .unwrap_or(cursor.len());
+        let len = match cursor.as_bytes().get(ascii) {
+            Some(b) if !b.is_ascii() => cursor[ascii..].char_indices()
+                .find(|(_, c)| !f(*c))
+                .map_or(cursor.len(), |(i, _)| ascii + i),
+            _ => ascii,
+        };
         let mut end = start;
-        let iter = self.cursor().chars().take_while(|c| f(*c));
-        for c in iter {
-            Self::adv(&mut end, c);
-        }
+        self.adv_str(&mut end, &cursor[..len]);
         (start != end)
-            .then(|| {
-                self.location.set(end);
-                let span = Span { start, end };
-                Token {
-                    span,
-                    content: Cow::Borrowed(self.lex_for(span)
-                        .expect("this should be unreachable"))
+            .then(|| self.bump(Span { start, end }))
+    }
+
+    /// Consumes `open`, everything up to its matching `close` (nested pairs included), and
+    /// the `close` itself, returning the span between the delimiters. Content is walked a
+    /// token at a time, so delimiters inside strings and comments don't count. `None` when
+    /// the input doesn't start with `open`; an error, with nothing consumed, when it is
+    /// never closed.
+    pub fn consume_balanced(&self, open: &str, close: &str) -> Result<Option<Span>, Error> {
+        let checkpoint = self.checkpoint();
+        let Some(left) = self.consume(open) else {
+            return Ok(None);
+        };
+        let outer = self.depth.get();
+        self.depth.set(outer + 1);
+        let result = loop {
+            if let Some(error) = self.nesting_error(left.span.start) {
+                break Err(error);
+            }
+            if let Some(right) = self.peek_str(close) {
+                self.commit(right.end);
+                if self.depth.get() == outer + 1 {
+                    break Ok(Span { start: left.span.end, end: right.start });
                 }
-            })
+                self.depth.set(self.depth.get() - 1);
+            } else if let Some(nested) = self.peek_str(open) {
+                self.depth.set(self.depth.get() + 1);
+                self.commit(nested.end);
+            } else {
+                match self.next_token() {
+                    Ok(token) if token.kind == TokenKind::Eof => break Err(Error::new(left.span, format!("unclosed `{open}`"))),
+                    Ok(_) => {}
+                    Err(e) => break Err(e),
+                }
+            }
+        };
+        self.depth.set(outer);
+        result
+            .map(Some)
+            .inspect_err(|_| self.restore(checkpoint))
+    }
+
+    /// Consumes the input matched by `pattern` as a single token, e.g.
+    /// `tok.consume_matching((Ident, ':'))` for a label. `None` if it doesn't match here
+    /// or only matches empty input.
+    pub fn consume_matching(&self, pattern: impl TokenPattern) -> Option<Token<'src>> {
+        let start = self.location();
+        let cursor = self.cursor();
+        let len = pattern.match_len(self, cursor).filter(|len| *len > 0)?;
+        let mut end = start;
+        self.adv_str(&mut end, &cursor[..len]);
+        Some(self.bump(Span { start, end }))
+    }
+
+    /// Consumes the rest of the current line, including its line ending, and returns it
+    /// as a [`TokenKind::String`] token without the line ending or leading blanks. Meant
+    /// for line-oriented directives like `#pragma ...`. `None` at the end of input.
+    pub fn consume_line(&self) -> Option<Token<'src>> {
+        let rest = &self.source[self.loc().index..];
+        if rest.is_empty() {
+            return None;
+        }
+        let blanks = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        let line_len = memchr::memchr(b'\n', rest.as_bytes()).unwrap_or(rest.len());
+        let line = &rest[blanks.min(line_len)..line_len];
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        let mut start = self.loc();
+        self.adv_str(&mut start, &rest[..blanks.min(line_len)]);
+        let mut end = start;
+        self.adv_str(&mut end, line);
+        let mut after = self.loc();
+        self.adv_str(&mut after, &rest[..(line_len + 1).min(rest.len())]);
+
+        let mut token = self.token(Span { start, end });
+        token.kind = TokenKind::String;
+        self.commit(after);
+        Some(token)
     }
 
     #[inline]
     pub fn consume(&self, s: &str) -> Option<Token<'src>> {
-        self.peek_str(s).map(|_| {
-            let start = self.loc();
-            let mut end = start;
-            for c in s.chars() {
-                Self::adv(&mut end, c);
-            }
-            self.location.set(end);
-            let span = Span { start, end };
-            Token {
-                span,
-                content: Cow::Borrowed(self.lex_for(span).unwrap()),
-            }
-        })
+        let token = self.peek_str(s).map(|span| self.bump(span));
+        trace!(at = %self.loc(), pattern = s, matched = token.is_some(), "consume");
+        token
     }
 
     #[inline]
     pub fn expect(&self, s: &str) -> Result<Token<'src>, Error> {
         let start = self.location();
+        if let Some(error) = self.error.borrow().clone().filter(|e| e.location() == start) {
+            return Err(error);
+        }
         self.consume(s)
-            .ok_or_else(|| Error {
-                location: start,
-                message: format!("Expected `{s:?}`"),
+            .ok_or_else(|| Error::at(start, format!("Expected `{s:?}`")))
+    }
+
+    /// Like [`Tokenizer::expect`], but the error names what the token was for:
+    /// ``expected `)` to close parameter list``.
+    #[inline]
+    pub fn expect_with(&self, s: &str, label: &str) -> Result<Token<'src>, Error> {
+        self.expect_one_of_with(&[s], label)
+    }
+
+    /// Consumes whichever of `options` matches (longest first, like [`Tokenizer::consume_any`]),
+    /// or fails with ``expected `,` or `)` ``.
+    #[inline]
+    pub fn expect_one_of(&self, options: &[&str]) -> Result<Token<'src>, Error> {
+        self.expect_one_of_with(options, "")
+    }
+
+    /// [`Tokenizer::expect_one_of`] with a label appended to the error, as in [`Tokenizer::expect_with`].
+    pub fn expect_one_of_with(&self, options: &[&str], label: &str) -> Result<Token<'src>, Error> {
+        let start = self.location();
+        if let Some(error) = self.error.borrow().clone().filter(|e| e.location() == start) {
+            return Err(error);
+        }
+        self.consume_any(options)
+            .ok_or_else(|| {
+                let mut message = format!("expected {}", one_of(options));
+                if !label.is_empty() {
+                    message.push(' ');
+                    message.push_str(label);
+                }
+                trace!(at = %start, error = message, "expect failed");
+                Error::at(start, message)
             })
     }
-}
 
+    /// Lexes one classified token, returning a [`TokenKind::Eof`] token at the end of input.
+    pub fn next_token(&self) -> Result<Token<'src>, Error> {
+        let token = self.lex_next();
+        match &token {
+            Ok(token) => {
+                trace!(at = %token.span.start, kind = ?token.kind, content = token.content(), "next_token");
+            }
+            Err(error) => {
+                trace!(at = %error.location(), error = error.message, "next_token failed");
+            }
+        }
+        token
+    }
+
+    fn lex_next(&self) -> Result<Token<'src>, Error> {
+        let start = self.location();
+        match self.lex_token() {
+            Err(_) if self.config.recover_errors => {
+                let end = self.invalid_end(start);
+                let mut token = self.bump(Span { start, end });
+                token.kind = TokenKind::Error;
+                Ok(token)
+            }
+            result => result,
+        }
+    }
+
+    fn lex_token(&self) -> Result<Token<'src>, Error> {
+        let start = self.location();
+        if let Some(error) = self.error.borrow().clone().filter(|e| e.location() == start) {
+            return Err(error);
+        }
+
+        let cursor = &self.source[start.index..];
+        let mut chars = cursor.chars();
+        let Some(first) = chars.next() else {
+            return Ok(self.token(Span { start, end: start }));
+        };
+
+        if self.raw.get() {
+            if let Some(trivia) = self.trivia_at(start) {
+                let (kind, end) = trivia?;
+                let mut token = self.bump(Span { start, end });
+                token.kind = TokenKind::Trivia(kind);
+                return Ok(token);
+            }
+        }
+
+        if let Some((span, _)) = self.raw_string_at(start)? {
+            self.check_len(span)?;
+            return Ok(self.bump(span));
+        }
+        if let Some((span, _)) = self.multiline_string_at(start)? {
+            self.check_len(span)?;
+            return Ok(self.bump(span));
+        }
+
+        if first.is_control() && !first.is_whitespace() {
+            return Err(Error::at(start, format!("unexpected character {:?}", first)));
+        }
+
+        let mut end = start;
+        self.adv(&mut end, first);
+        if self.is_ident_start(first) {
+            chars.take_while(|c| self.is_ident_continue(*c))
+                .for_each(|c| self.adv(&mut end, c));
+        } else if first.is_ascii_digit() {
+            if let Some(number) = self.number_at(start)? {
+                end = number.token.span.end;
+            }
+        } else if first == '"' {
+            let mut terminated = false;
+            while let Some(c) = chars.next() {
+                self.adv(&mut end, c);
+                match c {
+                    '"' => {
+                        terminated = true;
+                        break;
+                    }
+                    '\\' => {
+                        if let Some(c) = chars.next() {
+                            self.adv(&mut end, c);
+                        }
+                    }
+                    '\n' => break,
+                    _ => {}
+                }
+            }
+            if !terminated {
+                return Err(Error::at(start, "unterminated string"));
+            }
+        }
+
+        self.check_len(Span { start, end })?;
+        Ok(self.bump(Span { start, end }))
+    }
+
+    fn check_len(&self, span: Span) -> Result<(), Error> {
+        match self.config.max_token_len {
+            Some(max) if span.len() > max => Err(Error::new(span, format!("token is {} bytes long, over the limit of {max}", span.len()))),
+            _ => Ok(()),
+        }
+    }
+
+    fn nesting_error(&self, location: Location) -> Option<Error> {
+        let max = self.config.max_nesting?;
+        (self.depth.get() > max).then(|| Error::at(location, format!("nesting is deeper than the limit of {max}")))
+    }
+
+    /// Runs `f` one nesting level deeper, failing instead once that would exceed
+    /// [`TokenizerConfig::max_nesting`]. Recursive parsers wrap each delimited construct
+    /// in this to bound their recursion on untrusted input.
+    pub fn nested<T>(&self, f: impl FnOnce(&Self) -> ParseResult<T>) -> ParseResult<T> {
+        self.depth.set(self.depth.get() + 1);
+        let result = match self.nesting_error(self.location()) {
+            Some(error) => Err(error.into()),
+            None => f(self),
+        };
+        self.depth.set(self.depth.get() - 1);
+        result
+    }
+
+    /// Parses `open`, `f`, and `close`, returning the two delimiters around what `f`
+    /// parsed. Doesn't match without `open`; past it, `f` not matching is an error and
+    /// so is a missing `close`, reported with `label` as in [`Tokenizer::expect_with`]:
+    /// ``expected `)` to close argument list``.
+    pub fn delimited<T>(
+        &self,
+        open: &str,
+        f: impl FnOnce(&Self) -> ParseResult<T>,
+        close: &str,
+        label: &str,
+    ) -> ParseResult<(Token<'src>, T, Token<'src>)> {
+        let Some(left) = self.consume(open) else {
+            return Err(self.expected(format!("`{open}`")));
+        };
+        let inner = f(self).required(self)?;
+        let right = self.expect_with(close, label)?;
+        Ok((left, inner, right))
+    }
+
+    /// [`Tokenizer::delimited`] around a `sep`-separated list of `f`, which may have a
+    /// trailing `sep`: `(a, b,)`.
+    pub fn separated_list<T>(
+        &self,
+        open: &str,
+        sep: &str,
+        close: &str,
+        label: &str,
+        mut f: impl FnMut(&Self) -> ParseResult<T>,
+    ) -> ParseResult<(Token<'src>, Vec<T>, Token<'src>)> {
+        self.delimited(open, |tok| {
+            let mut items = Vec::new();
+            while tok.has_more_tokens() && tok.peek_str(close).is_none() {
+                items.push(f(tok).required(tok)?);
+                if tok.consume(sep).is_none() {
+                    break;
+                }
+            }
+            Ok(items)
+        }, close, label)
+    }
+
+    /// Runs `f` until it doesn't match or the input runs out. Stops as well once `f`
+    /// matches without consuming anything, which would otherwise repeat forever.
+    pub fn many<T>(&self, mut f: impl FnMut(&Self) -> ParseResult<T>) -> ParseResult<Vec<T>> {
+        let mut items = Vec::new();
+        while self.has_more_tokens() {
+            let start = self.location();
+            let Some(item) = self.try_with(&mut f).optional()? else {
+                break;
+            };
+            items.push(item);
+            if self.location() == start {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Records that `what` (`` "`{`" ``, `"expression"`) would have been accepted at the
+    /// cursor and returns [`ParseError::NoMatch`]. Only the alternatives tried at the
+    /// furthest location are kept, so [`Tokenizer::expectation_error`] can list them.
+    pub fn expected(&self, what: impl Into<String>) -> ParseError {
+        self.expect_at(self.location(), what.into());
+        ParseError::NoMatch
+    }
+
+    fn expect_at(&self, location: Location, what: String) {
+        let mut expected = self.expected.borrow_mut();
+        match expected.location {
+            Some(furthest) if furthest > location => {}
+            Some(furthest) if furthest == location => {
+                if !expected.items.contains(&what) {
+                    expected.items.push(what);
+                }
+            }
+            _ => {
+                expected.location = Some(location);
+                expected.items = vec![what];
+            }
+        }
+    }
+
+    /// Runs `f`, and if it doesn't match without getting past the cursor, replaces
+    /// everything it [expected](Tokenizer::expected) with `label`, so errors say
+    /// `expected type` rather than listing the first token of every kind of type.
+    pub fn labelled<T>(&self, label: &str, f: impl FnOnce(&Self) -> ParseResult<T>) -> ParseResult<T> {
+        let start = self.location();
+        let before = {
+            let expected = self.expected.borrow();
+            if expected.location == Some(start) { expected.items.len() } else { 0 }
+        };
+        let result = f(self);
+        if let Err(ParseError::NoMatch) = result {
+            let mut expected = self.expected.borrow_mut();
+            if expected.location == Some(start) {
+                expected.items.truncate(before);
+            }
+            drop(expected);
+            self.expect_at(start, label.to_string());
+        }
+        result
+    }
+
+    /// Builds the error for input no alternative matched, at the furthest location one
+    /// was tried: ``expected `{`, `if`, or expression, found `)` ``. Clears the recorded
+    /// expectations. Without any, it just names the token at the cursor.
+    pub fn expectation_error(&self) -> Error {
+        let Expectations { location, items } = std::mem::take(&mut *self.expected.borrow_mut());
+        let location = location.filter(|furthest| *furthest >= self.location()).unwrap_or(self.location());
+
+        let checkpoint = self.checkpoint();
+        self.seek(location);
+        let found = self.peek_token();
+        self.restore(checkpoint);
+        let (span, found) = match found {
+            Ok(token) if token.kind == TokenKind::Eof => (token.span, "end of input".to_string()),
+            Ok(token) => (token.span, format!("`{}`", token.content())),
+            Err(error) => return error,
+        };
+        let mut error = match items.as_slice() {
+            [] => Error::new(span, format!("unexpected {found}")),
+            items => Error::new(span, format!("expected {}, found {found}", or_list(items))),
+        };
+        error.expected = items;
+        error
+    }
+
+    /// Lexes the next classified token without consuming it.
+    #[inline]
+    pub fn peek_token(&self) -> Result<Token<'src>, Error> {
+        self.peek_nth(0)
+    }
+
+    /// Lexes the `n`th upcoming token (0-based) without consuming anything.
+    /// Tokens lexed ahead are buffered until the cursor moves.
+    pub fn peek_nth(&self, n: usize) -> Result<Token<'src>, Error> {
+        let origin = self.checkpoint();
+        let mut lookahead = self.lookahead.borrow_mut();
+        if lookahead.origin != Some(origin) || lookahead.raw != self.raw.get() {
+            lookahead.origin = Some(origin);
+            lookahead.raw = self.raw.get();
+            lookahead.tokens.clear();
+            lookahead.resume = None;
+        }
+
+        if let Some(resume) = lookahead.resume {
+            self.restore(resume);
+        }
+        while lookahead.tokens.len() <= n {
+            if let Some(Err(_)) = lookahead.tokens.last() {
+                break;
+            }
+            let token = self.lex_next();
+            lookahead.tokens.push(token);
+        }
+        lookahead.resume = Some(self.checkpoint());
+        self.restore(origin);
+
+        let token = match lookahead.tokens.get(n).or(lookahead.tokens.last()) {
+            Some(Ok(token)) => Ok(token.clone()),
+            Some(Err(e)) => Err(e.clone()),
+            None => unreachable!("lookahead buffer is never empty here"),
+        };
+        trace!(at = %origin.location, n, token = ?token.as_ref().map(Token::content), "peek_nth");
+        token
+    }
+
+    /// Lexes the rest of the input, skipping past each lexical error instead of stopping.
+    pub fn tokenize_all(&self) -> (Vec<Token<'src>>, Vec<Error>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            match self.next_token() {
+                Ok(token) if token.kind == TokenKind::Eof => break,
+                Ok(token) => tokens.push(token),
+                Err(e) => {
+                    self.skip_invalid(e.location());
+                    errors.push(e);
+                }
+            }
+        }
+        (tokens, errors)
+    }
+
+    /// Moves past whatever failed to lex at `start`: a stray control character is skipped
+    /// alone, an unterminated block comment swallows the rest of the input, and anything
+    /// else the rest of the line.
+    pub(crate) fn skip_invalid(&self, start: Location) {
+        self.location.set(self.invalid_end(start));
+    }
+
+    fn invalid_end(&self, start: Location) -> Location {
+        let rest = &self.source[start.index..];
+        let mut end = start;
+        if let Some(c) = rest.chars().next().filter(|c| c.is_control() && !c.is_whitespace()) {
+            self.adv(&mut end, c);
+            return end;
+        }
+        let in_comment = self.config.block_comments.iter()
+            .any(|(open, _)| rest.starts_with(open.as_str()));
+        for c in rest.chars() {
+            if c == '\n' && !in_comment {
+                break;
+            }
+            self.adv(&mut end, c);
+        }
+        end
+    }
+
+    #[inline]
+    pub fn iter<'tok>(&'tok self) -> Tokens<'tok, 'src> {
+        Tokens { tok: self, done: false }
+    }
+}
+
+/// Lists `options` as `` `a` ``, `` `a` or `b` ``, or `` `a`, `b`, or `c` ``.
+fn one_of(options: &[&str]) -> String {
+    or_list(&options.iter().map(|s| format!("`{s}`")).collect::<Vec<_>>())
+}
+
+/// Joins `items` as `a`, `a or b`, or `a, b, or c`.
+fn or_list(items: &[String]) -> String {
+    match items {
+        [] => "nothing".to_string(),
+        [one] => one.clone(),
+        [a, b] => format!("{a} or {b}"),
+        [init @ .., last] => format!("{}, or {last}", init.join(", ")),
+    }
+}
+
+/// Drops a leading newline and a trailing whitespace-only line, then removes the
+/// indentation shared by every non-blank line.
+fn dedent(s: &str) -> Cow<'_, str> {
+    let s = s.strip_prefix("\r\n").or_else(|| s.strip_prefix('\n')).unwrap_or(s);
+    let s = match s.rfind('\n') {
+        Some(i) if s[i + 1..].trim().is_empty() => s[..i].strip_suffix('\r').unwrap_or(&s[..i]),
+        _ => s,
+    };
+    let indent = s.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    if indent == 0 {
+        return Cow::Borrowed(s);
+    }
+
+    let mut out = String::with_capacity(s.len());
+    for (i, line) in s.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        out.push_str(line.get(indent..).unwrap_or_else(|| line.trim_start()));
+    }
+    Cow::Owned(out)
+}
+
+/// Iterator over the remaining tokens of a [`Tokenizer`], ending at EOF or after the first error.
+pub struct Tokens<'tok, 'src> {
+    tok: &'tok Tokenizer<'src>,
+    done: bool,
+}
+
+impl<'tok, 'src> Iterator for Tokens<'tok, 'src> {
+    type Item = Result<Token<'src>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.tok.next_token() {
+            Ok(token) if token.kind == TokenKind::Eof => {
+                self.done = true;
+                None
+            }
+            Ok(token) => Some(Ok(token)),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for Tokens<'_, '_> {}
+
+impl<'tok, 'src> IntoIterator for &'tok Tokenizer<'src> {
+    type Item = Result<Token<'src>, Error>;
+    type IntoIter = Tokens<'tok, 'src>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Tokenizer, TokenizerConfig};
+    use crate::StringPart;
+    use crate::{Location, TokenKind, NumberKind, TriviaKind};
+
+    #[test]
+    fn test_line_comments() {
+        let tok = Tokenizer::new("// leading\n  foo // trailing\n// only a comment");
+        assert_eq!(tok.consume("foo").unwrap(), "foo");
+        assert!(!tok.has_more_tokens());
+
+        let tok = Tokenizer::new("a / b");
+        assert!(tok.consume("a").is_some());
+        assert!(tok.consume("/").is_some());
+        assert!(tok.consume("b").is_some());
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let tok = Tokenizer::new("/* a /* nested */ comment */ foo /**/ bar");
+        assert_eq!(tok.consume("foo").unwrap(), "foo");
+        assert_eq!(tok.consume("bar").unwrap(), "bar");
+        assert!(!tok.has_more_tokens());
+        assert!(tok.error().is_none());
+
+        let tok = Tokenizer::new("foo\n  /* a /* nested */ comment");
+        assert!(tok.consume("foo").is_some());
+        let err = tok.expect("bar").unwrap_err();
+        assert_eq!((err.location().line, err.location().column), (1, 2));
+        assert_eq!(err.message, "unterminated block comment");
+    }
+
+    #[test]
+    fn test_config() {
+        let config = TokenizerConfig {
+            line_comments: vec!["#".to_string(), ";;".to_string()],
+            block_comments: vec![("(*".to_string(), "*)".to_string())],
+            newlines_are_whitespace: false,
+            ..TokenizerConfig::default()
+        };
+        let tok = Tokenizer::new_with_config("# one\n", config.clone());
+        assert!(tok.consume("\n").is_some());
+
+        let tok = Tokenizer::new_with_config("(* a (* b *) *) foo ;; bar\n// baz", config);
+        assert!(tok.consume("foo").is_some());
+        assert!(tok.consume("\n").is_some());
+        assert!(tok.consume("//").is_some());
+    }
+
+    #[test]
+    fn test_next_token() {
+        let config = TokenizerConfig {
+            keywords: vec!["if".to_string()],
+            ..TokenizerConfig::default()
+        };
+        let tok = Tokenizer::new_with_config(r#"if ifx 0x1F "a\"b" { "#, config);
+        let mut kinds = Vec::new();
+        loop {
+            let token = tok.next_token().unwrap();
+            kinds.push((token.kind, token.content.into_owned()));
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+        }
+        assert_eq!(kinds, [
+            (TokenKind::Keyword, "if".to_string()),
+            (TokenKind::Ident, "ifx".to_string()),
+            (TokenKind::Number, "0x1F".to_string()),
+            (TokenKind::String, r#""a\"b""#.to_string()),
+            (TokenKind::Punct, "{".to_string()),
+            (TokenKind::Eof, "".to_string()),
+        ]);
+
+        let tok = Tokenizer::new("\"abc");
+        assert_eq!(tok.next_token().unwrap_err().message, "unterminated string");
+    }
+
+    #[test]
+    fn test_iter() {
+        let tok = Tokenizer::new("a + 12 // done");
+        let lexemes = tok.iter()
+            .map(|t| t.map(|t| t.content.into_owned()))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(lexemes, ["a", "+", "12"]);
+
+        let tok = Tokenizer::new("a \"b");
+        let items = (&tok).into_iter().collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+        assert!(items[1].is_err());
+    }
+
+    #[test]
+    fn test_tokenize_all() {
+        let tok = Tokenizer::new("a \"b\nc /* d");
+        let (tokens, errors) = tok.tokenize_all();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0], "a");
+        assert_eq!(tokens[1], "c");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].message, "unterminated string");
+        assert_eq!(errors[1].message, "unterminated block comment");
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let tok = Tokenizer::new("foo bar baz");
+        assert!(tok.consume("foo").is_some());
+        let checkpoint = tok.checkpoint();
+        assert!(tok.consume("bar").is_some());
+        assert!(tok.consume("baz").is_some());
+        tok.restore(checkpoint);
+        assert_eq!(tok.consume("bar").unwrap().span.start.index, 4);
+    }
+
+    #[test]
+    fn test_peek_token() {
+        let tok = Tokenizer::new("( {");
+        let peeked = tok.peek_token().unwrap();
+        assert_eq!(peeked, "(");
+        assert_eq!(peeked.kind, TokenKind::Punct);
+        assert_eq!(tok.next_token().unwrap().span.start, peeked.span.start);
+        assert_eq!(tok.peek_token().unwrap(), "{");
+    }
+
+    #[test]
+    fn test_peek_nth() {
+        let tok = Tokenizer::new("x : int = 1");
+        assert_eq!(tok.peek_nth(2).unwrap(), "int");
+        assert_eq!(tok.peek_nth(1).unwrap(), ":");
+        assert_eq!(tok.peek_nth(9).unwrap().kind, TokenKind::Eof);
+        assert!(tok.consume("x").is_some());
+        assert_eq!(tok.peek_nth(1).unwrap(), "int");
+
+        let tok = Tokenizer::new("a \"b");
+        assert!(tok.peek_nth(1).is_err());
+        assert!(tok.peek_nth(3).is_err());
+        assert_eq!(tok.peek_nth(0).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_consume_any() {
+        let tok = Tokenizer::new("<= << < <");
+        let ops = ["<", "<=", "<<"];
+        assert_eq!(tok.consume_any(&ops).unwrap(), "<=");
+        assert_eq!(tok.consume_any(&ops).unwrap(), "<<");
+        assert_eq!(tok.consume_any(&ops).unwrap(), "<");
+        assert!(tok.consume_any(&["<<="]).is_none());
+        assert_eq!(tok.consume_any(&ops).unwrap(), "<");
+        assert!(tok.consume_any(&ops).is_none());
+    }
+
+    #[test]
+    fn test_keywords() {
+        let config = TokenizerConfig {
+            keywords: vec!["if".to_string()],
+            ..TokenizerConfig::default()
+        };
+        let tok = Tokenizer::new_with_config("ifx if(x)", config);
+        assert!(tok.is_keyword("if"));
+        assert!(tok.consume_keyword("if").is_none());
+        assert_eq!(tok.consume_ident().unwrap(), "ifx");
+        assert!(tok.consume_ident().is_none());
+        let kw = tok.consume_keyword("if").unwrap();
+        assert_eq!(kw.kind, TokenKind::Keyword);
+        assert!(tok.consume("(").is_some());
+    }
+
+    #[test]
+    fn test_numbers() {
+        let tok = Tokenizer::new("0x1_Fu8 0b1010 0o17 1_000_000 3.14 1e9 2.5E-3f32 7.foo");
+        let mut numbers = Vec::new();
+        while let Some(number) = tok.consume_number().unwrap() {
+            numbers.push((number.kind, number.radix, number.value, number.suffix));
+        }
+        assert_eq!(numbers, [
+            (NumberKind::Int, 16, "1F".to_string(), Some("u8")),
+            (NumberKind::Int, 2, "1010".to_string(), None),
+            (NumberKind::Int, 8, "17".to_string(), None),
+            (NumberKind::Int, 10, "1000000".to_string(), None),
+            (NumberKind::Float, 10, "3.14".to_string(), None),
+            (NumberKind::Float, 10, "1e9".to_string(), None),
+            (NumberKind::Float, 10, "2.5E-3".to_string(), Some("f32")),
+            (NumberKind::Int, 10, "7".to_string(), None),
+        ]);
+        assert!(tok.consume(".").is_some());
+
+        let err = Tokenizer::new("0b102").consume_number().unwrap_err();
+        assert_eq!(err.location().column, 4);
+        assert!(Tokenizer::new("0x").consume_number().is_err());
+        assert!(Tokenizer::new("x1").consume_number().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        let tok = Tokenizer::new(r###"r"C:\dir\n" r#"say "hi""# r"oops"###);
+        let path = tok.consume_raw_string().unwrap().unwrap();
+        assert_eq!(path.content, r"C:\dir\n");
+        assert!(matches!(path.content, std::borrow::Cow::Borrowed(_)));
+        assert_eq!(path.span.len(), 11);
+        assert_eq!(tok.peek_token().unwrap().kind, TokenKind::String);
+        assert_eq!(tok.consume_raw_string().unwrap().unwrap(), r#"say "hi""#);
+        assert_eq!(tok.consume_raw_string().unwrap_err().message, "unterminated raw string");
+        assert!(Tokenizer::new("rx").consume_raw_string().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_multiline_strings() {
+        let src = "\"\"\"\n    fn main() {\n        go()\n    }\n    \"\"\" after";
+        let tok = Tokenizer::new(src);
+        let text = tok.consume_multiline_string().unwrap().unwrap();
+        assert_eq!(text.content, "fn main() {\n    go()\n}");
+        assert_eq!(text.span.end.line, 4);
+        assert!(tok.consume("after").is_some());
+
+        let config = TokenizerConfig {
+            dedent_multiline_strings: false,
+            ..TokenizerConfig::default()
+        };
+        let tok = Tokenizer::new_with_config("\"\"\"\n  a\n\"\"\"", config);
+        assert_eq!(tok.peek_token().unwrap().kind, TokenKind::String);
+        assert_eq!(tok.consume_multiline_string().unwrap().unwrap(), "\n  a\n");
+
+        let tok = Tokenizer::new("\"\"\" open");
+        assert!(tok.consume_multiline_string().is_err());
+    }
+
+    #[test]
+    fn test_interpolated_strings() {
+        let tok = Tokenizer::new(r#""x = ${x + f("}")}!" "plain""#);
+        let string = tok.consume_interpolated_string().unwrap().unwrap();
+        assert_eq!(string.parts.len(), 3);
+        let StringPart::Expr(span) = &string.parts[1] else {
+            panic!("expected an expression part");
+        };
+        assert_eq!(tok.lex_for(*span).unwrap(), r#"x + f("}")"#);
+        let inner = tok.sub_tokenizer(*span);
+        assert_eq!(inner.consume("x").unwrap().span.start.index, 7);
+        assert!(matches!(&string.parts[2], StringPart::Literal(t) if t.content() == "!"));
+
+        assert!(tok.consume_interpolated_string().unwrap().is_none());
+        assert!(Tokenizer::new(r#""${x""#).consume_interpolated_string().is_err());
+    }
+
+    #[test]
+    fn test_ident_classes() {
+        let tok = Tokenizer::new("naïve _x1 x-y");
+        assert_eq!(tok.consume_ident().unwrap(), "naïve");
+        assert_eq!(tok.consume_ident().unwrap(), "_x1");
+        assert_eq!(tok.consume_ident().unwrap(), "x");
+
+        let config = TokenizerConfig {
+            ident_continue: |c| super::is_xid_ident_continue(c) || c == '-',
+            ..TokenizerConfig::default()
+        };
+        let tok = Tokenizer::new_with_config("list-head", config);
+        assert_eq!(tok.next_token().unwrap(), "list-head");
+    }
+
+    #[test]
+    fn test_columns() {
+        let tok = Tokenizer::new("a\r\n\tb\r\nc");
+        assert!(tok.consume("a").is_some());
+        let b = tok.consume("b").unwrap();
+        assert_eq!((b.span.start.line, b.span.start.column), (1, 4));
+        let c = tok.consume("c").unwrap();
+        assert_eq!((c.span.start.line, c.span.start.column), (2, 0));
+
+        let config = TokenizerConfig {
+            tab_width: 8,
+            ..TokenizerConfig::default()
+        };
+        let tok = Tokenizer::new_with_config("ab\tc", config);
+        assert!(tok.consume("ab").is_some());
+        assert_eq!(tok.consume("c").unwrap().span.start.column, 8);
+    }
+
+    #[test]
+    fn test_utf16_columns() {
+        let tok = Tokenizer::new("é𝄞 x\n\ty");
+        assert!(tok.consume("é𝄞").is_some());
+        let x = tok.consume("x").unwrap();
+        assert_eq!((x.span.start.column, x.span.start.utf16_column), (3, 4));
+        let y = tok.consume("y").unwrap();
+        assert_eq!((y.span.start.column, y.span.start.utf16_column), (4, 1));
+    }
+
+    #[test]
+    fn test_trivia() {
+        let src = "  // header\nfoo /* c */ bar // tail\n\n  /* x */ baz\n";
+        let config = TokenizerConfig {
+            preserve_trivia: true,
+            ..TokenizerConfig::default()
+        };
+        let tok = Tokenizer::new_with_config(src, config);
+        let bar = tok.peek_nth(1).unwrap();
+        assert_eq!(bar.leading.len(), 0);
+        assert_eq!(bar.trailing.iter().map(|t| t.content.as_ref()).collect::<String>(), " // tail\n");
+
+        let mut rebuilt = String::new();
+        loop {
+            let token = tok.next_token().unwrap();
+            rebuilt.extend(token.leading.iter().map(|t| t.content.as_ref()));
+            rebuilt.push_str(token.content());
+            rebuilt.extend(token.trailing.iter().map(|t| t.content.as_ref()));
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+        }
+        assert_eq!(rebuilt, src);
+
+        let tok = Tokenizer::new_with_config("a /* b */", TokenizerConfig {
+            preserve_trivia: true,
+            ..TokenizerConfig::default()
+        });
+        let a = tok.consume("a").unwrap();
+        assert_eq!(a.trailing.len(), 2);
+        assert_eq!(a.trailing[1].kind, TriviaKind::BlockComment);
+    }
+
+    #[test]
+    fn test_skip_cache() {
+        let tok = Tokenizer::new("a  /* open");
+        tok.consume("a").unwrap();
+        let skipped = tok.location();
+        assert_eq!(skipped.index, 3);
+        assert_eq!(tok.location(), skipped);
+        assert_eq!(tok.error().unwrap().location(), skipped);
+
+        let tok = Tokenizer::new("a  b");
+        let start = tok.checkpoint();
+        tok.consume("a").unwrap();
+        assert_eq!(tok.location().index, 3);
+        tok.restore(start);
+        assert_eq!(tok.location().index, 0);
+        assert_eq!(tok.consume_ident().unwrap().content, "a");
+        assert_eq!(tok.consume_ident().unwrap().content, "b");
+    }
+
+    #[test]
+    fn test_adv_str() {
+        let tok = Tokenizer::new("");
+        for s in ["plain ascii", "two\nlines\n", "tab\there\r\nnext", "ünï\tcode\n\u{1F600}x"] {
+            let mut fast = Location::zero();
+            tok.adv_str(&mut fast, s);
+            let mut slow = Location::zero();
+            s.chars().for_each(|c| tok.adv(&mut slow, c));
+            assert_eq!(fast, slow, "{s:?}");
+        }
+
+        let src = "  \u{3000} // ü note\n  abc123ü!";
+        let tok = Tokenizer::new(src);
+        assert_eq!(tok.location().index, src.find("abc").unwrap());
+        let word = tok.consume_while(|c| c.is_alphanumeric()).unwrap();
+        assert_eq!(word.content, "abc123ü");
+        assert_eq!(word.span.end.column, 9);
+        assert!(tok.peek_str("!").is_some());
+        assert!(tok.peek_str("").is_none());
+    }
+
+    #[test]
+    fn test_recover_errors() {
+        let src = "a \"open\nb \u{0} c /* never closed";
+        let tok = Tokenizer::new(src);
+        assert_eq!(tok.iter().filter_map(Result::ok).count(), 1);
+
+        let config = TokenizerConfig {
+            recover_errors: true,
+            ..TokenizerConfig::default()
+        };
+        let tok = Tokenizer::new_with_config(src, config);
+        let tokens = tok.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        let kinds = tokens.iter().map(|t| (t.kind, t.content())).collect::<Vec<_>>();
+        assert_eq!(kinds, [
+            (TokenKind::Ident, "a"),
+            (TokenKind::Error, "\"open"),
+            (TokenKind::Ident, "b"),
+            (TokenKind::Error, "\u{0}"),
+            (TokenKind::Ident, "c"),
+            (TokenKind::Error, "/* never closed"),
+        ]);
+    }
+
+    #[test]
+    fn test_expect_one_of() {
+        let tok = Tokenizer::new("a, b) ]");
+        tok.consume_ident().unwrap();
+        assert_eq!(tok.expect_one_of(&[",", ")"]).unwrap().content, ",");
+        tok.consume_ident().unwrap();
+        assert_eq!(tok.expect_with(")", "to close parameter list").unwrap().content, ")");
+
+        let error = tok.expect_one_of_with(&[",", ")"], "to close parameter list").unwrap_err();
+        assert_eq!(error.message, "expected `,` or `)` to close parameter list");
+        assert_eq!(error.location().index, 6);
+        assert_eq!(tok.expect_with(")", "after arguments").unwrap_err().message, "expected `)` after arguments");
+        assert_eq!(tok.expect_one_of(&["a", "b", "c"]).unwrap_err().message, "expected `a`, `b`, or `c`");
+    }
+
+    #[test]
+    fn test_consume_line() {
+        let tok = Tokenizer::new("#pragma  once extra\r\nnext\n#empty\n\nlast");
+        tok.consume("#pragma").unwrap();
+        let line = tok.consume_line().unwrap();
+        assert_eq!(line.content, "once extra");
+        assert_eq!(line.kind, TokenKind::String);
+        assert_eq!(line.span.start.column, 9);
+        assert_eq!(tok.consume_ident().unwrap().content, "next");
+
+        tok.consume("#empty").unwrap();
+        assert_eq!(tok.consume_line().unwrap().content, "");
+        assert_eq!(tok.consume_line().unwrap().content, "");
+        assert_eq!(tok.consume_line().unwrap().content, "last");
+        assert!(tok.consume_line().is_none());
+    }
+
+    #[test]
+    fn test_consume_balanced() {
+        let src = "(a, (b, \")\"), /* ) */ c) rest";
+        let tok = Tokenizer::new(src);
+        let inner = tok.consume_balanced("(", ")").unwrap().unwrap();
+        assert_eq!(tok.lex_for(inner).unwrap(), "a, (b, \")\"), /* ) */ c");
+        assert_eq!(tok.consume_ident().unwrap().content, "rest");
+        assert!(tok.consume_balanced("(", ")").unwrap().is_none());
+
+        let tok = Tokenizer::new("{ { }");
+        let error = tok.consume_balanced("{", "}").unwrap_err();
+        assert_eq!(error.message, "unclosed `{`");
+        assert_eq!(tok.location().index, 0);
+
+        let tok = Tokenizer::new("begin x begin y end end");
+        let inner = tok.consume_balanced("begin", "end").unwrap().unwrap();
+        assert_eq!(tok.lex_for(inner).unwrap(), " x begin y end ");
+    }
+
+    #[test]
+    fn test_spans() {
+        let tok = Tokenizer::new("let x\n  = 10;");
+        let first = tok.next_token().unwrap().span;
+        let name = tok.next_token().unwrap().span;
+        let eq = tok.next_token().unwrap().span;
+        let value = tok.next_token().unwrap().span;
+
+        let node = first.merge(value);
+        assert_eq!((node.start, node.end), (first.start, value.end));
+        assert_eq!(value.merge(first).to_string(), "0:0..1:6");
+        assert_eq!(eq.to_string(), "1:2..1:3");
+
+        assert!(node.contains(name.start));
+        assert!(name.contains(name.start));
+        assert!(!name.contains(name.end));
+        assert!(!first.contains(value.start));
+    }
+
+    #[test]
+    fn test_into_owned() {
+        let config = TokenizerConfig {
+            preserve_trivia: true,
+            ..TokenizerConfig::default()
+        };
+        let token = {
+            let src = String::from("/* doc */ name // note");
+            let tok = Tokenizer::new_with_config(&src, config);
+            tok.next_token().unwrap().into_owned()
+        };
+        let token: crate::Token<'static> = token;
+        assert_eq!(token.content, "name");
+        assert_eq!(token.leading[0].content, "/* doc */");
+        assert_eq!(token.trailing[1].content, "// note");
+        std::thread::spawn(move || assert_eq!(token.kind, TokenKind::Ident)).join().unwrap();
+    }
+
+    #[test]
+    fn test_numeric_values() {
+        let tok = Tokenizer::new("0xFF 1_000u32 2.5e1 0b101 99999999999999999999 12 x 1.5");
+        let tokens = tok.iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(tokens[0].as_u64().unwrap(), 255);
+        assert_eq!(tokens[1].as_i64().unwrap(), 1000);
+        assert_eq!(tokens[2].as_f64().unwrap(), 25.0);
+        assert_eq!(tokens[3].as_f64().unwrap(), 5.0);
+
+        let error = tokens[4].as_u64().unwrap_err();
+        assert_eq!(error.message, "integer literal `99999999999999999999` does not fit in u64");
+        assert_eq!(error.location(), tokens[4].span.start);
+        assert_eq!(tokens[4].as_f64().unwrap(), 1e20);
+
+        assert_eq!(tokens[5].as_i64().unwrap(), 12);
+        assert_eq!(tokens[6].as_u64().unwrap_err().message, "expected a number, found `x`");
+        assert_eq!(tokens[7].as_i64().unwrap_err().message, "expected an integer, found `1.5`");
+    }
+
+    #[test]
+    fn test_progress() {
+        let reports = std::cell::RefCell::new(Vec::new());
+        let tok = Tokenizer::new("a b c d e f g");
+        assert_eq!((tok.bytes_consumed(), tok.bytes_total()), (0, 13));
+        tok.on_progress(2, |done, total| reports.borrow_mut().push((done, total)));
+
+        tok.peek_nth(2).unwrap();
+        let checkpoint = tok.checkpoint();
+        tok.consume_ident().unwrap();
+        tok.restore(checkpoint);
+        assert_eq!(tok.tokenize_all().0.len(), 7);
+        assert_eq!(tok.bytes_consumed(), 13);
+        drop(tok);
+        assert_eq!(reports.into_inner(), [(3, 13), (7, 13), (11, 13)]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let config = TokenizerConfig {
+            max_token_len: Some(4),
+            max_nesting: Some(2),
+            ..TokenizerConfig::default()
+        };
+        let tok = Tokenizer::new_with_config("abcd abcde", config.clone());
+        assert_eq!(tok.next_token().unwrap().content, "abcd");
+        let error = tok.next_token().unwrap_err();
+        assert_eq!(error.message, "token is 5 bytes long, over the limit of 4");
+        assert_eq!(error.location().index, 5);
+
+        let tok = Tokenizer::new_with_config("((x)) (((x)))", config.clone());
+        assert!(tok.consume_balanced("(", ")").unwrap().is_some());
+        let error = tok.consume_balanced("(", ")").unwrap_err();
+        assert_eq!(error.message, "nesting is deeper than the limit of 2");
+        assert_eq!(tok.location().index, 6);
+
+        fn parens(tok: &Tokenizer) -> crate::ParseResult<usize> {
+            tok.consume("(").ok_or(crate::ParseError::NoMatch)?;
+            tok.nested(|tok| {
+                let inner = match parens(tok) {
+                    Err(crate::ParseError::NoMatch) => 0,
+                    depth => depth? + 1,
+                };
+                tok.expect(")")?;
+                Ok(inner)
+            })
+        }
+        assert_eq!(parens(&Tokenizer::new_with_config("(())", config.clone())).unwrap(), 1);
+        let error = parens(&Tokenizer::new_with_config("((()))", config)).unwrap_err().into_error().unwrap();
+        assert_eq!(error.location().index, 3);
+    }
+
+    #[test]
+    fn test_checked_slicing() {
+        let tok = Tokenizer::new("añb");
+        let mut span = tok.next_token().unwrap().span;
+        assert_eq!(tok.try_lex_for(span).unwrap(), "añb");
+
+        span.end.index = 2;
+        assert!(tok.lex_for(span).is_none());
+        let error = tok.try_lex_for(span).unwrap_err();
+        assert_eq!(error.message, "span 0..2 does not cover whole characters of the source");
+
+        span.start.index = 3;
+        assert!(tok.try_lex_for(span).is_err());
+        span.start.index = 2;
+        assert!(tok.try_cursor_for(span.start).is_err());
+        span.start.index = 4;
+        assert_eq!(tok.try_cursor_for(span.start).unwrap(), "");
+        assert!(tok.cursor_for(span.start).is_none());
+    }
+
+    #[test]
+    fn test_raw_mode() {
+        let tok = Tokenizer::new("if x:\n    y // done\nz");
+        tok.consume_keyword("if");
+        let tokens = tok.raw(|tok| {
+            assert!(tok.peek_str("x").is_none());
+            std::iter::from_fn(|| tok.next_token().ok().filter(|t| t.kind != TokenKind::Eof))
+                .take(8)
+                .map(|t| (t.kind, t.content.into_owned()))
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(tokens, [
+            (TokenKind::Trivia(TriviaKind::Whitespace), " ".to_string()),
+            (TokenKind::Ident, "x".to_string()),
+            (TokenKind::Punct, ":".to_string()),
+            (TokenKind::Trivia(TriviaKind::Newline), "\n".to_string()),
+            (TokenKind::Trivia(TriviaKind::Whitespace), "    ".to_string()),
+            (TokenKind::Ident, "y".to_string()),
+            (TokenKind::Trivia(TriviaKind::Whitespace), " ".to_string()),
+            (TokenKind::Trivia(TriviaKind::LineComment), "// done".to_string()),
+        ]);
+        assert_eq!(tok.next_token().unwrap().content, "z");
+
+        let tok = Tokenizer::new("a  b");
+        tok.consume_ident();
+        assert_eq!(tok.raw(|tok| tok.peek_token().unwrap().kind), TokenKind::Trivia(TriviaKind::Whitespace));
+        assert_eq!(tok.peek_token().unwrap().content, "b");
+    }
+
+    #[test]
+    fn test_token_traits() {
+        let tok = Tokenizer::new("x + x\n  y x");
+        let tokens = tok.iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(tokens[0], tokens[2]);
+        assert_eq!(tokens[0], tokens[4]);
+        assert_ne!(tokens[0], tokens[3]);
+        assert!(tokens[0].eq_ignore_span(&tokens[4].clone()));
+        assert_eq!(tokens[1].to_string(), "+");
+
+        let mut counts = std::collections::HashMap::new();
+        for token in &tokens {
+            *counts.entry(token.clone()).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&tokens[0]], 3);
+        assert_eq!(counts.len(), 3);
+    }
+
+    #[test]
+    fn test_span_slicing() {
+        let source = String::from("let ñame = 1");
+        let span = Tokenizer::new(&source).peek_nth(1).unwrap().span;
+        assert_eq!(&source[span], "ñame");
+        assert_eq!(&source.as_str()[span], "ñame");
+        assert_eq!(span.byte_range(), 4..9);
+        assert_eq!(std::ops::Range::from(span), 4..9);
+    }
+
+    #[test]
+    fn test_span_ordering() {
+        let tok = Tokenizer::new("aa bb cc");
+        let tokens = tok.iter().map(|t| t.unwrap().span).collect::<Vec<_>>();
+        let (a, b, c) = (tokens[0], tokens[1], tokens[2]);
+        assert!(a.start < b.start && b.end > a.end);
+        assert!(a < b && b < c);
+        assert_eq!(a, a.merge(a));
+
+        let mut sorted = vec![c, a, b];
+        sorted.sort();
+        assert_eq!(sorted, tokens);
+        assert_eq!(tokens.binary_search_by(|span| span.start.cmp(&b.start)), Ok(1));
+
+        assert!(a.before(b) && b.after(a) && !b.before(a));
+        let ab = a.merge(b);
+        assert!(ab.overlaps(b) && !ab.overlaps(c) && !a.overlaps(b));
+
+        // Columns depend on the tab width; the position doesn't.
+        let wide = Location { column: a.start.column + 4, ..a.start };
+        assert_eq!(wide, a.start);
+        assert_eq!(wide.cmp(&a.start), std::cmp::Ordering::Equal);
+    }
+
+    /// A tokenizer event's message and its other fields, rendered with `{:?}`.
+    #[cfg(feature = "trace")]
+    type TraceEvent = (String, Vec<(String, String)>);
+
+    #[cfg(feature = "trace")]
+    fn trace_events(f: impl FnOnce()) -> Vec<TraceEvent> {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        #[derive(Default)]
+        struct Fields(String, Vec<(String, String)>);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                match field.name() {
+                    "message" => self.0 = format!("{value:?}"),
+                    name => self.1.push((name.to_string(), format!("{value:?}"))),
+                }
+            }
+        }
+
+        struct Collect(Arc<Mutex<Vec<TraceEvent>>>);
+
+        impl tracing::Subscriber for Collect {
+            fn enabled(&self, metadata: &Metadata) -> bool {
+                metadata.target() == "parse_rs::tokenizer"
+            }
+
+            fn new_span(&self, _: &Attributes) -> Id {
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _: &Id, _: &Record) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event) {
+                assert_eq!(*event.metadata().level(), tracing::Level::TRACE);
+                let mut fields = Fields::default();
+                event.record(&mut fields);
+                self.0.lock().unwrap().push((fields.0, fields.1));
+            }
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        tracing::subscriber::with_default(Collect(events.clone()), f);
+        let events = events.lock().unwrap().clone();
+        events
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_trace() {
+        let events = trace_events(|| {
+            let tok = Tokenizer::new("foo bar");
+            assert!(tok.consume("foo").is_some());
+            assert!(tok.consume("baz").is_none());
+            assert!(tok.consume_ident().is_some());
+        });
+        let field = |event: &TraceEvent, name: &str| {
+            event.1.iter().find(|(field, _)| field == name).map(|(_, value)| value.clone())
+        };
+        let consumes = events.iter()
+            .filter(|event| event.0 == "consume")
+            .map(|event| (field(event, "at").unwrap(), field(event, "pattern").unwrap(), field(event, "matched").unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(consumes, [
+            ("0:3".to_string(), "\"foo\"".to_string(), "true".to_string()),
+            ("0:4".to_string(), "\"baz\"".to_string(), "false".to_string()),
+        ]);
+        let ident = events.iter().find(|event| event.0 == "consume_ident").unwrap();
+        assert_eq!(field(ident, "ident").unwrap(), "Some(\"bar\")");
+    }
+
+    #[test]
+    fn test_error_trait() {
+        fn lex(src: &str) -> Result<usize, Box<dyn std::error::Error>> {
+            Ok(Tokenizer::new(src).iter().collect::<Result<Vec<_>, _>>()?.len())
+        }
+        assert_eq!(lex("a b").unwrap(), 2);
+        assert_eq!(lex("a\n \"b").unwrap_err().to_string(), "1:1: unterminated string");
+
+        let tok = Tokenizer::new("abc");
+        let span = tok.next_token().unwrap().span;
+        let cause = "x".parse::<u8>().unwrap_err();
+        let error = crate::Error::new(span, "bad name").with_source(cause);
+        assert_eq!(error.location(), span.start);
+        assert_eq!(error.span, span);
+        assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[test]
+    fn test_combinator_helpers() {
+        use crate::{ParseError, ParseResult};
+        fn word(tok: &Tokenizer) -> ParseResult<String> {
+            let token = tok.consume_ident().ok_or_else(|| tok.expected("word"))?;
+            Ok(token.content().to_string())
+        }
+
+        let tok = Tokenizer::new("(a, b,) [c] x y 1");
+        let (left, items, right) = tok.separated_list("(", ",", ")", "to close word list", word).unwrap();
+        assert_eq!((left.content(), right.content()), ("(", ")"));
+        assert_eq!(items, ["a", "b"]);
+        assert!(matches!(tok.separated_list("(", ",", ")", "to close word list", word), Err(ParseError::NoMatch)));
+        let (_, inner, _) = tok.delimited("[", word, "]", "to close brackets").unwrap();
+        assert_eq!(inner, "c");
+        assert_eq!(tok.many(word).unwrap(), ["x", "y"]);
+        assert_eq!(tok.consume("1").unwrap(), "1");
+
+        let error = |src: &str, f: fn(&Tokenizer) -> ParseResult<()>| f(&Tokenizer::new(src)).unwrap_err().into_error().unwrap().into_inner().message;
+        let list = |tok: &Tokenizer| tok.separated_list("(", ",", ")", "to close word list", word).map(drop);
+        assert_eq!(error("(a b)", list), "expected `)` to close word list");
+        assert_eq!(error("(a,", list), "expected `)` to close word list");
+        assert_eq!(error("(a, 1)", list), "expected word, found `1`");
+        assert_eq!(error("[]", |tok| tok.delimited("[", word, "]", "to close brackets").map(drop)), "expected word, found `]`");
+    }
+}
@@ -0,0 +1,507 @@
+//! Traversal of the syntax tree in [`ast`](crate::ast): [`Visit`] reads it and
+//! [`VisitMut`] rewrites it in place.
+//!
+//! Each `visit_*` method defaults to the matching `walk_*` function, which visits the
+//! node's children. Override the methods for the nodes you care about and call the
+//! `walk_*` function from the override to keep descending.
+
+use crate::ast::{Block, Decl, Else, Expr, FnType, Func, If, Macro, Module, Stmt, StrPart, Type};
+
+pub trait Visit<'ast> {
+    fn visit_module(&mut self, module: &'ast Module<'ast>) {
+        walk_module(self, module);
+    }
+
+    fn visit_decl(&mut self, decl: &'ast Decl<'ast>) {
+        walk_decl(self, decl);
+    }
+
+    fn visit_func(&mut self, func: &'ast Func<'ast>) {
+        walk_func(self, func);
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type<'ast>) {
+        walk_type(self, ty);
+    }
+
+    fn visit_fn_type(&mut self, ty: &'ast FnType<'ast>) {
+        walk_fn_type(self, ty);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt<'ast>) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_block(&mut self, block: &'ast Block<'ast>) {
+        walk_block(self, block);
+    }
+
+    fn visit_if(&mut self, stmt: &'ast If<'ast>) {
+        walk_if(self, stmt);
+    }
+
+    /// Macro arguments are raw tokens, so there is nothing below a macro to visit.
+    fn visit_macro(&mut self, _mac: &'ast Macro<'ast>) {}
+
+    fn visit_expr(&mut self, expr: &'ast Expr<'ast>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_str_part(&mut self, part: &'ast StrPart<'ast>) {
+        walk_str_part(self, part);
+    }
+}
+
+pub fn walk_module<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, module: &'ast Module<'ast>) {
+    for decl in &module.decls {
+        v.visit_decl(decl);
+    }
+}
+
+pub fn walk_decl<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, decl: &'ast Decl<'ast>) {
+    match decl {
+        Decl::Func(_, func) => v.visit_func(func),
+        Decl::Global { ty, init, .. } => {
+            if let Some(ty) = ty {
+                v.visit_type(ty);
+            }
+            if let Some(init) = init {
+                v.visit_expr(init);
+            }
+        }
+        Decl::TypeAlias { ty, .. } => v.visit_type(ty),
+        Decl::Import(..) | Decl::Mod(..) => {}
+    }
+}
+
+pub fn walk_func<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, func: &'ast Func<'ast>) {
+    v.visit_fn_type(&func.ty);
+    v.visit_block(&func.body);
+}
+
+pub fn walk_type<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, ty: &'ast Type<'ast>) {
+    match ty {
+        Type::Name(_) => {}
+        Type::Ptr(inner, _) => v.visit_type(inner),
+        Type::Array(elem, len, _) => {
+            v.visit_type(elem);
+            if let Some(len) = len {
+                v.visit_expr(len);
+            }
+        }
+        Type::Func(func) => v.visit_fn_type(func),
+        Type::Generic(_, args, _) => {
+            for arg in args {
+                v.visit_type(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_fn_type<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, ty: &'ast FnType<'ast>) {
+    for arg in &ty.args {
+        v.visit_type(arg);
+    }
+    if let Some(ret) = &ty.ret {
+        v.visit_type(ret);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, stmt: &'ast Stmt<'ast>) {
+    match stmt {
+        Stmt::If(stmt) => v.visit_if(stmt),
+        Stmt::Return(expr, _) | Stmt::Expr(expr) => v.visit_expr(expr),
+        Stmt::Block(block) => v.visit_block(block),
+        Stmt::Assign { target, value } => {
+            v.visit_expr(target);
+            v.visit_expr(value);
+        }
+        Stmt::Macro(mac) => v.visit_macro(mac),
+        Stmt::Let { ty, init, .. } => {
+            if let Some(ty) = ty {
+                v.visit_type(ty);
+            }
+            if let Some(init) = init {
+                v.visit_expr(init);
+            }
+        }
+        Stmt::While(stmt) => {
+            v.visit_expr(&stmt.condition);
+            v.visit_block(&stmt.body);
+        }
+        Stmt::Break(..) | Stmt::Continue(..) => {}
+        Stmt::Func(_, func) => v.visit_func(func),
+    }
+}
+
+pub fn walk_block<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, block: &'ast Block<'ast>) {
+    for item in &block.items {
+        v.visit_stmt(item);
+    }
+}
+
+pub fn walk_if<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, stmt: &'ast If<'ast>) {
+    v.visit_expr(&stmt.condition);
+    v.visit_block(&stmt.then);
+    match &stmt.otherwise {
+        Some(Else::Block(block)) => v.visit_block(block),
+        Some(Else::If(stmt)) => v.visit_if(stmt),
+        None => {}
+    }
+}
+
+pub fn walk_expr<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, expr: &'ast Expr<'ast>) {
+    match expr {
+        Expr::Num(_) | Expr::Str(_) | Expr::Bool(_) | Expr::Name(_) | Expr::SelfRef(_) => {}
+        Expr::Interp(parts, _) => {
+            for part in parts {
+                v.visit_str_part(part);
+            }
+        }
+        Expr::Call(callee, args, _) => {
+            v.visit_expr(callee);
+            for arg in args {
+                v.visit_expr(arg);
+            }
+        }
+        Expr::ArrayLit(items, _) => {
+            for item in items {
+                v.visit_expr(item);
+            }
+        }
+        Expr::If(stmt) => v.visit_if(stmt),
+        Expr::Lambda { ty, body } => {
+            v.visit_fn_type(ty);
+            v.visit_block(body);
+        }
+        Expr::Add(operands)
+        | Expr::Sub(operands)
+        | Expr::Mul(operands)
+        | Expr::Div(operands)
+        | Expr::Rem(operands)
+        | Expr::Lt(operands)
+        | Expr::Eq(operands)
+        | Expr::Ne(operands)
+        | Expr::Le(operands)
+        | Expr::Gt(operands)
+        | Expr::Ge(operands)
+        | Expr::BitAnd(operands)
+        | Expr::BitOr(operands)
+        | Expr::BitXor(operands)
+        | Expr::Shl(operands)
+        | Expr::Shr(operands)
+        | Expr::And(operands)
+        | Expr::Or(operands)
+        | Expr::Binary(_, operands)
+        | Expr::Index(operands, _) => {
+            v.visit_expr(&operands.0);
+            v.visit_expr(&operands.1);
+        }
+        Expr::Unary(_, operand, _) => v.visit_expr(operand),
+    }
+}
+
+pub fn walk_str_part<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, part: &'ast StrPart<'ast>) {
+    match part {
+        StrPart::Lit(_) => {}
+        StrPart::Expr(expr) => v.visit_expr(expr),
+    }
+}
+
+/// Like [`Visit`], but with mutable access so a pass can replace nodes as it goes,
+/// e.g. `*expr = folded`. Walk before rewriting to work bottom-up.
+pub trait VisitMut<'src> {
+    fn visit_module_mut(&mut self, module: &mut Module<'src>) {
+        walk_module_mut(self, module);
+    }
+
+    fn visit_decl_mut(&mut self, decl: &mut Decl<'src>) {
+        walk_decl_mut(self, decl);
+    }
+
+    fn visit_func_mut(&mut self, func: &mut Func<'src>) {
+        walk_func_mut(self, func);
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut Type<'src>) {
+        walk_type_mut(self, ty);
+    }
+
+    fn visit_fn_type_mut(&mut self, ty: &mut FnType<'src>) {
+        walk_fn_type_mut(self, ty);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt<'src>) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block<'src>) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_if_mut(&mut self, stmt: &mut If<'src>) {
+        walk_if_mut(self, stmt);
+    }
+
+    fn visit_macro_mut(&mut self, _mac: &mut Macro<'src>) {}
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'src>) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_str_part_mut(&mut self, part: &mut StrPart<'src>) {
+        walk_str_part_mut(self, part);
+    }
+}
+
+pub fn walk_module_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, module: &mut Module<'src>) {
+    for decl in &mut module.decls {
+        v.visit_decl_mut(decl);
+    }
+}
+
+pub fn walk_decl_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, decl: &mut Decl<'src>) {
+    match decl {
+        Decl::Func(_, func) => v.visit_func_mut(func),
+        Decl::Global { ty, init, .. } => {
+            if let Some(ty) = ty {
+                v.visit_type_mut(ty);
+            }
+            if let Some(init) = init {
+                v.visit_expr_mut(init);
+            }
+        }
+        Decl::TypeAlias { ty, .. } => v.visit_type_mut(ty),
+        Decl::Import(..) | Decl::Mod(..) => {}
+    }
+}
+
+pub fn walk_func_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, func: &mut Func<'src>) {
+    v.visit_fn_type_mut(&mut func.ty);
+    v.visit_block_mut(&mut func.body);
+}
+
+pub fn walk_type_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, ty: &mut Type<'src>) {
+    match ty {
+        Type::Name(_) => {}
+        Type::Ptr(inner, _) => v.visit_type_mut(inner),
+        Type::Array(elem, len, _) => {
+            v.visit_type_mut(elem);
+            if let Some(len) = len {
+                v.visit_expr_mut(len);
+            }
+        }
+        Type::Func(func) => v.visit_fn_type_mut(func),
+        Type::Generic(_, args, _) => {
+            for arg in args {
+                v.visit_type_mut(arg);
+            }
+        }
+    }
+}
+
+pub fn walk_fn_type_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, ty: &mut FnType<'src>) {
+    for arg in &mut ty.args {
+        v.visit_type_mut(arg);
+    }
+    if let Some(ret) = &mut ty.ret {
+        v.visit_type_mut(ret);
+    }
+}
+
+pub fn walk_stmt_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, stmt: &mut Stmt<'src>) {
+    match stmt {
+        Stmt::If(stmt) => v.visit_if_mut(stmt),
+        Stmt::Return(expr, _) | Stmt::Expr(expr) => v.visit_expr_mut(expr),
+        Stmt::Block(block) => v.visit_block_mut(block),
+        Stmt::Assign { target, value } => {
+            v.visit_expr_mut(target);
+            v.visit_expr_mut(value);
+        }
+        Stmt::Macro(mac) => v.visit_macro_mut(mac),
+        Stmt::Let { ty, init, .. } => {
+            if let Some(ty) = ty {
+                v.visit_type_mut(ty);
+            }
+            if let Some(init) = init {
+                v.visit_expr_mut(init);
+            }
+        }
+        Stmt::While(stmt) => {
+            v.visit_expr_mut(&mut stmt.condition);
+            v.visit_block_mut(&mut stmt.body);
+        }
+        Stmt::Break(..) | Stmt::Continue(..) => {}
+        Stmt::Func(_, func) => v.visit_func_mut(func),
+    }
+}
+
+pub fn walk_block_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, block: &mut Block<'src>) {
+    for item in &mut block.items {
+        v.visit_stmt_mut(item);
+    }
+}
+
+pub fn walk_if_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, stmt: &mut If<'src>) {
+    v.visit_expr_mut(&mut stmt.condition);
+    v.visit_block_mut(&mut stmt.then);
+    match &mut stmt.otherwise {
+        Some(Else::Block(block)) => v.visit_block_mut(block),
+        Some(Else::If(stmt)) => v.visit_if_mut(stmt),
+        None => {}
+    }
+}
+
+pub fn walk_expr_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, expr: &mut Expr<'src>) {
+    match expr {
+        Expr::Num(_) | Expr::Str(_) | Expr::Bool(_) | Expr::Name(_) | Expr::SelfRef(_) => {}
+        Expr::Interp(parts, _) => {
+            for part in parts {
+                v.visit_str_part_mut(part);
+            }
+        }
+        Expr::Call(callee, args, _) => {
+            v.visit_expr_mut(callee);
+            for arg in args {
+                v.visit_expr_mut(arg);
+            }
+        }
+        Expr::ArrayLit(items, _) => {
+            for item in items {
+                v.visit_expr_mut(item);
+            }
+        }
+        Expr::If(stmt) => v.visit_if_mut(stmt),
+        Expr::Lambda { ty, body } => {
+            v.visit_fn_type_mut(ty);
+            v.visit_block_mut(body);
+        }
+        Expr::Add(operands)
+        | Expr::Sub(operands)
+        | Expr::Mul(operands)
+        | Expr::Div(operands)
+        | Expr::Rem(operands)
+        | Expr::Lt(operands)
+        | Expr::Eq(operands)
+        | Expr::Ne(operands)
+        | Expr::Le(operands)
+        | Expr::Gt(operands)
+        | Expr::Ge(operands)
+        | Expr::BitAnd(operands)
+        | Expr::BitOr(operands)
+        | Expr::BitXor(operands)
+        | Expr::Shl(operands)
+        | Expr::Shr(operands)
+        | Expr::And(operands)
+        | Expr::Or(operands)
+        | Expr::Binary(_, operands)
+        | Expr::Index(operands, _) => {
+            v.visit_expr_mut(&mut operands.0);
+            v.visit_expr_mut(&mut operands.1);
+        }
+        Expr::Unary(_, operand, _) => v.visit_expr_mut(operand),
+    }
+}
+
+pub fn walk_str_part_mut<'src, V: VisitMut<'src> + ?Sized>(v: &mut V, part: &mut StrPart<'src>) {
+    match part {
+        StrPart::Lit(_) => {}
+        StrPart::Expr(expr) => v.visit_expr_mut(expr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse_expr, parse_program, tokenizer, NumLit, NumValue};
+    use crate::Token;
+    use std::borrow::Cow;
+
+    #[derive(Default)]
+    struct Counter<'ast> {
+        exprs: usize,
+        types: Vec<&'ast str>,
+        calls: usize,
+    }
+
+    impl<'ast> Visit<'ast> for Counter<'ast> {
+        fn visit_expr(&mut self, expr: &'ast Expr<'ast>) {
+            self.exprs += 1;
+            if matches!(expr, Expr::Call(..)) {
+                self.calls += 1;
+            }
+            walk_expr(self, expr);
+        }
+
+        fn visit_type(&mut self, ty: &'ast Type<'ast>) {
+            if let Type::Name(name) = ty {
+                self.types.push(name.content());
+            }
+            walk_type(self, ty);
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let src = "limit int[4] = 2\nfib (int) int {\n    :if 1 < 2 { :0 } else { :self(1) + self(\"${3}\") }\n}";
+        let tok = tokenizer(src);
+        let decls = parse_program(&tok).unwrap();
+
+        let mut counter = Counter::default();
+        for decl in &decls {
+            counter.visit_decl(decl);
+        }
+        assert_eq!(counter.types, ["int", "int", "int"]);
+        assert_eq!(counter.calls, 2);
+        // `4`, `2`, the if, `1 < 2` and its operands, `0`, the sum, two calls with
+        // their callees and arguments, and the `3` inside the interpolated argument.
+        assert_eq!(counter.exprs, 15);
+    }
+
+    /// Folds `+` and `*` over integer literals.
+    struct Folder;
+
+    impl<'src> VisitMut<'src> for Folder {
+        fn visit_expr_mut(&mut self, expr: &mut Expr<'src>) {
+            walk_expr_mut(self, expr);
+            let add = matches!(expr, Expr::Add(_));
+            let (Expr::Add(operands) | Expr::Mul(operands)) = &*expr else {
+                return;
+            };
+            let (Expr::Num(lhs), Expr::Num(rhs)) = &**operands else {
+                return;
+            };
+            let (NumValue::Int(a), NumValue::Int(b)) = (lhs.value, rhs.value) else {
+                return;
+            };
+            let value = if add { a + b } else { a * b };
+            *expr = Expr::Num(NumLit {
+                token: Token {
+                    span: lhs.token.span.merge(rhs.token.span),
+                    content: Cow::Owned(value.to_string()),
+                    ..lhs.token.clone()
+                },
+                value: NumValue::Int(value),
+                radix: 10,
+                suffix: None,
+            });
+        }
+    }
+
+    #[test]
+    fn test_visit_mut() {
+        let tok = tokenizer("1 + 2 * 3 + self(4 * 5)");
+        let mut expr = parse_expr(&tok).unwrap_or_else(|_| panic!("expected an expression"));
+        Folder.visit_expr_mut(&mut expr);
+
+        let Expr::Add(operands) = &expr else {
+            panic!("expected the call to stay unfolded");
+        };
+        assert!(matches!(&operands.0, Expr::Num(n) if n.value == NumValue::Int(7)));
+        assert_eq!(operands.0.span().end.index, 9);
+        let Expr::Call(_, args, _) = &operands.1 else {
+            panic!("expected a call");
+        };
+        assert!(matches!(&args[0], Expr::Num(n) if n.value == NumValue::Int(20)));
+    }
+}